@@ -0,0 +1,814 @@
+//! A module for using the library from async code, enabled by the `async` feature.
+//!
+//! The functions of the library block on SQLite and on the filesystem, an [`AsyncConnection`]
+//! runs them on a dedicated thread that owns the connection so that the async runtime isn't
+//! blocked. The calls are run one after the other, in the order they were made, and return the
+//! same values and errors as the functions of [`crate`] and of its modules. The functions that
+//! borrow their arguments take owned values instead, paths as [`PathBuf`]s, and the readers and
+//! writers must be `Send + 'static`, the writers are given back once written. Any other function
+//! of the library is reached with [`AsyncConnection::call`].
+//!
+//! The futures don't need a particular runtime, they work with tokio as with any other executor.
+//!
+//! ## Cancellation
+//! A call is sent to the thread when its future is first polled. When the future is dropped
+//! before the thread starts the call, the call isn't run. When the thread has already started
+//! it, the call runs to the end and its result is dropped, a cancellation never stops a call
+//! halfway.
+//!
+//! A call that fails leaves the library like the blocking function does: the functions changing
+//! the library write their changes in a single transaction, so they make all of them or none,
+//! except [`health::find_missing_books`], which marks each missing book on its own, and
+//! [`history::set_retention`], which saves the retention before removing the old sessions.
+//!
+//! A call that panics on the thread panics again in the task awaiting it, the connection can
+//! still be used by the other calls.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::async_api::AsyncConnection;
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let connection = AsyncConnection::setup().await;
+//!     match connection.update_favourite("book_name".to_string(), true).await {
+//!         Ok(book) => println!("{} is a favourite", book.name),
+//!         Err(err) => println!("{}", err),
+//!     }
+//!     let trash = connection
+//!         .call(|conn| book_lib::trash::list_trash(conn))
+//!         .await;
+//! }
+//! ```
+//!
+//! A call dropped before it started isn't run:
+//! ```rust
+//! use std::future::Future;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::{mpsc, Arc};
+//! use std::task::{Context, Waker};
+//!
+//! use book_lib::async_api::AsyncConnection;
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let connection = AsyncConnection::new(rusqlite::Connection::open_in_memory().unwrap());
+//!     let runs = Arc::new(AtomicUsize::new(0));
+//!     let mut cx = Context::from_waker(Waker::noop());
+//!
+//!     // polling a call once sends it to the thread, this one keeps it busy until `release` is
+//!     // sent to
+//!     let (release, wait) = mpsc::channel::<()>();
+//!     let mut busy = Box::pin(connection.call(move |_| wait.recv()));
+//!     assert!(busy.as_mut().poll(&mut cx).is_pending());
+//!     let counter = Arc::clone(&runs);
+//!     let mut cancelled =
+//!         Box::pin(connection.call(move |_| counter.fetch_add(1, Ordering::SeqCst)));
+//!     assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+//!     drop(cancelled);
+//!     release.send(()).unwrap();
+//!     busy.await.unwrap();
+//!
+//!     connection.call(|_| ()).await;
+//!     assert_eq!(runs.load(Ordering::SeqCst), 0);
+//!
+//!     // the errors are the ones of the blocking functions
+//!     let res = connection.get_book("missing".to_string()).await;
+//!     assert!(res.is_err());
+//! }
+//! ```
+//!
+//! ```rust
+//! fn assert_send<T: Send>() {}
+//! fn assert_sync<T: Sync>() {}
+//!
+//! assert_send::<book_lib::async_api::AsyncConnection>();
+//! assert_sync::<book_lib::async_api::AsyncConnection>();
+//! ```
+
+use std::io::{Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use rusqlite::Connection;
+use tokio::sync::oneshot;
+
+use crate::audit::{self, AuditEntry, AuditFilter};
+use crate::batch::{self, Batch, BatchReport};
+use crate::book::Book;
+use crate::db;
+use crate::errors::{
+    AuditError, BatchError, BatchItemError, CreateBookError, ExportError, GetBookError,
+    GetBooksError, HashError, HealthError, HistoryError, ImportError, JournalError, KindError,
+    OpenBookError, ProgressError, RemoveBookError, RenameBookError, RootError, ScanError,
+    StatsError, StorageError, TrashError, UpdateFavouriteError, UpdatePathError,
+    UpdateSectionError, WatchError,
+};
+use crate::export::{self, LibraryImportOptions, LibraryImportReport};
+use crate::hash::{self, DuplicateGroup, DuplicatePolicy};
+use crate::health::{self, Candidate, RelocateMode, RelocationReport};
+use crate::history::{self, BookActivity, DailyReading, OpenEvent};
+use crate::import::{self, ImportOptions, ImportReport};
+use crate::journal::{self, JournalEntry};
+use crate::kind::{self, DocumentKind};
+use crate::open::{self, CommandOpener, OpenerRegistry};
+use crate::progress::{self, Progress};
+use crate::roots;
+use crate::scan::{self, ScanOptions, ScanReport};
+use crate::stats::{self, Goal, GoalProgress, PeriodTotal, SectionTime, Streaks};
+use crate::storage::{self, ConsolidateReport, ManagedStorage};
+use crate::trash::{self, RestoreConflict, TrashedBook};
+use crate::watch::{self, ReconcileReport, WatchedFolder};
+
+/// A call sent to the thread of the connection
+type Job = Box<dyn FnOnce(&Connection) + Send>;
+
+/// A connection to the library used from async code, see the module documentation.
+///
+/// The clones send their calls to the same thread, which stops when the last clone is dropped.
+#[derive(Clone, Debug)]
+pub struct AsyncConnection {
+    jobs: mpsc::Sender<Job>,
+}
+
+/// Starts the thread owning the connection opened by `open`, it stops right away if None is
+/// returned
+fn spawn(open: impl FnOnce() -> Option<Connection> + Send + 'static) -> AsyncConnection {
+    let (jobs, received) = mpsc::channel::<Job>();
+    thread::Builder::new()
+        .name("book_lib-db".to_string())
+        .spawn(move || {
+            let Some(conn) = open() else { return };
+            for job in received {
+                job(&conn);
+            }
+        })
+        .expect("Couldn't start the database thread!");
+    AsyncConnection { jobs }
+}
+
+impl AsyncConnection {
+    /// Runs the calls with a connection, it should be configured with [`db::configure`]
+    pub fn new(conn: Connection) -> AsyncConnection {
+        spawn(move || Some(conn))
+    }
+
+    /// Opens the library database on the thread of the connection, see [`db::setup`], and panics
+    /// like it if the database can't be opened
+    pub async fn setup() -> AsyncConnection {
+        let (opened, wait) = oneshot::channel();
+        let connection = spawn(move || match panic::catch_unwind(db::setup) {
+            Ok(conn) => {
+                let _ = opened.send(Ok(()));
+                Some(conn)
+            }
+            Err(err) => {
+                let _ = opened.send(Err(err));
+                None
+            }
+        });
+        match wait.await {
+            Ok(Ok(())) => connection,
+            Ok(Err(err)) => panic::resume_unwind(err),
+            Err(_) => panic!("The database thread stopped!"),
+        }
+    }
+
+    /// Runs `f` with the connection on its thread and returns its result, e.g.
+    /// `connection.call(|conn| book_lib::stats::time_per_section(conn))`
+    pub async fn call<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&Connection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result, wait) = oneshot::channel();
+        let job: Job = Box::new(move |conn| {
+            // the future was dropped, the call is cancelled
+            if result.is_closed() {
+                return;
+            }
+            let _ = result.send(panic::catch_unwind(AssertUnwindSafe(|| f(conn))));
+        });
+        if self.jobs.send(job).is_err() {
+            panic!("The database thread stopped!");
+        }
+        match wait.await {
+            Ok(Ok(value)) => value,
+            Ok(Err(err)) => panic::resume_unwind(err),
+            Err(_) => panic!("The database thread stopped!"),
+        }
+    }
+
+    /// See [`crate::get_books`]
+    pub async fn get_books(&self) -> Result<Vec<Book>, GetBooksError> {
+        self.call(crate::get_books).await
+    }
+
+    /// See [`crate::get_book`]
+    pub async fn get_book(&self, name: String) -> Result<Book, GetBookError> {
+        self.call(move |conn| crate::get_book(conn, &name)).await
+    }
+
+    /// See [`crate::remove_book`]
+    pub async fn remove_book(&self, name: String) -> Result<Book, RemoveBookError> {
+        self.call(move |conn| crate::remove_book(conn, &name)).await
+    }
+
+    /// See [`crate::create_book`]
+    pub async fn create_book(&self, bk: Book) -> Result<bool, CreateBookError> {
+        self.call(move |conn| crate::create_book(conn, &bk)).await
+    }
+
+    /// See [`crate::create_book_with_policy`]
+    pub async fn create_book_with_policy(
+        &self,
+        bk: Book,
+        policy: DuplicatePolicy,
+    ) -> Result<Vec<String>, CreateBookError> {
+        self.call(move |conn| crate::create_book_with_policy(conn, &bk, policy))
+            .await
+    }
+
+    /// See [`crate::update_favourite`]
+    pub async fn update_favourite(
+        &self,
+        name: String,
+        favourite: bool,
+    ) -> Result<Book, UpdateFavouriteError> {
+        self.call(move |conn| crate::update_favourite(conn, &name, favourite))
+            .await
+    }
+
+    /// See [`crate::update_path`]
+    pub async fn update_path(&self, name: String, path: String) -> Result<Book, UpdatePathError> {
+        self.call(move |conn| crate::update_path(conn, &name, &path))
+            .await
+    }
+
+    /// See [`crate::rename_book`]
+    pub async fn rename_book(
+        &self,
+        name: String,
+        new_name: String,
+    ) -> Result<Book, RenameBookError> {
+        self.call(move |conn| crate::rename_book(conn, &name, &new_name))
+            .await
+    }
+
+    /// See [`crate::update_section`]
+    pub async fn update_section(
+        &self,
+        name: String,
+        section: Option<String>,
+    ) -> Result<Book, UpdateSectionError> {
+        self.call(move |conn| crate::update_section(conn, &name, section))
+            .await
+    }
+
+    /// See [`batch::run_batch`], the whole batch runs on the thread of the connection
+    pub async fn run_batch<F>(&self, changes: F) -> Result<BatchReport, BatchError>
+    where
+        F: FnOnce(&mut Batch) -> Result<(), BatchItemError> + Send + 'static,
+    {
+        self.call(move |conn| batch::run_batch(conn, changes)).await
+    }
+
+    /// See [`trash::list_trash`]
+    pub async fn list_trash(&self) -> Result<Vec<TrashedBook>, TrashError> {
+        self.call(trash::list_trash).await
+    }
+
+    /// See [`trash::get_trashed_book`]
+    pub async fn get_trashed_book(&self, id: i64) -> Result<TrashedBook, TrashError> {
+        self.call(move |conn| trash::get_trashed_book(conn, id))
+            .await
+    }
+
+    /// See [`trash::restore_book`]
+    pub async fn restore_book(
+        &self,
+        id: i64,
+        conflict: RestoreConflict,
+    ) -> Result<Book, TrashError> {
+        self.call(move |conn| trash::restore_book(conn, id, conflict))
+            .await
+    }
+
+    /// See [`trash::empty_trash`]
+    pub async fn empty_trash(&self, older_than: Option<i64>) -> Result<usize, TrashError> {
+        self.call(move |conn| trash::empty_trash(conn, older_than))
+            .await
+    }
+
+    /// See [`journal::get_journal`]
+    pub async fn get_journal(&self, limit: usize) -> Result<Vec<JournalEntry>, JournalError> {
+        self.call(move |conn| journal::get_journal(conn, limit))
+            .await
+    }
+
+    /// See [`journal::next_undo`]
+    pub async fn next_undo(&self) -> Result<Option<JournalEntry>, JournalError> {
+        self.call(journal::next_undo).await
+    }
+
+    /// See [`journal::next_redo`]
+    pub async fn next_redo(&self) -> Result<Option<JournalEntry>, JournalError> {
+        self.call(journal::next_redo).await
+    }
+
+    /// See [`journal::undo`]
+    pub async fn undo(&self) -> Result<JournalEntry, JournalError> {
+        self.call(journal::undo).await
+    }
+
+    /// See [`journal::redo`]
+    pub async fn redo(&self) -> Result<JournalEntry, JournalError> {
+        self.call(journal::redo).await
+    }
+
+    /// See [`journal::clear_journal`]
+    pub async fn clear_journal(&self) -> Result<(), JournalError> {
+        self.call(journal::clear_journal).await
+    }
+
+    /// See [`audit::set_actor`]
+    pub async fn set_actor(&self, actor: Option<String>) -> Result<(), AuditError> {
+        self.call(move |conn| audit::set_actor(conn, actor.as_deref()))
+            .await
+    }
+
+    /// See [`audit::get_actor`]
+    pub async fn get_actor(&self) -> Option<String> {
+        self.call(audit::get_actor).await
+    }
+
+    /// See [`audit::query_audit`]
+    pub async fn query_audit(&self, filter: AuditFilter) -> Result<Vec<AuditEntry>, AuditError> {
+        self.call(move |conn| audit::query_audit(conn, &filter))
+            .await
+    }
+
+    /// See [`audit::get_audit_entry`]
+    pub async fn get_audit_entry(&self, id: i64) -> Result<AuditEntry, AuditError> {
+        self.call(move |conn| audit::get_audit_entry(conn, id))
+            .await
+    }
+
+    /// See [`audit::export_audit_jsonl`], returns the number of entries and the writer
+    pub async fn export_audit_jsonl<W>(
+        &self,
+        filter: AuditFilter,
+        mut writer: W,
+    ) -> Result<(usize, W), AuditError>
+    where
+        W: Write + Send + 'static,
+    {
+        self.call(move |conn| {
+            audit::export_audit_jsonl(conn, &filter, &mut writer).map(|count| (count, writer))
+        })
+        .await
+    }
+
+    /// See [`import::import_bibtex`]
+    pub async fn import_bibtex(
+        &self,
+        path: PathBuf,
+        options: ImportOptions,
+    ) -> Result<ImportReport, ImportError> {
+        self.call(move |conn| import::import_bibtex(conn, path, &options))
+            .await
+    }
+
+    /// See [`import::import_ris`]
+    pub async fn import_ris(
+        &self,
+        path: PathBuf,
+        options: ImportOptions,
+    ) -> Result<ImportReport, ImportError> {
+        self.call(move |conn| import::import_ris(conn, path, &options))
+            .await
+    }
+
+    /// See [`export::export_json`], returns the writer
+    pub async fn export_json<W>(&self, mut writer: W) -> Result<W, ExportError>
+    where
+        W: Write + Send + 'static,
+    {
+        self.call(move |conn| export::export_json(conn, &mut writer).map(|_| writer))
+            .await
+    }
+
+    /// See [`export::export_csv`], returns the writer
+    pub async fn export_csv<W>(&self, mut writer: W) -> Result<W, ExportError>
+    where
+        W: Write + Send + 'static,
+    {
+        self.call(move |conn| export::export_csv(conn, &mut writer).map(|_| writer))
+            .await
+    }
+
+    /// See [`export::import_json`]
+    pub async fn import_json<R>(
+        &self,
+        reader: R,
+        options: LibraryImportOptions,
+    ) -> Result<LibraryImportReport, ImportError>
+    where
+        R: Read + Send + 'static,
+    {
+        self.call(move |conn| export::import_json(conn, reader, &options))
+            .await
+    }
+
+    /// See [`export::import_csv`]
+    pub async fn import_csv<R>(
+        &self,
+        reader: R,
+        options: LibraryImportOptions,
+    ) -> Result<LibraryImportReport, ImportError>
+    where
+        R: Read + Send + 'static,
+    {
+        self.call(move |conn| export::import_csv(conn, reader, &options))
+            .await
+    }
+
+    /// See [`scan::scan_directory`]
+    pub async fn scan_directory(
+        &self,
+        root: PathBuf,
+        options: ScanOptions,
+    ) -> Result<ScanReport, ScanError> {
+        self.call(move |conn| scan::scan_directory(conn, root, &options))
+            .await
+    }
+
+    /// See [`watch::add_watched_folder`]
+    pub async fn add_watched_folder(&self, folder: WatchedFolder) -> Result<(), WatchError> {
+        self.call(move |conn| watch::add_watched_folder(conn, &folder))
+            .await
+    }
+
+    /// See [`watch::remove_watched_folder`]
+    pub async fn remove_watched_folder(&self, path: String) -> Result<(), WatchError> {
+        self.call(move |conn| watch::remove_watched_folder(conn, &path))
+            .await
+    }
+
+    /// See [`watch::get_watched_folders`]
+    pub async fn get_watched_folders(&self) -> Result<Vec<WatchedFolder>, WatchError> {
+        self.call(watch::get_watched_folders).await
+    }
+
+    /// See [`watch::reconcile`]
+    pub async fn reconcile(&self) -> Result<ReconcileReport, WatchError> {
+        self.call(watch::reconcile).await
+    }
+
+    /// See [`health::find_missing_books`]
+    pub async fn find_missing_books(&self) -> Result<Vec<Book>, HealthError> {
+        self.call(health::find_missing_books).await
+    }
+
+    /// See [`health::find_candidates`]
+    pub async fn find_candidates(
+        &self,
+        name: String,
+        roots: Vec<PathBuf>,
+    ) -> Result<Vec<Candidate>, HealthError> {
+        self.call(move |conn| health::find_candidates(conn, &name, &roots))
+            .await
+    }
+
+    /// See [`health::relocate_missing_books`]
+    pub async fn relocate_missing_books(
+        &self,
+        roots: Vec<PathBuf>,
+        mode: RelocateMode,
+    ) -> Result<RelocationReport, HealthError> {
+        self.call(move |conn| health::relocate_missing_books(conn, &roots, mode))
+            .await
+    }
+
+    /// See [`hash::content_hash`]
+    pub async fn content_hash(&self, name: String) -> Result<String, HashError> {
+        self.call(move |conn| hash::content_hash(conn, &name)).await
+    }
+
+    /// See [`hash::find_duplicates`]
+    pub async fn find_duplicates(&self) -> Result<Vec<DuplicateGroup>, HashError> {
+        self.call(hash::find_duplicates).await
+    }
+
+    /// See [`hash::find_same_content`]
+    pub async fn find_same_content(&self, path: PathBuf) -> Result<Vec<String>, HashError> {
+        self.call(move |conn| hash::find_same_content(conn, path))
+            .await
+    }
+
+    /// See [`storage::get_managed_storage`]
+    pub async fn get_managed_storage(&self) -> Result<Option<ManagedStorage>, StorageError> {
+        self.call(storage::get_managed_storage).await
+    }
+
+    /// See [`storage::set_managed_storage`]
+    pub async fn set_managed_storage(
+        &self,
+        managed: ManagedStorage,
+    ) -> Result<ManagedStorage, StorageError> {
+        self.call(move |conn| storage::set_managed_storage(conn, &managed))
+            .await
+    }
+
+    /// See [`storage::disable_managed_storage`]
+    pub async fn disable_managed_storage(&self) -> Result<(), StorageError> {
+        self.call(storage::disable_managed_storage).await
+    }
+
+    /// See [`storage::consolidate`]
+    pub async fn consolidate(&self, dry_run: bool) -> Result<ConsolidateReport, StorageError> {
+        self.call(move |conn| storage::consolidate(conn, dry_run))
+            .await
+    }
+
+    /// See [`roots::relativize_paths`]
+    pub async fn relativize_paths(&self) -> Result<Vec<String>, RootError> {
+        self.call(roots::relativize_paths).await
+    }
+
+    /// See [`kind::get_allowed_kinds`]
+    pub async fn get_allowed_kinds(&self) -> Result<Vec<DocumentKind>, KindError> {
+        self.call(kind::get_allowed_kinds).await
+    }
+
+    /// See [`kind::set_allowed_kinds`]
+    pub async fn set_allowed_kinds(&self, kinds: Vec<DocumentKind>) -> Result<(), KindError> {
+        self.call(move |conn| kind::set_allowed_kinds(conn, &kinds))
+            .await
+    }
+
+    /// See [`progress::get_progress`]
+    pub async fn get_progress(&self, name: String) -> Result<Progress, ProgressError> {
+        self.call(move |conn| progress::get_progress(conn, &name))
+            .await
+    }
+
+    /// See [`progress::set_page_count`]
+    pub async fn set_page_count(
+        &self,
+        name: String,
+        page_count: Option<u32>,
+    ) -> Result<Progress, ProgressError> {
+        self.call(move |conn| progress::set_page_count(conn, &name, page_count))
+            .await
+    }
+
+    /// See [`progress::update_progress`]
+    pub async fn update_progress(
+        &self,
+        name: String,
+        page: u32,
+        at: Option<i64>,
+    ) -> Result<Progress, ProgressError> {
+        self.call(move |conn| progress::update_progress(conn, &name, page, at))
+            .await
+    }
+
+    /// See [`progress::mark_finished`]
+    pub async fn mark_finished(
+        &self,
+        name: String,
+        finished: bool,
+        at: Option<i64>,
+    ) -> Result<Progress, ProgressError> {
+        self.call(move |conn| progress::mark_finished(conn, &name, finished, at))
+            .await
+    }
+
+    /// See [`progress::reset_progress`]
+    pub async fn reset_progress(&self, name: String) -> Result<(), ProgressError> {
+        self.call(move |conn| progress::reset_progress(conn, &name))
+            .await
+    }
+
+    /// See [`history::record_open`]
+    pub async fn record_open(
+        &self,
+        name: String,
+        opened_at: Option<i64>,
+    ) -> Result<i64, HistoryError> {
+        self.call(move |conn| history::record_open(conn, &name, opened_at))
+            .await
+    }
+
+    /// See [`history::record_close`]
+    pub async fn record_close(
+        &self,
+        id: i64,
+        closed_at: Option<i64>,
+    ) -> Result<OpenEvent, HistoryError> {
+        self.call(move |conn| history::record_close(conn, id, closed_at))
+            .await
+    }
+
+    /// See [`history::record_session`]
+    pub async fn record_session(
+        &self,
+        name: String,
+        opened_at: i64,
+        duration: i64,
+    ) -> Result<i64, HistoryError> {
+        self.call(move |conn| history::record_session(conn, &name, opened_at, duration))
+            .await
+    }
+
+    /// See [`history::get_event`]
+    pub async fn get_event(&self, id: i64) -> Result<OpenEvent, HistoryError> {
+        self.call(move |conn| history::get_event(conn, id)).await
+    }
+
+    /// See [`history::get_open_session`]
+    pub async fn get_open_session(&self, name: String) -> Result<Option<OpenEvent>, HistoryError> {
+        self.call(move |conn| history::get_open_session(conn, &name))
+            .await
+    }
+
+    /// See [`history::get_history`]
+    pub async fn get_history(&self, name: String) -> Result<Vec<OpenEvent>, HistoryError> {
+        self.call(move |conn| history::get_history(conn, &name))
+            .await
+    }
+
+    /// See [`history::recently_opened`]
+    pub async fn recently_opened(&self, limit: usize) -> Result<Vec<BookActivity>, HistoryError> {
+        self.call(move |conn| history::recently_opened(conn, limit))
+            .await
+    }
+
+    /// See [`history::most_read`]
+    pub async fn most_read(&self, limit: usize) -> Result<Vec<BookActivity>, HistoryError> {
+        self.call(move |conn| history::most_read(conn, limit)).await
+    }
+
+    /// See [`history::reading_time_per_day`]
+    pub async fn reading_time_per_day(
+        &self,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<DailyReading>, HistoryError> {
+        self.call(move |conn| history::reading_time_per_day(conn, from, to))
+            .await
+    }
+
+    /// See [`history::get_retention`]
+    pub async fn get_retention(&self) -> Result<Option<u32>, HistoryError> {
+        self.call(history::get_retention).await
+    }
+
+    /// See [`history::set_retention`]
+    pub async fn set_retention(&self, days: Option<u32>) -> Result<(), HistoryError> {
+        self.call(move |conn| history::set_retention(conn, days))
+            .await
+    }
+
+    /// See [`history::prune_history`]
+    pub async fn prune_history(&self) -> Result<usize, HistoryError> {
+        self.call(history::prune_history).await
+    }
+
+    /// See [`history::clear_book_history`]
+    pub async fn clear_book_history(&self, name: String) -> Result<usize, HistoryError> {
+        self.call(move |conn| history::clear_book_history(conn, &name))
+            .await
+    }
+
+    /// See [`history::clear_history`]
+    pub async fn clear_history(&self) -> Result<usize, HistoryError> {
+        self.call(history::clear_history).await
+    }
+
+    /// See [`stats::books_finished_per_month`]
+    pub async fn books_finished_per_month(&self) -> Result<Vec<PeriodTotal>, StatsError> {
+        self.call(stats::books_finished_per_month).await
+    }
+
+    /// See [`stats::pages_read_per_week`]
+    pub async fn pages_read_per_week(&self) -> Result<Vec<PeriodTotal>, StatsError> {
+        self.call(stats::pages_read_per_week).await
+    }
+
+    /// See [`stats::reading_streaks`]
+    pub async fn reading_streaks(&self, at: Option<i64>) -> Result<Streaks, StatsError> {
+        self.call(move |conn| stats::reading_streaks(conn, at))
+            .await
+    }
+
+    /// See [`stats::time_per_section`]
+    pub async fn time_per_section(&self) -> Result<Vec<SectionTime>, StatsError> {
+        self.call(stats::time_per_section).await
+    }
+
+    /// See [`stats::add_goal`]
+    pub async fn add_goal(&self, goal: Goal) -> Result<(), StatsError> {
+        self.call(move |conn| stats::add_goal(conn, &goal)).await
+    }
+
+    /// See [`stats::remove_goal`]
+    pub async fn remove_goal(&self, name: String) -> Result<(), StatsError> {
+        self.call(move |conn| stats::remove_goal(conn, &name)).await
+    }
+
+    /// See [`stats::get_goals`]
+    pub async fn get_goals(&self) -> Result<Vec<Goal>, StatsError> {
+        self.call(stats::get_goals).await
+    }
+
+    /// See [`stats::goal_progress`]
+    pub async fn goal_progress(&self, at: Option<i64>) -> Result<Vec<GoalProgress>, StatsError> {
+        self.call(move |conn| stats::goal_progress(conn, at)).await
+    }
+
+    /// See [`open::load_registry`]
+    pub async fn load_registry(&self) -> Result<OpenerRegistry, OpenBookError> {
+        self.call(open::load_registry).await
+    }
+
+    /// See [`open::get_command_openers`]
+    pub async fn get_command_openers(&self) -> Result<Vec<CommandOpener>, OpenBookError> {
+        self.call(open::get_command_openers).await
+    }
+
+    /// See [`open::add_command_opener`]
+    pub async fn add_command_opener(
+        &self,
+        name: String,
+        template: String,
+    ) -> Result<CommandOpener, OpenBookError> {
+        self.call(move |conn| open::add_command_opener(conn, &name, &template))
+            .await
+    }
+
+    /// See [`open::remove_command_opener`]
+    pub async fn remove_command_opener(&self, name: String) -> Result<(), OpenBookError> {
+        self.call(move |conn| open::remove_command_opener(conn, &name))
+            .await
+    }
+
+    /// See [`open::get_book_opener`]
+    pub async fn get_book_opener(&self, name: String) -> Result<Option<String>, OpenBookError> {
+        self.call(move |conn| open::get_book_opener(conn, &name))
+            .await
+    }
+
+    /// See [`open::set_book_opener`]
+    pub async fn set_book_opener(
+        &self,
+        name: String,
+        opener: Option<String>,
+    ) -> Result<(), OpenBookError> {
+        self.call(move |conn| open::set_book_opener(conn, &name, opener.as_deref()))
+            .await
+    }
+
+    /// See [`open::get_kind_opener`]
+    pub async fn get_kind_opener(
+        &self,
+        kind: DocumentKind,
+    ) -> Result<Option<String>, OpenBookError> {
+        self.call(move |conn| open::get_kind_opener(conn, kind))
+            .await
+    }
+
+    /// See [`open::set_kind_opener`]
+    pub async fn set_kind_opener(
+        &self,
+        kind: DocumentKind,
+        opener: Option<String>,
+    ) -> Result<(), OpenBookError> {
+        self.call(move |conn| open::set_kind_opener(conn, kind, opener.as_deref()))
+            .await
+    }
+
+    /// See [`open::resolve_opener`]
+    pub async fn resolve_opener(
+        &self,
+        registry: Arc<OpenerRegistry>,
+        name: String,
+    ) -> Result<String, OpenBookError> {
+        self.call(move |conn| open::resolve_opener(conn, &registry, &name))
+            .await
+    }
+
+    /// See [`open::open_book`], the opener runs on the thread of the connection too
+    pub async fn open_book(
+        &self,
+        registry: Arc<OpenerRegistry>,
+        name: String,
+        page: Option<u32>,
+    ) -> Result<String, OpenBookError> {
+        self.call(move |conn| open::open_book(conn, &registry, &name, page))
+            .await
+    }
+}
@@ -0,0 +1,323 @@
+//! A module for the audit log of the library.
+//!
+//! Every change of the books made by the database layer is appended to a log in the same
+//! transaction as the change itself, with the time, the book, the value before and after the
+//! change and the actor set on the connection with [`set_actor`]. The cached file sizes and
+//! content hashes aren't logged, nor the time a book was last opened, which is kept from the
+//! reading history (see [`crate::history`]). Entries can't be changed or removed, the database
+//! refuses it.
+//!
+//! The log is read with [`query_audit`] and written as JSON Lines, one entry per line, with
+//! [`export_audit_jsonl`]:
+//! ```json
+//! {"actor":"alice","at":1700000000,"book":"book_name","id":1,"new_value":"math","old_value":null,"op":"update_section"}
+//! ```
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::audit::{self, AuditFilter};
+//! # let connection = book_lib::db::setup();
+//!
+//! let _ = audit::set_actor(&connection, Some("alice"));
+//! let _ = book_lib::update_favourite(&connection, &"book_name".to_string(), true);
+//! let filter = AuditFilter {
+//!     book: Some("book_name".to_string()),
+//!     ..Default::default()
+//! };
+//! let _ = audit::export_audit_jsonl(&connection, &filter, std::io::stdout());
+//! ```
+
+use std::io::Write;
+
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde_json::json;
+
+use crate::errors::AuditError;
+
+/// A change of a book recorded in the audit log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuditOp {
+    CreateBook,
+    /// the book was moved to the trash
+    RemoveBook,
+    /// the book was brought back from the trash
+    RestoreBook,
+    /// the book was deleted for good from the trash
+    PurgeBook,
+    RenameBook,
+    UpdateFavourite,
+    UpdateSection,
+    UpdatePath,
+    UpdateOpener,
+    UpdateMissing,
+    /// the number of pages of the book, see [`crate::progress`]
+    UpdatePageCount,
+    /// the page the reader is at
+    UpdateProgress,
+    /// the time the book was finished at
+    UpdateFinished,
+}
+
+impl AuditOp {
+    /// Name of the change as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOp::CreateBook => "create_book",
+            AuditOp::RemoveBook => "remove_book",
+            AuditOp::RestoreBook => "restore_book",
+            AuditOp::PurgeBook => "purge_book",
+            AuditOp::RenameBook => "rename_book",
+            AuditOp::UpdateFavourite => "update_favourite",
+            AuditOp::UpdateSection => "update_section",
+            AuditOp::UpdatePath => "update_path",
+            AuditOp::UpdateOpener => "update_opener",
+            AuditOp::UpdateMissing => "update_missing",
+            AuditOp::UpdatePageCount => "update_page_count",
+            AuditOp::UpdateProgress => "update_progress",
+            AuditOp::UpdateFinished => "update_finished",
+        }
+    }
+
+    /// Returns the change with the given name, None if it's unknown
+    ///
+    /// ```rust
+    /// use book_lib::audit::AuditOp;
+    ///
+    /// assert_eq!(AuditOp::from_name("purge_book"), Some(AuditOp::PurgeBook));
+    /// assert_eq!(AuditOp::from_name("lend_book"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<AuditOp> {
+        match name {
+            "create_book" => Some(AuditOp::CreateBook),
+            "remove_book" => Some(AuditOp::RemoveBook),
+            "restore_book" => Some(AuditOp::RestoreBook),
+            "purge_book" => Some(AuditOp::PurgeBook),
+            "rename_book" => Some(AuditOp::RenameBook),
+            "update_favourite" => Some(AuditOp::UpdateFavourite),
+            "update_section" => Some(AuditOp::UpdateSection),
+            "update_path" => Some(AuditOp::UpdatePath),
+            "update_opener" => Some(AuditOp::UpdateOpener),
+            "update_missing" => Some(AuditOp::UpdateMissing),
+            "update_page_count" => Some(AuditOp::UpdatePageCount),
+            "update_progress" => Some(AuditOp::UpdateProgress),
+            "update_finished" => Some(AuditOp::UpdateFinished),
+            _ => None,
+        }
+    }
+}
+
+/// An entry of the audit log
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuditEntry {
+    pub id: i64,
+    /// when the change was made, in seconds since the Unix epoch
+    pub at: i64,
+    /// who made the change, see [`set_actor`]
+    pub actor: Option<String>,
+    pub op: AuditOp,
+    /// name of the book after the change, before it for a removal
+    pub book: String,
+    /// value before the change as stored in the database, favourite and missing are "0" or "1",
+    /// the path for a removal
+    pub old_value: Option<String>,
+    /// value after the change, the path for a creation
+    pub new_value: Option<String>,
+}
+
+/// Filter of [`query_audit`], every field that is set must match
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuditFilter {
+    pub book: Option<String>,
+    pub op: Option<AuditOp>,
+    pub actor: Option<String>,
+    /// entries made at or after this time
+    pub from: Option<i64>,
+    /// entries made before this time
+    pub to: Option<i64>,
+    /// maximum number of entries, the most recent are kept
+    pub limit: Option<usize>,
+}
+
+/// Sets who makes the next changes on this connection, None to log them without an actor.
+///
+/// The actor is kept for the lifetime of the connection.
+pub fn set_actor(conn: &Connection, actor: Option<&str>) -> Result<(), AuditError> {
+    let set = || -> rusqlite::Result<()> {
+        conn.execute_batch(
+            "CREATE TEMP TABLE IF NOT EXISTS audit_actor(actor TEXT);
+            DELETE FROM temp.audit_actor;",
+        )?;
+        conn.execute(
+            "INSERT INTO temp.audit_actor (actor) VALUES (?)",
+            params![actor],
+        )?;
+        Ok(())
+    };
+    match set() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(AuditError::DatabaseError),
+    }
+}
+
+/// Returns the actor set on this connection
+pub fn get_actor(conn: &Connection) -> Option<String> {
+    conn.query_row("SELECT actor FROM temp.audit_actor", [], |row| row.get(0))
+        .ok()
+        .flatten()
+}
+
+/// Appends a change to the log, meant to be called in the transaction of the change.
+pub(crate) fn log(
+    conn: &Connection,
+    op: AuditOp,
+    book: &str,
+    old_value: Option<String>,
+    new_value: Option<String>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO audit_log (at, actor, op, book, old_value, new_value)
+            VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            crate::db::now(),
+            get_actor(conn),
+            op.as_str(),
+            book,
+            old_value,
+            new_value
+        ],
+    )?;
+    Ok(())
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<AuditEntry> {
+    let op: String = row.get(3)?;
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        at: row.get(1)?,
+        actor: row.get(2)?,
+        op: AuditOp::from_name(&op).ok_or(rusqlite::Error::InvalidColumnType(
+            3,
+            op,
+            rusqlite::types::Type::Text,
+        ))?,
+        book: row.get(4)?,
+        old_value: row.get(5)?,
+        new_value: row.get(6)?,
+    })
+}
+
+/// Returns the entries of the log matching the filter, the most recent first
+pub fn query_audit(conn: &Connection, filter: &AuditFilter) -> Result<Vec<AuditEntry>, AuditError> {
+    let mut conditions = Vec::new();
+    let mut values: Vec<SqlValue> = Vec::new();
+    if let Some(book) = &filter.book {
+        conditions.push("book = ?");
+        values.push(SqlValue::Text(book.clone()));
+    }
+    if let Some(op) = filter.op {
+        conditions.push("op = ?");
+        values.push(SqlValue::Text(op.as_str().to_string()));
+    }
+    if let Some(actor) = &filter.actor {
+        conditions.push("actor = ?");
+        values.push(SqlValue::Text(actor.clone()));
+    }
+    if let Some(from) = filter.from {
+        conditions.push("at >= ?");
+        values.push(SqlValue::Integer(from));
+    }
+    if let Some(to) = filter.to {
+        conditions.push("at < ?");
+        values.push(SqlValue::Integer(to));
+    }
+    let mut sql = "SELECT id, at, actor, op, book, old_value, new_value FROM audit_log".to_string();
+    if !conditions.is_empty() {
+        sql = format!("{} WHERE {}", sql, conditions.join(" AND "));
+    }
+    sql.push_str(" ORDER BY id DESC");
+    if let Some(limit) = filter.limit {
+        sql.push_str(" LIMIT ?");
+        values.push(SqlValue::Integer(limit as i64));
+    }
+    let query = || -> rusqlite::Result<Vec<AuditEntry>> {
+        let mut stmt = conn.prepare(&sql)?;
+        let entries = stmt
+            .query_map(params_from_iter(values.iter()), entry_from_row)?
+            .collect();
+        entries
+    };
+    match query() {
+        Ok(entries) => Ok(entries),
+        Err(_) => Err(AuditError::DatabaseError),
+    }
+}
+
+/// Returns an entry of the log by its id
+pub fn get_audit_entry(conn: &Connection, id: i64) -> Result<AuditEntry, AuditError> {
+    match conn
+        .query_row(
+            "SELECT id, at, actor, op, book, old_value, new_value FROM audit_log WHERE id = ?",
+            params![id],
+            entry_from_row,
+        )
+        .optional()
+    {
+        Ok(Some(entry)) => Ok(entry),
+        Ok(None) => Err(AuditError::EntryDoesNotExist),
+        Err(_) => Err(AuditError::DatabaseError),
+    }
+}
+
+/// Converts an entry of the log to its JSON representation
+///
+/// ```rust
+/// use book_lib::audit::{self, AuditEntry, AuditOp};
+///
+/// let entry = AuditEntry {
+///     id: 1,
+///     at: 1700000000,
+///     actor: None,
+///     op: AuditOp::RenameBook,
+///     book: "new".to_string(),
+///     old_value: Some("old".to_string()),
+///     new_value: Some("new".to_string()),
+/// };
+/// assert_eq!(
+///     audit::entry_to_json(&entry).to_string(),
+///     r#"{"actor":null,"at":1700000000,"book":"new","id":1,"new_value":"new","old_value":"old","op":"rename_book"}"#
+/// );
+/// ```
+pub fn entry_to_json(entry: &AuditEntry) -> serde_json::Value {
+    json!({
+        "id": entry.id,
+        "at": entry.at,
+        "actor": entry.actor,
+        "op": entry.op.as_str(),
+        "book": entry.book,
+        "old_value": entry.old_value,
+        "new_value": entry.new_value,
+    })
+}
+
+/// Writes the entries of the log matching the filter as JSON Lines, the oldest first. Returns
+/// the number of written entries.
+pub fn export_audit_jsonl<W: Write>(
+    conn: &Connection,
+    filter: &AuditFilter,
+    mut writer: W,
+) -> Result<usize, AuditError> {
+    let entries = query_audit(conn, filter)?;
+    for entry in entries.iter().rev() {
+        if writeln!(writer, "{}", entry_to_json(entry)).is_err() {
+            return Err(AuditError::CouldNotWrite);
+        }
+    }
+    match writer.flush() {
+        Ok(_) => Ok(entries.len()),
+        Err(_) => Err(AuditError::CouldNotWrite),
+    }
+}
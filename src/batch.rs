@@ -0,0 +1,201 @@
+//! A module for running several changes of the library at once.
+//!
+//! [`run_batch`] gives a [`Batch`] to a closure, every change made through it is written in a
+//! single transaction. Either every change is kept, or none is: when a change fails or the
+//! closure returns an error, the whole batch is rolled back, including the files moved into the
+//! managed library root (see [`crate::storage`]).
+//!
+//! The report of a batch has a line for every change with its outcome, the same errors as the
+//! functions of [`crate`] are returned for each change.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::batch;
+//! # let connection = book_lib::db::setup();
+//!
+//! let names = vec!["first".to_string(), "second".to_string()];
+//! let res = batch::run_batch(&connection, |batch| {
+//!     for name in &names {
+//!         batch.update_favourite(name, true)?;
+//!     }
+//!     Ok(())
+//! });
+//! match res {
+//!     Ok(report) => println!("{} books updated", report.items.len()),
+//!     Err(err) => println!("nothing was changed: {}", err),
+//! }
+//! ```
+
+use rusqlite::Connection;
+
+use crate::book::Book;
+use crate::db;
+use crate::errors::{BatchError, BatchItemError};
+use crate::journal::JournalOp;
+
+/// What happened to a single change of a batch
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BatchOutcome {
+    /// the change was made, it's kept only if the whole batch is
+    Done,
+    /// the change failed, the batch is rolled back
+    Failed(BatchItemError),
+}
+
+/// A report line for a single change of a batch
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchItem {
+    pub op: JournalOp,
+    /// name of the book the change was made to
+    pub book: String,
+    pub outcome: BatchOutcome,
+}
+
+/// Per-change report of a batch
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchReport {
+    pub items: Vec<BatchItem>,
+    /// true if the changes were kept, false if the batch was rolled back
+    pub committed: bool,
+}
+
+impl BatchReport {
+    /// Changes that failed
+    pub fn failed(&self) -> impl Iterator<Item = &BatchItem> {
+        self.items
+            .iter()
+            .filter(|item| matches!(item.outcome, BatchOutcome::Failed(_)))
+    }
+}
+
+/// Handle given to the closure of [`run_batch`], its changes are written in the transaction of
+/// the batch.
+pub struct Batch<'conn> {
+    conn: &'conn Connection,
+    items: Vec<BatchItem>,
+}
+
+impl<'conn> Batch<'conn> {
+    /// Connection of the batch, to read the library or to make other changes in the transaction
+    pub fn connection(&self) -> &'conn Connection {
+        self.conn
+    }
+
+    fn push<T, E>(
+        &mut self,
+        op: JournalOp,
+        book: &str,
+        res: Result<T, E>,
+        into: fn(E) -> BatchItemError,
+    ) -> Result<T, BatchItemError> {
+        let (outcome, res) = match res.map_err(into) {
+            Ok(value) => (BatchOutcome::Done, Ok(value)),
+            Err(err) => (BatchOutcome::Failed(err.clone()), Err(err)),
+        };
+        self.items.push(BatchItem {
+            op,
+            book: book.to_string(),
+            outcome,
+        });
+        res
+    }
+
+    /// Creates a book, see [`crate::create_book`]
+    pub fn create_book(&mut self, bk: &Book) -> Result<(), BatchItemError> {
+        let res = crate::create_book(self.conn, bk);
+        self.push(
+            JournalOp::CreateBook,
+            &bk.name,
+            res,
+            BatchItemError::CreateBook,
+        )
+        .map(|_| ())
+    }
+
+    /// Moves a book to the trash, see [`crate::remove_book`]
+    pub fn remove_book(&mut self, name: &String) -> Result<Book, BatchItemError> {
+        let res = crate::remove_book(self.conn, name);
+        self.push(JournalOp::RemoveBook, name, res, BatchItemError::RemoveBook)
+    }
+
+    /// Updates the favourite state of a book, see [`crate::update_favourite`]
+    pub fn update_favourite(
+        &mut self,
+        name: &String,
+        favourite: bool,
+    ) -> Result<Book, BatchItemError> {
+        let res = crate::update_favourite(self.conn, name, favourite);
+        self.push(
+            JournalOp::UpdateFavourite,
+            name,
+            res,
+            BatchItemError::UpdateFavourite,
+        )
+    }
+
+    /// Points a book to a new file, see [`crate::update_path`]
+    pub fn update_path(&mut self, name: &String, path: &str) -> Result<Book, BatchItemError> {
+        let res = crate::update_path(self.conn, name, path);
+        self.push(JournalOp::UpdatePath, name, res, BatchItemError::UpdatePath)
+    }
+
+    /// Renames a book, see [`crate::rename_book`]
+    pub fn rename_book(
+        &mut self,
+        name: &String,
+        new_name: &String,
+    ) -> Result<Book, BatchItemError> {
+        let res = crate::rename_book(self.conn, name, new_name);
+        self.push(JournalOp::RenameBook, name, res, BatchItemError::RenameBook)
+    }
+
+    /// Moves a book to another section, see [`crate::update_section`]
+    pub fn update_section(
+        &mut self,
+        name: &String,
+        section: Option<String>,
+    ) -> Result<Book, BatchItemError> {
+        let res = crate::update_section(self.conn, name, section);
+        self.push(
+            JournalOp::UpdateSection,
+            name,
+            res,
+            BatchItemError::UpdateSection,
+        )
+    }
+}
+
+/// Runs the changes made by `changes` in a single transaction, returns the report of the batch.
+///
+/// If a change fails or `changes` returns an error, nothing is written and
+/// [`BatchError::RolledBack`] is returned with the report.
+pub fn run_batch<F>(conn: &Connection, changes: F) -> Result<BatchReport, BatchError>
+where
+    F: FnOnce(&mut Batch) -> Result<(), BatchItemError>,
+{
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(BatchError::DatabaseError),
+    };
+    let mut batch = Batch {
+        conn,
+        items: Vec::new(),
+    };
+    let res = changes(&mut batch);
+    let mut report = BatchReport {
+        items: std::mem::take(&mut batch.items),
+        committed: false,
+    };
+    // dropping the savepoint rolls the batch back and puts the files back
+    if res.is_err() || report.failed().next().is_some() {
+        return Err(BatchError::RolledBack(report));
+    }
+    if savepoint.release().is_err() {
+        return Err(BatchError::DatabaseError);
+    }
+    report.committed = true;
+    Ok(report)
+}
@@ -1,13 +1,29 @@
 //! A module that contains a Book implementation and associated methods and functions.
+//!
+//! ## Serialization
+//! With the `serde` feature enabled, [`Book`], [`Metadata`] and [`SectionGroup`] implement
+//! `Serialize` and `Deserialize`. The field names are part of the public format and match the
+//! JSON written by [`crate::export::export_json`]:
+//! - `Book`: `name`, `path`, `section`, `favourite`, `metadata`, `missing`, `kind`, `added_at`,
+//!   `updated_at`, `last_opened_at`
+//! - `Metadata`: `title`, `authors`, `year`, `publisher`, `series`, `doi`, `isbn`, `tags`
+//! - `SectionGroup`: `section`, `books`
+//!
+//! Missing optional fields and lists are read as empty values.
 
 use std::cmp::Ordering;
+use std::path::PathBuf;
+
+use crate::help;
+use crate::kind::DocumentKind;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A struct representing a book
 ///
-/// The struct contains book name, path and optional section
+/// The struct contains book name, path, optional section and bibliographic metadata
 pub struct Book {
-    /// path to the book in the system
+    /// path to the book in the system, see [`Book::file_path`] for paths that aren't valid Unicode
     pub path: String,
     /// name given to the book
     pub name: String,
@@ -15,6 +31,49 @@ pub struct Book {
     pub section: Option<String>,
     /// book marked as favourite
     pub favourite: bool,
+    /// bibliographic information about the book, empty by default
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: Metadata,
+    /// the file of the book disappeared from its path, see [`crate::watch::reconcile`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub missing: bool,
+    /// kind of the document, detected when the book is created
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub kind: DocumentKind,
+    /// when the book was added to the library, in seconds since the Unix epoch
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub added_at: Option<i64>,
+    /// when the data of the book last changed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub updated_at: Option<i64>,
+    /// when the book was last opened, see [`crate::history`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub last_opened_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+/// Bibliographic information attached to a book
+///
+/// Every field is optional, the books created by [`Book::init`] have empty metadata.
+pub struct Metadata {
+    /// full title of the document
+    pub title: Option<String>,
+    /// authors in the order they are given by the source
+    pub authors: Vec<String>,
+    /// year of publication
+    pub year: Option<i32>,
+    /// publisher, journal or proceedings the document appeared in
+    pub publisher: Option<String>,
+    /// series the document belongs to
+    pub series: Option<String>,
+    /// digital object identifier
+    pub doi: Option<String>,
+    /// ISBN or ISSN
+    pub isbn: Option<String>,
+    /// free-form keywords
+    pub tags: Vec<String>,
 }
 
 impl Book {
@@ -25,23 +84,19 @@ impl Book {
             name,
             section,
             favourite,
+            metadata: Metadata::default(),
+            missing: false,
+            kind: DocumentKind::default(),
+            added_at: None,
+            updated_at: None,
+            last_opened_at: None,
         }
     }
-}
 
-/// Takes a filename from a long path
-///
-/// ## Example
-/// ```rust
-/// let path = "path/to/my/file_unique_name.pdf";
-/// let file_name = extract_file_name_from_path(path);
-/// assert_eq!(file_name, "file_unique_name.pdf");
-/// ```
-fn extract_file_name_from_path(path: &str) -> String {
-    if !path.contains('/') {
-        path.to_string()
-    } else {
-        path.split("/").last().unwrap().to_string()
+    /// Returns the path to the file of the book, converted back with [`help::path_from_string`]
+    /// when it isn't valid Unicode
+    pub fn file_path(&self) -> PathBuf {
+        help::path_from_string(&self.path)
     }
 }
 
@@ -55,7 +110,7 @@ fn print_book(bk: Book) {
         } else {
             "".to_string()
         },
-        extract_file_name_from_path(&bk.path)
+        help::file_name(&bk.path)
     );
     print!("{}", res);
 }
@@ -74,6 +129,105 @@ pub fn print_books(books: Vec<Book>, indent: u16) {
     }
 }
 
+/// Key to sort books by, see [`sort_books`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SortKey {
+    Name,
+    AddedAt,
+    UpdatedAt,
+    LastOpenedAt,
+}
+
+/// Sorts books by a key, the books without a value for the key (e.g. never opened) come last in
+/// both orders. Books with the same value are sorted by name.
+///
+/// ## Example
+/// ```rust
+/// use book_lib::book::{sort_books, Book, SortKey};
+///
+/// let mut old = Book::init("old".to_string(), "old.pdf".to_string(), None, false);
+/// old.added_at = Some(1_600_000_000);
+/// let mut new = Book::init("new".to_string(), "new.pdf".to_string(), None, false);
+/// new.added_at = Some(1_700_000_000);
+/// new.last_opened_at = Some(1_700_000_100);
+///
+/// let recent = sort_books(vec![old.clone(), new.clone()], SortKey::AddedAt, true);
+/// assert_eq!(recent[0].name, "new");
+/// let opened = sort_books(vec![new, old], SortKey::LastOpenedAt, false);
+/// assert_eq!(opened[1].name, "old");
+/// ```
+pub fn sort_books(books: Vec<Book>, key: SortKey, descending: bool) -> Vec<Book> {
+    let mut bks = books;
+    let value = |bk: &Book| match key {
+        SortKey::Name => Some(0),
+        SortKey::AddedAt => bk.added_at,
+        SortKey::UpdatedAt => bk.updated_at,
+        SortKey::LastOpenedAt => bk.last_opened_at,
+    };
+    bks.sort_by(|bk1, bk2| {
+        let ordering = match (value(bk1), value(bk2)) {
+            (Some(v1), Some(v2)) if descending => v2.cmp(&v1),
+            (Some(v1), Some(v2)) => v1.cmp(&v2),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        ordering.then_with(|| match (key, descending) {
+            (SortKey::Name, true) => bk2.name.cmp(&bk1.name),
+            _ => bk1.name.cmp(&bk2.name),
+        })
+    });
+    bks
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A section with its books, as grouped by [`sort_books_by_section`]
+pub struct SectionGroup {
+    /// name of the section, empty for the books without a section
+    pub section: String,
+    pub books: Vec<Book>,
+}
+
+impl From<(String, Vec<Book>)> for SectionGroup {
+    fn from(value: (String, Vec<Book>)) -> Self {
+        SectionGroup {
+            section: value.0,
+            books: value.1,
+        }
+    }
+}
+
+/// Takes a vector of books and groups it by section.
+///
+/// Same as [`sort_books_by_section`] with named fields for each group.
+///
+/// ## Example
+/// ```rust
+/// use book_lib::book::{group_books_by_section, Book};
+///
+/// let books = vec![
+///     Book::init("a".to_string(), "a.pdf".to_string(), Some("math".to_string()), false),
+///     Book::init("b".to_string(), "b.pdf".to_string(), None, true),
+/// ];
+/// let groups = group_books_by_section(books);
+/// assert_eq!(groups[0].section, "");
+/// assert_eq!(groups[1].books[0].name, "a");
+/// # #[cfg(feature = "serde")]
+/// # assert_eq!(
+/// #     serde_json::to_value(&groups[1].books[0]).unwrap(),
+/// #     book_lib::export::book_to_json(&groups[1].books[0])
+/// # );
+/// ```
+pub fn group_books_by_section(books: Vec<Book>) -> Vec<SectionGroup> {
+    sort_books_by_section(books)
+        .into_iter()
+        .map(SectionGroup::from)
+        .collect()
+}
+
 /// Takes a vector of books sorts it by section.
 ///
 /// Return format:
@@ -3,9 +3,18 @@
 use dirs;
 use loggit::debug;
 use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
+use crate::audit::{self, AuditOp};
 use crate::book;
-use rusqlite::{params, Connection, Result};
+use crate::help;
+use crate::kind::DocumentKind;
+use crate::notify;
+use crate::roots;
+use crate::storage;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Result, TransactionBehavior};
 
 pub enum CreateBookError {
     BookWithNameExists,
@@ -25,16 +34,16 @@ pub struct DbConfig {
     pub path_to_db: String,
 }
 
-fn create_folder_if_not_exist(path: &str) -> io::Result<()> {
-    if !std::path::Path::new(&path).exists() {
+fn create_folder_if_not_exist(path: &Path) -> io::Result<()> {
+    if !path.exists() {
         std::fs::create_dir(path)
     } else {
         Ok(())
     }
 }
 
-fn create_file_if_not_exist(path: &str) -> io::Result<()> {
-    if !std::path::Path::new(&path).exists() {
+fn create_file_if_not_exist(path: &Path) -> io::Result<()> {
+    if !path.exists() {
         match std::fs::File::create(path) {
             Ok(_) => Ok(()),
             Err(e) => Err(e),
@@ -49,17 +58,11 @@ enum VerifyDbExistsError {
     CouldNtGetHomeDirectory,
 }
 fn verify_db_exists() -> Result<String, VerifyDbExistsError> {
-    let home_folder: String;
-    let home_fold = match dirs::home_dir() {
+    let home_folder = match dirs::home_dir() {
         None => return Err(VerifyDbExistsError::CouldNtGetHomeDirectory),
         Some(r) => r,
     };
-    if let Some(home_directory) = home_fold.to_str() {
-        home_folder = home_directory.to_string();
-    } else {
-        return Err(VerifyDbExistsError::CouldNtGetHomeDirectory);
-    }
-    let mut path_to_config = format!("{}/.config", home_folder);
+    let mut path_to_config = home_folder.join(".config");
     match create_folder_if_not_exist(&path_to_config) {
         Ok(_) => {}
         Err(_) => {
@@ -67,7 +70,7 @@ fn verify_db_exists() -> Result<String, VerifyDbExistsError> {
         }
     }
 
-    path_to_config = format!("{}/book-cli", path_to_config);
+    path_to_config = path_to_config.join("book-cli");
     match create_folder_if_not_exist(&path_to_config) {
         Ok(_) => {}
         Err(_) => {
@@ -75,12 +78,12 @@ fn verify_db_exists() -> Result<String, VerifyDbExistsError> {
         }
     }
 
-    path_to_config = format!("{}/books.db", path_to_config);
+    path_to_config = path_to_config.join("books.db");
     match create_file_if_not_exist(&path_to_config) {
         Ok(_) => {}
         Err(_) => return Err(VerifyDbExistsError::FileCouldNotBeCreated),
     }
-    Ok(path_to_config)
+    Ok(help::path_to_string(path_to_config))
 }
 
 //impl Default for DbConfig {
@@ -99,11 +102,11 @@ fn verify_db_exists() -> Result<String, VerifyDbExistsError> {
 
 fn connect_to_db() -> Connection {
     let path_to_db = match verify_db_exists() {
-        Err(e) => panic!("Couldn't run app because the db doesn't exist"),
+        Err(_) => panic!("Couldn't run app because the db doesn't exist"),
         Ok(r) => r,
     };
     let config: DbConfig = DbConfig { path_to_db };
-    let db = Connection::open(config.path_to_db);
+    let db = Connection::open(help::path_from_string(&config.path_to_db));
     match db {
         Ok(conn) => conn,
         Err(mess) => panic!("An error occured! {}", mess),
@@ -126,24 +129,475 @@ fn create_table(conn: &Connection) -> Result<bool, bool> {
     }
 }
 
+/// Schema migrations applied in order on top of the initial `books` table.
+///
+/// The number of applied migrations is kept in `PRAGMA user_version`, thus new migrations must
+/// only be appended to the end of this list.
+const MIGRATIONS: &[fn(&Connection) -> Result<()>] = &[
+    add_metadata_columns,
+    create_sources_table,
+    create_watched_folders_table,
+    add_file_size_column,
+    add_content_hash_columns,
+    create_settings_table,
+    add_kind_column,
+    add_opener_column,
+    create_reading_history_table,
+    create_progress_and_goals_tables,
+    add_timestamp_columns,
+    add_deleted_at_column,
+    create_journal_table,
+    create_audit_log_table,
+];
+
+fn add_metadata_columns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE books ADD COLUMN title TEXT;
+        ALTER TABLE books ADD COLUMN authors TEXT;
+        ALTER TABLE books ADD COLUMN year INTEGER;
+        ALTER TABLE books ADD COLUMN publisher TEXT;
+        ALTER TABLE books ADD COLUMN series TEXT;
+        ALTER TABLE books ADD COLUMN doi TEXT;
+        ALTER TABLE books ADD COLUMN isbn TEXT;
+        ALTER TABLE books ADD COLUMN tags TEXT;",
+    )
+}
+
+fn create_sources_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS book_sources(
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            source TEXT NOT NULL,
+            key TEXT NOT NULL,
+            UNIQUE(source, key)
+            );",
+    )
+}
+
+fn create_watched_folders_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE books ADD COLUMN missing INTEGER NOT NULL DEFAULT 0;
+        CREATE TABLE IF NOT EXISTS watched_folders(
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE,
+            section TEXT,
+            sections_from_dirs INTEGER NOT NULL DEFAULT 0
+            );",
+    )
+}
+
+fn add_file_size_column(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE books ADD COLUMN file_size INTEGER;")
+}
+
+fn add_content_hash_columns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE books ADD COLUMN content_hash TEXT;
+        ALTER TABLE books ADD COLUMN file_mtime INTEGER;
+        CREATE INDEX IF NOT EXISTS books_content_hash ON books(content_hash);",
+    )
+}
+
+fn create_settings_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS settings(
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+            );",
+    )
+}
+
+fn add_kind_column(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE books ADD COLUMN kind TEXT NOT NULL DEFAULT 'pdf';")
+}
+
+fn add_opener_column(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE books ADD COLUMN opener TEXT;")
+}
+
+fn create_reading_history_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS reading_history(
+            id INTEGER PRIMARY KEY,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            opened_at INTEGER NOT NULL,
+            duration INTEGER
+            );
+        CREATE INDEX IF NOT EXISTS reading_history_book ON reading_history(book_id, opened_at);
+        CREATE INDEX IF NOT EXISTS reading_history_opened ON reading_history(opened_at);",
+    )
+}
+
+fn create_progress_and_goals_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE books ADD COLUMN page_count INTEGER;
+        ALTER TABLE books ADD COLUMN current_page INTEGER;
+        ALTER TABLE books ADD COLUMN finished_at INTEGER;
+        CREATE TABLE IF NOT EXISTS page_events(
+            id INTEGER PRIMARY KEY,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            at INTEGER NOT NULL,
+            pages INTEGER NOT NULL
+            );
+        CREATE INDEX IF NOT EXISTS page_events_at ON page_events(at);
+        CREATE INDEX IF NOT EXISTS books_finished_at ON books(finished_at);
+        CREATE TABLE IF NOT EXISTS goals(
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            metric TEXT NOT NULL,
+            period TEXT NOT NULL,
+            target INTEGER NOT NULL
+            );",
+    )
+}
+
+/// Adds the timestamps of the books, the existing books are added at the last modification time of
+/// their file recorded with their content hash, or at the time of the migration when there's none,
+/// and last opened at the end of their reading history.
+///
+/// `updated_at` is kept by a trigger on every change of the data of a book.
+fn add_timestamp_columns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE books ADD COLUMN added_at INTEGER;
+        ALTER TABLE books ADD COLUMN updated_at INTEGER;
+        ALTER TABLE books ADD COLUMN last_opened_at INTEGER;
+        UPDATE books SET
+            added_at = COALESCE(file_mtime / 1000000000, CAST(strftime('%s', 'now') AS INTEGER)),
+            updated_at = COALESCE(file_mtime / 1000000000, CAST(strftime('%s', 'now') AS INTEGER)),
+            last_opened_at = (SELECT MAX(opened_at) FROM reading_history WHERE book_id = books.id);
+        CREATE TRIGGER IF NOT EXISTS books_updated_at
+            AFTER UPDATE OF name, path, section, favourite, title, authors, year, publisher,
+                series, doi, isbn, tags, kind, opener, page_count, current_page, finished_at
+            ON books
+            WHEN NEW.updated_at IS OLD.updated_at
+            BEGIN
+                UPDATE books SET updated_at = CAST(strftime('%s', 'now') AS INTEGER)
+                    WHERE id = NEW.id;
+            END;",
+    )
+}
+
+/// Adds the time a book was moved to the trash, removed books are kept until the trash is emptied
+fn add_deleted_at_column(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE books ADD COLUMN deleted_at INTEGER;
+        CREATE INDEX IF NOT EXISTS books_deleted_at ON books(deleted_at);",
+    )
+}
+
+/// Adds the journal of the changes that can be undone, see [`crate::journal`]
+fn create_journal_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS journal(
+            id INTEGER PRIMARY KEY,
+            op TEXT NOT NULL,
+            book_id INTEGER NOT NULL,
+            book TEXT NOT NULL,
+            before TEXT,
+            after TEXT,
+            at INTEGER NOT NULL,
+            undone INTEGER NOT NULL DEFAULT 0
+            );",
+    )
+}
+
+/// Adds the audit log, see [`crate::audit`]. Its entries can only be appended.
+fn create_audit_log_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS audit_log(
+            id INTEGER PRIMARY KEY,
+            at INTEGER NOT NULL,
+            actor TEXT,
+            op TEXT NOT NULL,
+            book TEXT NOT NULL,
+            old_value TEXT,
+            new_value TEXT
+            );
+        CREATE INDEX IF NOT EXISTS audit_log_book ON audit_log(book);
+        CREATE INDEX IF NOT EXISTS audit_log_at ON audit_log(at);
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'the audit log is append-only');
+            END;
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'the audit log is append-only');
+            END;",
+    )
+}
+
+fn user_version(conn: &Connection) -> Result<usize> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))
+        .map(|version| version as usize)
+}
+
+/// Applies the missing migrations, each one in a transaction that holds the write lock so that
+/// two processes opening the database at the same time don't apply the same migration twice.
+fn migrate(conn: &Connection) -> Result<()> {
+    while user_version(conn)? < MIGRATIONS.len() {
+        let tx = retry_busy(|| {
+            rusqlite::Transaction::new_unchecked(conn, TransactionBehavior::Immediate)
+        })?;
+        let version = user_version(&tx)?;
+        if let Some(migration) = MIGRATIONS.get(version) {
+            migration(&tx)?;
+            tx.pragma_update(None, "user_version", (version + 1) as i64)?;
+        }
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// How long SQLite waits for a lock held by another connection before giving up
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of times an operation refused because the database is locked is tried again
+const BUSY_RETRIES: u32 = 4;
+
+/// Wait before the first retry, doubled at each retry
+const BUSY_BACKOFF: Duration = Duration::from_millis(25);
+
+/// Runs an operation again while it fails because another connection holds a lock, waiting a bit
+/// longer each time, up to [`BUSY_RETRIES`] times.
+pub(crate) fn retry_busy<T>(mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut wait = BUSY_BACKOFF;
+    for _ in 0..BUSY_RETRIES {
+        match op() {
+            Err(err) if is_busy(&err) => {
+                std::thread::sleep(wait);
+                wait *= 2;
+            }
+            res => return res,
+        }
+    }
+    op()
+}
+
+fn is_busy(err: &rusqlite::Error) -> bool {
+    matches!(
+        err.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
+
+/// Sets the options of a connection to the library database:
+/// - the write-ahead log, readers don't block the writer and the writer doesn't block readers;
+/// - a busy timeout of [`BUSY_TIMEOUT`], a connection waits for the lock of another one instead of
+///   failing with "database is locked";
+/// - `synchronous = NORMAL`, which is safe with the write-ahead log;
+/// - the foreign keys.
+///
+/// [`setup`] calls it, it's only needed for connections opened by other means.
+pub fn configure(conn: &Connection) -> Result<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    retry_busy(|| conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(())))?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.pragma_update(None, "foreign_keys", true)
+}
+
+/// Opens the library database, configures the connection (see [`configure`]) and brings the
+/// database up to date.
+///
+/// Several processes can open the database at the same time, e.g. a CLI and a GUI. A
+/// [`rusqlite::Connection`] can be moved to another thread but not shared between threads, see
+/// [`crate::shared`].
 pub fn setup() -> Connection {
     let conn = connect_to_db();
-    let _ = create_table(&conn);
+    if let Err(mess) = configure(&conn) {
+        panic!("Couldn't configure the database! {}", mess)
+    }
+    if let Err(mess) = prepare(&conn) {
+        panic!("Couldn't migrate the database! {}", mess)
+    }
     conn
 }
 
+/// Creates the tables of the library and brings them up to date, for a connection opened by
+/// other means than [`setup`], e.g. an in-memory database:
+///
+/// ```rust
+/// let conn = rusqlite::Connection::open_in_memory().unwrap();
+/// book_lib::db::configure(&conn).unwrap();
+/// book_lib::db::prepare(&conn).unwrap();
+/// assert!(book_lib::get_books(&conn).unwrap().is_empty());
+/// ```
+pub fn prepare(conn: &Connection) -> Result<()> {
+    let _ = create_table(conn);
+    migrate(conn)
+}
+
+/// The columns of the `books` table in the order expected by [`book_from_row`].
+pub(crate) const BOOK_COLUMNS: &str =
+    "name, path, section, favourite, title, authors, year, publisher, series, doi, isbn, tags, missing, kind, added_at, updated_at, last_opened_at";
+
+/// Separator used to store lists (authors, tags) in a single column.
+const LIST_SEPARATOR: &str = "\n";
+
+fn join_list(list: &[String]) -> Option<String> {
+    if list.is_empty() {
+        None
+    } else {
+        Some(list.join(LIST_SEPARATOR))
+    }
+}
+
+fn split_list(value: Option<String>) -> Vec<String> {
+    match value {
+        Some(val) => val
+            .split(LIST_SEPARATOR)
+            .filter(|item| !item.is_empty())
+            .map(|item| item.to_string())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Builds a book from a row selected with [`BOOK_COLUMNS`].
+pub(crate) fn book_from_row(row: &rusqlite::Row) -> Result<book::Book> {
+    Ok(book::Book {
+        name: row.get(0)?,
+        path: roots::resolve_path(&row.get::<_, String>(1)?),
+        section: row.get(2)?,
+        favourite: row.get(3)?,
+        metadata: book::Metadata {
+            title: row.get(4)?,
+            authors: split_list(row.get(5)?),
+            year: row.get(6)?,
+            publisher: row.get(7)?,
+            series: row.get(8)?,
+            doi: row.get(9)?,
+            isbn: row.get(10)?,
+            tags: split_list(row.get(11)?),
+        },
+        missing: row.get(12)?,
+        kind: DocumentKind::from_name(&row.get::<_, String>(13)?).unwrap_or_default(),
+        added_at: row.get(14)?,
+        updated_at: row.get(15)?,
+        last_opened_at: row.get(16)?,
+    })
+}
+
+static SAVEPOINT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A savepoint that is rolled back when dropped unless it was released.
+///
+/// Unlike a transaction, a savepoint can be opened while another transaction or savepoint is
+/// already open on the connection, which lets the functions taking a `&Connection` be atomic
+/// both on their own and as a part of a bigger transaction.
+///
+/// Outside of a transaction, the savepoint is opened as an immediate transaction that takes the
+/// write lock right away: a deferred one could fail with "database is locked" halfway when another
+/// process writes, without waiting for the busy timeout.
+///
+/// The files copied or moved into the managed library root while the savepoint is open are put
+/// back when it's rolled back, see [`storage`].
+pub(crate) struct Savepoint<'a> {
+    conn: &'a Connection,
+    /// None for a top level transaction
+    name: Option<String>,
+    /// transfers of files made before the savepoint was opened
+    transfers: usize,
+    done: bool,
+}
+
+impl<'a> Savepoint<'a> {
+    pub(crate) fn new(conn: &'a Connection) -> Result<Savepoint<'a>> {
+        let name = match conn.is_autocommit() {
+            true => {
+                retry_busy(|| conn.execute_batch("BEGIN IMMEDIATE"))?;
+                // transfers left by a transaction that wasn't opened by the library
+                storage::commit_transfers(conn, 0);
+                None
+            }
+            false => {
+                let name = format!(
+                    "book_lib_{}",
+                    SAVEPOINT_COUNTER.fetch_add(1, Ordering::Relaxed)
+                );
+                conn.execute_batch(&format!("SAVEPOINT {}", name))?;
+                Some(name)
+            }
+        };
+        Ok(Savepoint {
+            conn,
+            name,
+            transfers: storage::transfers_mark(),
+            done: false,
+        })
+    }
+
+    /// Keeps the changes made since the savepoint was opened
+    pub(crate) fn release(mut self) -> Result<()> {
+        self.done = true;
+        match &self.name {
+            Some(name) => self.conn.execute_batch(&format!("RELEASE {}", name)),
+            None => retry_busy(|| self.conn.execute_batch("COMMIT")).inspect(|_| {
+                storage::commit_transfers(self.conn, self.transfers);
+                notify::committed(self.conn);
+            }),
+        }
+        .inspect_err(|_| self.done = false)
+    }
+}
+
+impl Drop for Savepoint<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = match &self.name {
+                Some(name) => self
+                    .conn
+                    .execute_batch(&format!("ROLLBACK TO {name}; RELEASE {name}")),
+                None => self.conn.execute_batch("ROLLBACK"),
+            };
+            storage::rollback_transfers(self.conn, self.transfers);
+        }
+    }
+}
+
 pub(crate) fn create_book(conn: &Connection, bk: &book::Book) -> Result<bool, CreateBookError> {
     let bk_res = get_book(conn, &bk.name);
     if bk_res.is_ok() {
         return Err(CreateBookError::BookWithNameExists);
     }
-    match conn.execute(
-        "INSERT INTO books (name, path, section) VALUES (?, ?, ?)",
-        params![bk.name, bk.path, bk.section],
-    ) {
-        Ok(_) => Ok(true),
-        Err(_) => Err(CreateBookError::Other),
+    let meta = &bk.metadata;
+    // the timestamps of a restored book are kept
+    let added_at = bk.added_at.unwrap_or_else(now);
+    let path = roots::portable_path(&bk.path);
+    let savepoint = match Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(CreateBookError::Other),
+    };
+    let inserted = conn.execute(
+        "INSERT INTO books (name, path, section, favourite, title, authors, year, publisher, series, doi, isbn, tags, file_size, kind, added_at, updated_at, last_opened_at, missing)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            bk.name,
+            path,
+            bk.section,
+            bk.favourite,
+            meta.title,
+            join_list(&meta.authors),
+            meta.year,
+            meta.publisher,
+            meta.series,
+            meta.doi,
+            meta.isbn,
+            join_list(&meta.tags),
+            file_size(&bk.path),
+            bk.kind.as_str(),
+            added_at,
+            bk.updated_at.unwrap_or(added_at),
+            bk.last_opened_at,
+            bk.missing,
+        ],
+    );
+    if inserted.is_err()
+        || audit::log(conn, AuditOp::CreateBook, &bk.name, None, Some(path)).is_err()
+        || savepoint.release().is_err()
+    {
+        return Err(CreateBookError::Other);
     }
+    Ok(true)
 }
 
 #[derive(Debug)]
@@ -162,7 +616,12 @@ impl std::fmt::Display for RemoveBookError {
 }
 
 fn remove_book_from_db(conn: &Connection, name: &String) -> Result<usize> {
-    let res = conn.execute("DELETE FROM books WHERE name = ?", params![name]);
+    let res = audited_update(conn, AuditOp::RemoveBook, name, "path", || {
+        conn.execute(
+            "UPDATE books SET deleted_at = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+            params![now(), name],
+        )
+    });
     debug!("Name: {}, query: {}", name, "temp");
     res
 }
@@ -180,6 +639,40 @@ pub(crate) fn remove_book(conn: &Connection, name: &String) -> Result<book::Book
     }
 }
 
+/// Returns the value of a column of a book as text, None if it's NULL or the book doesn't exist
+fn column_value(conn: &Connection, name: &String, column: &str) -> Result<Option<String>> {
+    conn.query_row(
+        &format!(
+            "SELECT CAST({} AS TEXT) FROM books WHERE name = ? AND deleted_at IS NULL",
+            column
+        ),
+        params![name],
+        |row| row.get(0),
+    )
+    .optional()
+    .map(Option::flatten)
+}
+
+/// Runs an update of a column of a book and logs the change in the audit log in the same
+/// transaction, see [`audit`]
+pub(crate) fn audited_update(
+    conn: &Connection,
+    op: AuditOp,
+    name: &String,
+    column: &str,
+    update: impl FnOnce() -> Result<usize>,
+) -> Result<usize> {
+    let savepoint = Savepoint::new(conn)?;
+    let old = column_value(conn, name, column)?;
+    let updated = update()?;
+    let new = column_value(conn, name, column)?;
+    if updated > 0 && old != new {
+        audit::log(conn, op, name, old, new)?;
+    }
+    savepoint.release()?;
+    Ok(updated)
+}
+
 pub enum GetBookError {
     QueryError(rusqlite::Error),
     EmptyList,
@@ -188,17 +681,12 @@ pub enum GetBookError {
 }
 
 pub fn get_book(conn: &Connection, name: &String) -> Result<book::Book, GetBookError> {
-    let stmt = conn.prepare("SELECT * FROM books WHERE name = :name;");
+    let stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE name = :name AND deleted_at IS NULL;",
+        BOOK_COLUMNS
+    ));
     if let Ok(mut stmt_res) = stmt {
-        match stmt_res.query_map(&[(":name", name)], |row| {
-            let _: u32 = row.get(0)?;
-            Ok(book::Book {
-                name: row.get(1)?,
-                path: row.get(2)?,
-                section: row.get(3)?,
-                favourite: row.get(4)?,
-            })
-        }) {
+        match stmt_res.query_map(&[(":name", name)], book_from_row) {
             Ok(mut book_iter) => {
                 if let Some(bk_) = book_iter.next() {
                     if let Ok(bk) = bk_ {
@@ -234,17 +722,12 @@ impl std::fmt::Display for GetBooksError {
 }
 
 pub(crate) fn get_books(conn: &Connection) -> Result<Vec<book::Book>, GetBooksError> {
-    let stmt = conn.prepare("SELECT * FROM books");
+    let stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE deleted_at IS NULL",
+        BOOK_COLUMNS
+    ));
     if let Ok(mut stmt_res) = stmt {
-        match stmt_res.query_map([], |row| {
-            let _: u32 = row.get(0)?;
-            Ok(book::Book {
-                name: row.get(1)?,
-                path: row.get(2)?,
-                section: row.get(3)?,
-                favourite: row.get(4)?,
-            })
-        }) {
+        match stmt_res.query_map([], book_from_row) {
             Ok(book_iter) => {
                 let mut res: Vec<book::Book> = Vec::new();
                 for bk_ in book_iter.flatten() {
@@ -259,6 +742,222 @@ pub(crate) fn get_books(conn: &Connection) -> Result<Vec<book::Book>, GetBooksEr
     }
 }
 
+/// Returns the current time in seconds since the Unix epoch
+pub(crate) fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Returns the size of a file in bytes, None if it can't be read
+pub(crate) fn file_size(path: &str) -> Option<i64> {
+    std::fs::metadata(help::path_from_string(path))
+        .ok()
+        .map(|meta| meta.len() as i64)
+}
+
+/// Returns the modification time of a file in nanoseconds since the Unix epoch, None if it can't
+/// be read
+pub(crate) fn file_mtime(path: &str) -> Option<i64> {
+    let modified = std::fs::metadata(help::path_from_string(path))
+        .ok()?
+        .modified()
+        .ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_nanos() as i64)
+}
+
+/// Points a book to a new path, records the size of the new file and clears the missing flag
+///
+/// The path is stored relative to a library root when possible, see [`roots`], and the kind of
+/// the document is detected again.
+/// The content hash of the book is forgotten, it's computed again when needed.
+pub(crate) fn update_path(conn: &Connection, name: &String, path: &str) -> Result<usize> {
+    audited_update(conn, AuditOp::UpdatePath, name, "path", || {
+        conn.execute(
+        "UPDATE books SET path = ?1, missing = 0, file_size = ?2, content_hash = NULL, file_mtime = NULL,
+            kind = COALESCE(?3, kind) WHERE name = ?4 AND deleted_at IS NULL",
+        params![
+            roots::portable_path(path),
+            file_size(path),
+            DocumentKind::detect(help::path_from_string(path)).map(|kind| kind.as_str()),
+            name
+        ],
+        )
+    })
+}
+
+/// Renames a book
+pub(crate) fn rename_book(conn: &Connection, name: &String, new_name: &String) -> Result<usize> {
+    let savepoint = Savepoint::new(conn)?;
+    let renamed = conn.execute(
+        "UPDATE books SET name = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+        params![new_name, name],
+    )?;
+    if renamed > 0 {
+        let (old, new) = (Some(name.clone()), Some(new_name.clone()));
+        audit::log(conn, AuditOp::RenameBook, new_name, old, new)?;
+    }
+    savepoint.release()?;
+    Ok(renamed)
+}
+
+/// Moves a book to another section, None to remove it from its section
+pub(crate) fn update_section(
+    conn: &Connection,
+    name: &String,
+    section: &Option<String>,
+) -> Result<usize> {
+    audited_update(conn, AuditOp::UpdateSection, name, "section", || {
+        conn.execute(
+            "UPDATE books SET section = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+            params![section, name],
+        )
+    })
+}
+
+/// Returns the value of a library setting
+pub(crate) fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT value FROM settings WHERE key = ?")?;
+    let mut rows = stmt.query(params![key])?;
+    match rows.next()? {
+        Some(row) => row.get(0),
+        None => Ok(None),
+    }
+}
+
+/// Sets the value of a library setting, None removes it
+pub(crate) fn set_setting(conn: &Connection, key: &str, value: Option<&str>) -> Result<usize> {
+    match value {
+        Some(value) => conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)",
+            params![key, value],
+        ),
+        None => conn.execute("DELETE FROM settings WHERE key = ?", params![key]),
+    }
+}
+
+/// Returns the file size recorded for a book
+pub(crate) fn get_file_size(conn: &Connection, name: &String) -> Result<Option<i64>> {
+    conn.query_row(
+        "SELECT file_size FROM books WHERE name = ? AND deleted_at IS NULL",
+        params![name],
+        |row| row.get(0),
+    )
+}
+
+/// Records the size of the file of a book
+pub(crate) fn set_file_size(conn: &Connection, name: &String, size: Option<i64>) -> Result<usize> {
+    conn.execute(
+        "UPDATE books SET file_size = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+        params![size, name],
+    )
+}
+
+/// Returns the name of the opener preferred for a book
+pub(crate) fn get_opener(conn: &Connection, name: &String) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT opener FROM books WHERE name = ? AND deleted_at IS NULL",
+        params![name],
+        |row| row.get(0),
+    )
+}
+
+/// Sets the opener preferred for a book, None to use the opener of its kind
+pub(crate) fn set_opener(conn: &Connection, name: &String, opener: Option<&str>) -> Result<usize> {
+    audited_update(conn, AuditOp::UpdateOpener, name, "opener", || {
+        conn.execute(
+            "UPDATE books SET opener = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+            params![opener, name],
+        )
+    })
+}
+
+/// Content hash of a book with the size and modification time of the file it was computed from
+pub(crate) struct StoredHash {
+    pub(crate) hash: Option<String>,
+    pub(crate) size: Option<i64>,
+    pub(crate) mtime: Option<i64>,
+}
+
+/// Returns the content hash recorded for a book
+pub(crate) fn get_content_hash(conn: &Connection, name: &String) -> Result<StoredHash> {
+    conn.query_row(
+        "SELECT content_hash, file_size, file_mtime FROM books WHERE name = ? AND deleted_at IS NULL",
+        params![name],
+        |row| {
+            Ok(StoredHash {
+                hash: row.get(0)?,
+                size: row.get(1)?,
+                mtime: row.get(2)?,
+            })
+        },
+    )
+}
+
+/// Records the content hash of a book with the size and modification time of its file
+pub(crate) fn set_content_hash(
+    conn: &Connection,
+    name: &String,
+    stored: &StoredHash,
+) -> Result<usize> {
+    conn.execute(
+        "UPDATE books SET content_hash = ?1, file_size = ?2, file_mtime = ?3 WHERE name = ?4 AND deleted_at IS NULL",
+        params![stored.hash, stored.size, stored.mtime, name],
+    )
+}
+
+/// Flags a book whose file disappeared, or clears the flag
+pub(crate) fn set_missing(conn: &Connection, name: &String, missing: bool) -> Result<usize> {
+    audited_update(conn, AuditOp::UpdateMissing, name, "missing", || {
+        conn.execute(
+            "UPDATE books SET missing = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+            params![missing, name],
+        )
+    })
+}
+
+/// Returns the id of the row of a book by its name
+pub(crate) fn get_book_id(conn: &Connection, name: &String) -> Result<i64> {
+    conn.query_row(
+        "SELECT id FROM books WHERE name = ? AND deleted_at IS NULL",
+        params![name],
+        |row| row.get(0),
+    )
+}
+
+/// Remembers that a book was imported from the entry `key` of an external `source`
+pub(crate) fn add_book_source(
+    conn: &Connection,
+    name: &String,
+    source: &str,
+    key: &str,
+) -> Result<usize> {
+    let book_id = get_book_id(conn, name)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO book_sources (book_id, source, key) VALUES (?, ?, ?)",
+        params![book_id, source, key],
+    )
+}
+
+/// Returns the name of the book imported from the entry `key` of an external `source` if any,
+/// with true if the book is in the trash
+pub(crate) fn get_book_by_source(
+    conn: &Connection,
+    source: &str,
+    key: &str,
+) -> Option<(String, bool)> {
+    conn.query_row(
+        "SELECT books.name, books.deleted_at IS NOT NULL
+        FROM book_sources JOIN books ON books.id = book_sources.book_id
+        WHERE book_sources.source = ? AND book_sources.key = ?",
+        params![source, key],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .ok()
+}
+
 pub enum UpdateFavouriteError {
     BookDoesNotExist,
     OtherError,
@@ -272,10 +971,12 @@ pub(crate) fn update_favourite_error(
     if get_book(conn, name).is_err() {
         return Err(UpdateFavouriteError::BookDoesNotExist);
     }
-    let stmt = conn.execute(
-        "UPDATE books SET favourite = ?1 WHERE name = ?2",
-        params![(favourite as u8), name,],
-    );
+    let stmt = audited_update(conn, AuditOp::UpdateFavourite, name, "favourite", || {
+        conn.execute(
+            "UPDATE books SET favourite = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+            params![(favourite as u8), name,],
+        )
+    });
     match stmt {
         Ok(_) => {
             if let Ok(book) = get_book(conn, name) {
@@ -1,9 +1,31 @@
+//! Errors returned by the public API of the library.
+//!
+//! With the `serde` feature enabled, every error implements `Serialize` and `Deserialize` using
+//! the externally tagged representation: unit variants are written as their name (e.g.
+//! `"BookNameAlreadyUsed"`) and variants with data as an object with a single key
+//! (e.g. `{"InvalidFormat": {"line": 3, "message": "..."}}`). The variant names are part of the
+//! public format.
+
 use super::db;
+use crate::batch::BatchReport;
+use crate::kind::DocumentKind;
+use crate::pdf::PdfVerdict;
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CreateBookError {
     ProvidedPathIsNotPdf,
     ProvidedPathIsIncorrect,
     BookNameAlreadyUsed,
+    /// the file has the same content as the file of the given book
+    SameContentAs(String),
+    /// the file couldn't be copied or moved into the managed library root
+    CouldNotStoreFile,
+    /// the kind of the document isn't allowed, None if it isn't known. When only PDFs are allowed
+    /// [`CreateBookError::ProvidedPathIsNotPdf`] is returned instead
+    DocumentKindNotAllowed(Option<DocumentKind>),
+    /// the content of the file isn't a readable PDF, see [`crate::pdf::check_pdf`]
+    InvalidPdf(PdfVerdict),
     OtherError,
 }
 
@@ -13,11 +35,28 @@ impl std::fmt::Display for CreateBookError {
             CreateBookError::ProvidedPathIsNotPdf => write!(f, "Provdied path is not a PDF file!"),
             CreateBookError::ProvidedPathIsIncorrect => write!(f, "Provide path is incorrect!"),
             CreateBookError::BookNameAlreadyUsed => write!(f, "Provided name is already in use!"),
+            CreateBookError::SameContentAs(name) => {
+                write!(f, "The same file is already registered as {}!", name)
+            }
+            CreateBookError::DocumentKindNotAllowed(Some(kind)) => {
+                write!(f, "{} documents aren't allowed!", kind)
+            }
+            CreateBookError::DocumentKindNotAllowed(None) => {
+                write!(f, "Unknown document kind!")
+            }
+            CreateBookError::InvalidPdf(verdict) => {
+                write!(f, "The file isn't a readable PDF ({})!", verdict)
+            }
+            CreateBookError::CouldNotStoreFile => {
+                write!(f, "Couldn't store the file in the library root!")
+            }
             CreateBookError::OtherError => write!(f, "Unexpected error!"),
         }
     }
 }
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UpdateFavouriteError {
     BookDoesNotExist,
     Other,
@@ -41,6 +80,8 @@ impl From<db::UpdateFavouriteError> for UpdateFavouriteError {
     }
 }
 
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GetBooksError {
     BookOrTableDoesnotExist,
     NoBooks,
@@ -66,6 +107,8 @@ impl From<db::GetBooksError> for GetBooksError {
     }
 }
 
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GetBookError {
     TableOrBookDoesnotExist,
 }
@@ -89,6 +132,8 @@ impl From<db::GetBookError> for GetBookError {
     }
 }
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RemoveBookError {
     BookDoesNotExist,
     Other,
@@ -103,23 +148,521 @@ impl std::fmt::Display for RemoveBookError {
     }
 }
 
-//pub enum OpenBookError {
-//    BookDoesNotExist,
-//    PathIsIncorrect,
-//    FileIsNotPDF,
-//    OtherError,
-//}
-//
-//impl std::fmt::Display for OpenBookError {
-//    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//        match self {
-//            OpenBookError::BookDoesNotExist => write!(f, "This book does not exist!"),
-//            OpenBookError::PathIsIncorrect => write!(f, "Provided path is incorrect!"),
-//            OpenBookError::FileIsNotPDF => write!(f, "Provided path is not a PDF file!"),
-//            OpenBookError::OtherError => write!(f, "Unexpected error!"),
-//        }
-//    }
-//}
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImportError {
+    CouldNotReadFile,
+    InvalidFormat {
+        line: usize,
+        message: String,
+    },
+    InvalidDatabase(String),
+    /// a book of the library couldn't be created, nothing was imported
+    BookNotCreated {
+        name: String,
+        error: CreateBookError,
+    },
+    DatabaseError,
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::CouldNotReadFile => write!(f, "Couldn't read the provided file!"),
+            ImportError::InvalidFormat { line, message } => {
+                write!(f, "Invalid file format on line {}: {}", line, message)
+            }
+            ImportError::InvalidDatabase(message) => {
+                write!(f, "Couldn't read the provided database: {}", message)
+            }
+            ImportError::BookNotCreated { name, error } => {
+                write!(f, "Couldn't import the book {}: {}", name, error)
+            }
+            ImportError::DatabaseError => write!(f, "Couldn't write the books to the database!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExportError {
+    CouldNotReadBooks,
+    CouldNotWrite,
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::CouldNotReadBooks => {
+                write!(f, "Couldn't read the books from the database!")
+            }
+            ExportError::CouldNotWrite => write!(f, "Couldn't write the exported books!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScanError {
+    RootDoesNotExist,
+    InvalidGlob(String),
+    DatabaseError,
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanError::RootDoesNotExist => write!(f, "The directory to scan doesn't exist!"),
+            ScanError::InvalidGlob(glob) => write!(f, "Invalid glob pattern: {}", glob),
+            ScanError::DatabaseError => write!(f, "Couldn't write the books to the database!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WatchError {
+    FolderDoesNotExist,
+    FolderAlreadyWatched,
+    FolderNotWatched,
+    DatabaseError,
+}
+
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchError::FolderDoesNotExist => write!(f, "The folder doesn't exist!"),
+            WatchError::FolderAlreadyWatched => write!(f, "The folder is already watched!"),
+            WatchError::FolderNotWatched => write!(f, "The folder isn't watched!"),
+            WatchError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UpdatePathError {
+    BookDoesNotExist,
+    ProvidedPathIsNotPdf,
+    DocumentKindNotAllowed(Option<DocumentKind>),
+    InvalidPdf(PdfVerdict),
+    ProvidedPathIsIncorrect,
+    Other,
+}
+
+impl std::fmt::Display for UpdatePathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdatePathError::BookDoesNotExist => write!(f, "The book doesn't exist!"),
+            UpdatePathError::ProvidedPathIsNotPdf => write!(f, "Provided path is not a PDF file!"),
+            UpdatePathError::DocumentKindNotAllowed(Some(kind)) => {
+                write!(f, "{} documents aren't allowed!", kind)
+            }
+            UpdatePathError::DocumentKindNotAllowed(None) => write!(f, "Unknown document kind!"),
+            UpdatePathError::InvalidPdf(verdict) => {
+                write!(f, "The file isn't a readable PDF ({})!", verdict)
+            }
+            UpdatePathError::ProvidedPathIsIncorrect => write!(f, "Provided path is incorrect!"),
+            UpdatePathError::Other => write!(f, "Unexpected error!"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenameBookError {
+    BookDoesNotExist,
+    BookNameAlreadyUsed,
+    CouldNotMoveFile,
+    Other,
+}
+
+impl std::fmt::Display for RenameBookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameBookError::BookDoesNotExist => write!(f, "The book doesn't exist!"),
+            RenameBookError::BookNameAlreadyUsed => write!(f, "Provided name is already in use!"),
+            RenameBookError::CouldNotMoveFile => write!(f, "Couldn't move the file of the book!"),
+            RenameBookError::Other => write!(f, "Unexpected error!"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UpdateSectionError {
+    BookDoesNotExist,
+    CouldNotMoveFile,
+    Other,
+}
+
+impl std::fmt::Display for UpdateSectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateSectionError::BookDoesNotExist => write!(f, "The book doesn't exist!"),
+            UpdateSectionError::CouldNotMoveFile => {
+                write!(f, "Couldn't move the file of the book!")
+            }
+            UpdateSectionError::Other => write!(f, "Unexpected error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HealthError {
+    CouldNotReadBooks,
+    BookDoesNotExist,
+    DatabaseError,
+}
+
+impl std::fmt::Display for HealthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthError::CouldNotReadBooks => {
+                write!(f, "Couldn't read the books from the database!")
+            }
+            HealthError::BookDoesNotExist => write!(f, "The book doesn't exist!"),
+            HealthError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashError {
+    CouldNotReadBooks,
+    BookDoesNotExist,
+    CouldNotReadFile,
+    DatabaseError,
+}
+
+impl std::fmt::Display for HashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashError::CouldNotReadBooks => {
+                write!(f, "Couldn't read the books from the database!")
+            }
+            HashError::BookDoesNotExist => write!(f, "The book doesn't exist!"),
+            HashError::CouldNotReadFile => write!(f, "Couldn't read the file!"),
+            HashError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StorageError {
+    NotConfigured,
+    CouldNotCreateRoot,
+    InvalidTemplate(String),
+    BookDoesNotExist,
+    CouldNotTransferFile(String),
+    DatabaseError,
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::NotConfigured => write!(f, "The managed storage isn't configured!"),
+            StorageError::CouldNotCreateRoot => write!(f, "Couldn't create the library root!"),
+            StorageError::InvalidTemplate(message) => {
+                write!(f, "Invalid naming template: {}!", message)
+            }
+            StorageError::BookDoesNotExist => write!(f, "The book doesn't exist!"),
+            StorageError::CouldNotTransferFile(message) => {
+                write!(f, "Couldn't copy or move the file: {}!", message)
+            }
+            StorageError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RootError {
+    InvalidName(String),
+    PathDoesNotExist,
+    UnknownRoot(String),
+    CouldNotWriteFile,
+    CouldNotReadBooks,
+    DatabaseError,
+}
+
+impl std::fmt::Display for RootError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RootError::InvalidName(name) => write!(f, "{} isn't a valid root name!", name),
+            RootError::PathDoesNotExist => write!(f, "Provided directory doesn't exist!"),
+            RootError::UnknownRoot(name) => write!(f, "There's no root named {}!", name),
+            RootError::CouldNotWriteFile => write!(f, "Couldn't write the roots file!"),
+            RootError::CouldNotReadBooks => {
+                write!(f, "Couldn't read the books from the database!")
+            }
+            RootError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KindError {
+    NoKindAllowed,
+    DatabaseError,
+}
+
+impl std::fmt::Display for KindError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KindError::NoKindAllowed => write!(f, "At least one document kind must be allowed!"),
+            KindError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PathError {
+    DoesNotExist,
+    /// the path exists but couldn't be resolved, e.g. a directory on the way isn't readable
+    CouldNotResolve,
+}
+
+impl std::fmt::Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::DoesNotExist => write!(f, "Provided path doesn't exist!"),
+            PathError::CouldNotResolve => write!(f, "Provided path couldn't be resolved!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HistoryError {
+    BookDoesNotExist,
+    EventDoesNotExist,
+    /// the session ends before it starts
+    InvalidDuration,
+    DatabaseError,
+}
+
+impl std::fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::BookDoesNotExist => write!(f, "The book doesn't exist!"),
+            HistoryError::EventDoesNotExist => write!(f, "The reading session doesn't exist!"),
+            HistoryError::InvalidDuration => {
+                write!(f, "A reading session can't end before it starts!")
+            }
+            HistoryError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProgressError {
+    BookDoesNotExist,
+    /// the page is after the last page of the book
+    PageOutOfRange(u32),
+    /// a book must have at least one page
+    InvalidPageCount,
+    DatabaseError,
+}
+
+impl std::fmt::Display for ProgressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProgressError::BookDoesNotExist => write!(f, "The book doesn't exist!"),
+            ProgressError::PageOutOfRange(page) => {
+                write!(f, "The book doesn't have a page {}!", page)
+            }
+            ProgressError::InvalidPageCount => write!(f, "A book must have at least one page!"),
+            ProgressError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatsError {
+    GoalAlreadyExists(String),
+    GoalDoesNotExist(String),
+    /// the target of a goal must be positive
+    InvalidTarget,
+    DatabaseError,
+}
+
+impl std::fmt::Display for StatsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatsError::GoalAlreadyExists(name) => write!(f, "A goal named {} exists!", name),
+            StatsError::GoalDoesNotExist(name) => write!(f, "There's no goal named {}!", name),
+            StatsError::InvalidTarget => write!(f, "The target of a goal must be positive!"),
+            StatsError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OpenBookError {
+    BookDoesNotExist,
+    /// the file of the book doesn't exist
+    PathIsIncorrect,
+    /// no opener with this name is registered
+    UnknownOpener(String),
+    /// the command template of an opener is empty
+    InvalidTemplate(String),
+    /// the opener couldn't be started, with the reason
+    CouldNotOpen(String),
+    DatabaseError,
+}
+
+impl std::fmt::Display for OpenBookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenBookError::BookDoesNotExist => write!(f, "This book does not exist!"),
+            OpenBookError::PathIsIncorrect => write!(f, "Provided path is incorrect!"),
+            OpenBookError::UnknownOpener(name) => write!(f, "There's no opener named {}!", name),
+            OpenBookError::InvalidTemplate(template) => {
+                write!(f, "{:?} isn't a valid command template!", template)
+            }
+            OpenBookError::CouldNotOpen(reason) => write!(f, "Couldn't open the book: {}!", reason),
+            OpenBookError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrashError {
+    /// there's no book with this id in the trash
+    BookNotInTrash,
+    /// a book with the same name is in the library
+    NameConflict(String),
+    DatabaseError,
+}
+
+impl std::fmt::Display for TrashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrashError::BookNotInTrash => write!(f, "This book isn't in the trash!"),
+            TrashError::NameConflict(name) => {
+                write!(f, "A book named {} is already in the library!", name)
+            }
+            TrashError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JournalError {
+    NothingToUndo,
+    NothingToRedo,
+    /// the book isn't in the state the change left it in
+    Diverged,
+    /// the file of a book kept in the managed library root couldn't be moved
+    CouldNotMoveFile,
+    DatabaseError,
+}
+
+impl std::fmt::Display for JournalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JournalError::NothingToUndo => write!(f, "There's nothing to undo!"),
+            JournalError::NothingToRedo => write!(f, "There's nothing to redo!"),
+            JournalError::Diverged => {
+                write!(
+                    f,
+                    "The book was changed since, the change can't be replayed!"
+                )
+            }
+            JournalError::CouldNotMoveFile => write!(f, "Couldn't move the file of the book!"),
+            JournalError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuditError {
+    EntryDoesNotExist,
+    CouldNotWrite,
+    DatabaseError,
+}
+
+impl std::fmt::Display for AuditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditError::EntryDoesNotExist => {
+                write!(f, "This entry of the audit log doesn't exist!")
+            }
+            AuditError::CouldNotWrite => write!(f, "Couldn't write the audit log!"),
+            AuditError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+/// Error of a single change of a batch, see [`crate::batch`]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BatchItemError {
+    CreateBook(CreateBookError),
+    RemoveBook(RemoveBookError),
+    UpdateFavourite(UpdateFavouriteError),
+    UpdatePath(UpdatePathError),
+    RenameBook(RenameBookError),
+    UpdateSection(UpdateSectionError),
+}
+
+impl std::fmt::Display for BatchItemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchItemError::CreateBook(err) => err.fmt(f),
+            BatchItemError::RemoveBook(err) => err.fmt(f),
+            BatchItemError::UpdateFavourite(err) => err.fmt(f),
+            BatchItemError::UpdatePath(err) => err.fmt(f),
+            BatchItemError::RenameBook(err) => err.fmt(f),
+            BatchItemError::UpdateSection(err) => err.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BatchError {
+    /// a change failed or the batch was aborted, nothing was written
+    RolledBack(BatchReport),
+    DatabaseError,
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchError::RolledBack(_) => {
+                write!(f, "The batch was rolled back, nothing was changed!")
+            }
+            BatchError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NotifyError {
+    DatabaseError,
+}
+
+impl std::fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotifyError::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
 
 impl From<db::RemoveBookError> for RemoveBookError {
     fn from(value: db::RemoveBookError) -> Self {
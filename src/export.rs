@@ -0,0 +1,440 @@
+//! A module for moving the whole library in and out of the database as JSON or CSV.
+//!
+//! ## JSON format
+//! ```json
+//! {
+//!   "version": 1,
+//!   "books": [
+//!     {
+//!       "name": "book_name",
+//!       "path": "/path/to/file.pdf",
+//!       "section": "math",
+//!       "favourite": true,
+//!       "metadata": {
+//!         "title": "A title", "authors": ["Doe, Jane"], "year": 2020, "publisher": null,
+//!         "series": null, "doi": null, "isbn": null, "tags": ["algebra"]
+//!       },
+//!       "missing": false,
+//!       "kind": "pdf",
+//!       "added_at": 1700000000,
+//!       "updated_at": 1700000000,
+//!       "last_opened_at": null
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! ## CSV format
+//! One book per row with the header
+//! `name,path,section,favourite,title,authors,year,publisher,series,doi,isbn,tags,kind`, authors
+//! and tags are joined with `"; "`.
+//!
+//! The `kind` field was added after the first version of the formats, books without it are PDFs.
+//! The timestamps (in seconds since the Unix epoch) are only in the JSON format, a book imported
+//! without them is added at the time of the import.
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+use crate::book::{Book, Metadata};
+use crate::db;
+use crate::errors::{CreateBookError, ExportError, ImportError};
+use crate::kind::DocumentKind;
+
+/// Version of the JSON format written by [`export_json`]
+pub const FORMAT_VERSION: u64 = 1;
+
+const CSV_HEADER: [&str; 13] = [
+    "name",
+    "path",
+    "section",
+    "favourite",
+    "title",
+    "authors",
+    "year",
+    "publisher",
+    "series",
+    "doi",
+    "isbn",
+    "tags",
+    "kind",
+];
+
+const CSV_LIST_SEPARATOR: &str = "; ";
+
+/// How an imported library is combined with the books already in the database
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImportMode {
+    /// keep the existing books and add the imported ones, books with a name that is already in
+    /// use are not imported
+    #[default]
+    Merge,
+    /// move every existing book to the trash before adding the imported ones, see [`crate::trash`]
+    Replace,
+}
+
+/// Options of [`import_json`] and [`import_csv`]
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LibraryImportOptions {
+    pub mode: ImportMode,
+    /// compute the report without writing anything to the database
+    pub dry_run: bool,
+}
+
+/// Report of a library import
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LibraryImportReport {
+    /// names of the books that were (or would be for a dry run) created
+    pub created: Vec<String>,
+    /// names of the imported books that conflict with an existing book or with another imported
+    /// book, these books are not imported
+    pub conflicts: Vec<String>,
+    /// names of the existing books removed by [`ImportMode::Replace`]
+    pub removed: Vec<String>,
+    /// books whose file is on this machine but can't be a book of the library, e.g. a file that
+    /// isn't a PDF, these books are not imported
+    pub rejected: Vec<(String, CreateBookError)>,
+}
+
+fn get_all_books(conn: &Connection) -> Result<Vec<Book>, ExportError> {
+    match db::get_books(conn) {
+        Ok(books) => Ok(books),
+        Err(_) => Err(ExportError::CouldNotReadBooks),
+    }
+}
+
+/// Converts a book to its JSON representation
+pub fn book_to_json(bk: &Book) -> Value {
+    let meta = &bk.metadata;
+    json!({
+        "name": bk.name,
+        "path": bk.path,
+        "section": bk.section,
+        "favourite": bk.favourite,
+        "metadata": {
+            "title": meta.title,
+            "authors": meta.authors,
+            "year": meta.year,
+            "publisher": meta.publisher,
+            "series": meta.series,
+            "doi": meta.doi,
+            "isbn": meta.isbn,
+            "tags": meta.tags,
+        },
+        "missing": bk.missing,
+        "kind": bk.kind.as_str(),
+        "added_at": bk.added_at,
+        "updated_at": bk.updated_at,
+        "last_opened_at": bk.last_opened_at,
+    })
+}
+
+fn json_string(value: &Value, key: &str) -> Option<String> {
+    value.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+fn json_list(value: &Value, key: &str) -> Vec<String> {
+    match value.get(key).and_then(Value::as_array) {
+        Some(list) => list
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Reads a book from its JSON representation, returns None if the name or the path is missing
+pub fn book_from_json(value: &Value) -> Option<Book> {
+    let name = json_string(value, "name")?;
+    let path = json_string(value, "path")?;
+    let section = json_string(value, "section");
+    let favourite = value
+        .get("favourite")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let mut bk = Book::init(name, path, section, favourite);
+    bk.missing = value
+        .get("missing")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    bk.kind = json_string(value, "kind")
+        .and_then(|kind| DocumentKind::from_name(&kind))
+        .unwrap_or_default();
+    bk.added_at = value.get("added_at").and_then(Value::as_i64);
+    bk.updated_at = value.get("updated_at").and_then(Value::as_i64);
+    bk.last_opened_at = value.get("last_opened_at").and_then(Value::as_i64);
+    if let Some(meta) = value.get("metadata") {
+        bk.metadata = Metadata {
+            title: json_string(meta, "title"),
+            authors: json_list(meta, "authors"),
+            year: meta
+                .get("year")
+                .and_then(Value::as_i64)
+                .map(|year| year as i32),
+            publisher: json_string(meta, "publisher"),
+            series: json_string(meta, "series"),
+            doi: json_string(meta, "doi"),
+            isbn: json_string(meta, "isbn"),
+            tags: json_list(meta, "tags"),
+        };
+    }
+    Some(bk)
+}
+
+/// Writes every book of the database to the writer as JSON
+pub fn export_json<W: Write>(conn: &Connection, writer: W) -> Result<(), ExportError> {
+    let books = get_all_books(conn)?;
+    let doc = json!({
+        "version": FORMAT_VERSION,
+        "books": books.iter().map(book_to_json).collect::<Vec<Value>>(),
+    });
+    match serde_json::to_writer_pretty(writer, &doc) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ExportError::CouldNotWrite),
+    }
+}
+
+fn optional(value: &Option<String>) -> String {
+    value.clone().unwrap_or_default()
+}
+
+/// Writes every book of the database to the writer as CSV
+pub fn export_csv<W: Write>(conn: &Connection, writer: W) -> Result<(), ExportError> {
+    let books = get_all_books(conn)?;
+    let mut wtr = csv::Writer::from_writer(writer);
+    if wtr.write_record(CSV_HEADER).is_err() {
+        return Err(ExportError::CouldNotWrite);
+    }
+    for bk in books {
+        let meta = &bk.metadata;
+        let record = [
+            bk.name.clone(),
+            bk.path.clone(),
+            optional(&bk.section),
+            bk.favourite.to_string(),
+            optional(&meta.title),
+            meta.authors.join(CSV_LIST_SEPARATOR),
+            meta.year.map(|year| year.to_string()).unwrap_or_default(),
+            optional(&meta.publisher),
+            optional(&meta.series),
+            optional(&meta.doi),
+            optional(&meta.isbn),
+            meta.tags.join(CSV_LIST_SEPARATOR),
+            bk.kind.as_str().to_string(),
+        ];
+        if wtr.write_record(&record).is_err() {
+            return Err(ExportError::CouldNotWrite);
+        }
+    }
+    match wtr.flush() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ExportError::CouldNotWrite),
+    }
+}
+
+/// Reads a library written by [`export_json`] and adds its books to the database
+pub fn import_json<R: Read>(
+    conn: &Connection,
+    reader: R,
+    options: &LibraryImportOptions,
+) -> Result<LibraryImportReport, ImportError> {
+    let doc: Value = match serde_json::from_reader(reader) {
+        Ok(doc) => doc,
+        Err(err) => {
+            return Err(ImportError::InvalidFormat {
+                line: err.line(),
+                message: err.to_string(),
+            })
+        }
+    };
+    let list = match doc.get("books").and_then(Value::as_array) {
+        Some(list) => list,
+        None => {
+            return Err(ImportError::InvalidFormat {
+                line: 1,
+                message: "missing the list of books".to_string(),
+            })
+        }
+    };
+    let mut books = Vec::new();
+    for (i, value) in list.iter().enumerate() {
+        match book_from_json(value) {
+            Some(bk) => books.push(bk),
+            None => {
+                return Err(ImportError::InvalidFormat {
+                    line: 1,
+                    message: format!("the book #{} has no name or path", i + 1),
+                })
+            }
+        }
+    }
+    import_books(conn, books, options)
+}
+
+fn csv_list(value: &str) -> Vec<String> {
+    value
+        .split(CSV_LIST_SEPARATOR.trim())
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn csv_optional(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Reads a library written by [`export_csv`] and adds its books to the database
+pub fn import_csv<R: Read>(
+    conn: &Connection,
+    reader: R,
+    options: &LibraryImportOptions,
+) -> Result<LibraryImportReport, ImportError> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let header = match rdr.headers() {
+        Ok(header) => header.clone(),
+        Err(_) => return Err(ImportError::CouldNotReadFile),
+    };
+    let column = |name: &str| header.iter().position(|col| col == name);
+    let (name_col, path_col) = match (column("name"), column("path")) {
+        (Some(name_col), Some(path_col)) => (name_col, path_col),
+        _ => {
+            return Err(ImportError::InvalidFormat {
+                line: 1,
+                message: "the header must have the 'name' and 'path' columns".to_string(),
+            })
+        }
+    };
+    let mut books = Vec::new();
+    for (i, record_res) in rdr.records().enumerate() {
+        let line = i + 2;
+        let record = match record_res {
+            Ok(record) => record,
+            Err(err) => {
+                return Err(ImportError::InvalidFormat {
+                    line,
+                    message: err.to_string(),
+                })
+            }
+        };
+        let get = |name: &str| -> &str {
+            column(name)
+                .and_then(|col| record.get(col))
+                .unwrap_or_default()
+        };
+        let name = record.get(name_col).unwrap_or_default();
+        let path = record.get(path_col).unwrap_or_default();
+        if name.is_empty() || path.is_empty() {
+            return Err(ImportError::InvalidFormat {
+                line,
+                message: "the book has no name or path".to_string(),
+            });
+        }
+        let mut bk = Book::init(
+            name.to_string(),
+            path.to_string(),
+            csv_optional(get("section")),
+            get("favourite") == "true",
+        );
+        bk.metadata = Metadata {
+            title: csv_optional(get("title")),
+            authors: csv_list(get("authors")),
+            year: get("year").parse().ok(),
+            publisher: csv_optional(get("publisher")),
+            series: csv_optional(get("series")),
+            doi: csv_optional(get("doi")),
+            isbn: csv_optional(get("isbn")),
+            tags: csv_list(get("tags")),
+        };
+        bk.kind = DocumentKind::from_name(get("kind")).unwrap_or_default();
+        books.push(bk);
+    }
+    import_books(conn, books, options)
+}
+
+/// Plans the import and writes it in a single transaction unless it's a dry run.
+///
+/// A library is usually imported on a different machine: the books whose file isn't found are
+/// imported as missing (see [`crate::health`]) if their kind is allowed, the others go through the
+/// same checks as in [`crate::create_book`] and their kind is detected from the file.
+fn import_books(
+    conn: &Connection,
+    books: Vec<Book>,
+    options: &LibraryImportOptions,
+) -> Result<LibraryImportReport, ImportError> {
+    let existing = match db::get_books(conn) {
+        Ok(existing) => existing,
+        Err(_) => return Err(ImportError::DatabaseError),
+    };
+    let mut report = LibraryImportReport::default();
+    let mut used_names: HashSet<String> = HashSet::new();
+    if options.mode == ImportMode::Merge {
+        used_names.extend(existing.iter().map(|bk| bk.name.clone()));
+    } else {
+        report.removed = existing.iter().map(|bk| bk.name.clone()).collect();
+    }
+
+    let mut to_create = Vec::new();
+    for mut bk in books {
+        if used_names.contains(&bk.name) {
+            report.conflicts.push(bk.name);
+            continue;
+        }
+        if bk.file_path().exists() {
+            match crate::validate_book(conn, &bk) {
+                Ok(kind) => bk.kind = kind,
+                Err(err) => {
+                    report.rejected.push((bk.name, err));
+                    continue;
+                }
+            }
+        } else if let Err(err) = crate::check_kind(conn, Some(bk.kind)) {
+            report.rejected.push((bk.name, err));
+            continue;
+        } else {
+            bk.missing = true;
+        }
+        used_names.insert(bk.name.clone());
+        report.created.push(bk.name.clone());
+        to_create.push(bk);
+    }
+    if options.dry_run {
+        return Ok(report);
+    }
+
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(ImportError::DatabaseError),
+    };
+    for name in &report.removed {
+        if crate::remove_book(conn, name).is_err() {
+            return Err(ImportError::DatabaseError);
+        }
+    }
+    for bk in &to_create {
+        let created = match bk.missing {
+            true => crate::create_missing_book(conn, bk),
+            false => crate::create_book(conn, bk),
+        };
+        if let Err(error) = created {
+            return Err(ImportError::BookNotCreated {
+                name: bk.name.clone(),
+                error,
+            });
+        }
+    }
+    match savepoint.release() {
+        Ok(_) => Ok(report),
+        Err(_) => Err(ImportError::DatabaseError),
+    }
+}
@@ -0,0 +1,185 @@
+//! A module for recognizing the same PDF registered several times.
+//!
+//! The content of a book's file is identified by its BLAKE3 hash. Hashes are computed lazily, the
+//! first time they are needed, and are stored with the size and modification time of the file.
+//! A stored hash is computed again when the size or the modification time of the file changed.
+//!
+//! Files are only hashed when another file has the same size, so looking for duplicates in a
+//! large library mostly reads file metadata.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::hash;
+//! # let connection = book_lib::db::setup();
+//!
+//! if let Ok(groups) = hash::find_duplicates(&connection) {
+//!     for group in groups {
+//!         let names: Vec<String> = group.books.into_iter().map(|bk| bk.name).collect();
+//!         println!("{} are the same file", names.join(", "));
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::book::Book;
+use crate::db;
+use crate::errors::HashError;
+use crate::help;
+
+/// What [`crate::create_book_with_policy`] does when the file of the new book is already
+/// registered as another book
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DuplicatePolicy {
+    /// create the book without looking for duplicates
+    #[default]
+    Allow,
+    /// create the book and return the names of the books with the same content
+    Warn,
+    /// don't create the book, see [`crate::errors::CreateBookError::SameContentAs`]
+    Refuse,
+}
+
+/// Books whose files have the same content
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DuplicateGroup {
+    /// BLAKE3 hash of the content, as hex
+    pub hash: String,
+    /// size of the files in bytes
+    pub size: i64,
+    pub books: Vec<Book>,
+}
+
+/// Returns the BLAKE3 hash of the content of a file, as hex
+pub fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Returns the hash of the file of a book, computing it if the stored one is missing or stale
+fn refreshed_hash(conn: &Connection, bk: &Book) -> Result<Option<String>, HashError> {
+    let stored = match db::get_content_hash(conn, &bk.name) {
+        Ok(stored) => stored,
+        Err(_) => return Err(HashError::DatabaseError),
+    };
+    let size = db::file_size(&bk.path);
+    let mtime = db::file_mtime(&bk.path);
+    if size.is_none() {
+        return Ok(None);
+    }
+    if stored.hash.is_some() && stored.size == size && stored.mtime == mtime {
+        return Ok(stored.hash);
+    }
+    let hash = match hash_file(bk.file_path()) {
+        Ok(hash) => hash,
+        Err(_) => return Ok(None),
+    };
+    let fresh = db::StoredHash {
+        hash: Some(hash),
+        size,
+        mtime,
+    };
+    if db::set_content_hash(conn, &bk.name, &fresh).is_err() {
+        return Err(HashError::DatabaseError);
+    }
+    Ok(fresh.hash)
+}
+
+/// Returns the content hash of the file of a book.
+///
+/// The hash is computed and stored if the book doesn't have one yet or if its file changed
+/// since.
+pub fn content_hash(conn: &Connection, name: &String) -> Result<String, HashError> {
+    let bk = match db::get_book(conn, name) {
+        Ok(bk) => bk,
+        Err(_) => return Err(HashError::BookDoesNotExist),
+    };
+    match refreshed_hash(conn, &bk)? {
+        Some(hash) => Ok(hash),
+        None => Err(HashError::CouldNotReadFile),
+    }
+}
+
+fn get_all_books(conn: &Connection) -> Result<Vec<Book>, HashError> {
+    match db::get_books(conn) {
+        Ok(books) => Ok(books),
+        Err(_) => Err(HashError::CouldNotReadBooks),
+    }
+}
+
+/// Groups the given books by the hash of their file, only hashing the files whose size is shared
+/// with another file. Books whose file can't be read are left out.
+fn group_by_content(
+    conn: &Connection,
+    books: Vec<Book>,
+) -> Result<HashMap<String, (i64, Vec<Book>)>, HashError> {
+    let mut by_size: HashMap<i64, Vec<Book>> = HashMap::new();
+    for bk in books {
+        if let Some(size) = db::file_size(&bk.path) {
+            by_size.entry(size).or_default().push(bk);
+        }
+    }
+    let mut by_hash: HashMap<String, (i64, Vec<Book>)> = HashMap::new();
+    for (size, books) in by_size.into_iter().filter(|(_, books)| books.len() > 1) {
+        for bk in books {
+            if let Some(hash) = refreshed_hash(conn, &bk)? {
+                by_hash.entry(hash).or_insert((size, Vec::new())).1.push(bk);
+            }
+        }
+    }
+    Ok(by_hash)
+}
+
+/// Returns the groups of books whose files have the same content.
+///
+/// Groups are sorted by hash and the books of a group by name.
+pub fn find_duplicates(conn: &Connection) -> Result<Vec<DuplicateGroup>, HashError> {
+    let books = get_all_books(conn)?;
+    let mut groups: Vec<DuplicateGroup> = group_by_content(conn, books)?
+        .into_iter()
+        .filter(|(_, (_, books))| books.len() > 1)
+        .map(|(hash, (size, mut books))| {
+            books.sort_by(|bk1, bk2| bk1.name.cmp(&bk2.name));
+            DuplicateGroup { hash, size, books }
+        })
+        .collect();
+    groups.sort_by(|group1, group2| group1.hash.cmp(&group2.hash));
+    Ok(groups)
+}
+
+/// Returns the names of the books whose file has the same content as the file at `path`
+pub fn find_same_content<P: AsRef<Path>>(
+    conn: &Connection,
+    path: P,
+) -> Result<Vec<String>, HashError> {
+    let path = help::path_to_string(path);
+    let size = match db::file_size(&path) {
+        Some(size) => size,
+        None => return Err(HashError::CouldNotReadFile),
+    };
+    let mut hash = None;
+    let mut names = Vec::new();
+    for bk in get_all_books(conn)? {
+        if db::file_size(&bk.path) != Some(size) {
+            continue;
+        }
+        if hash.is_none() {
+            match hash_file(&path) {
+                Ok(file_hash) => hash = Some(file_hash),
+                Err(_) => return Err(HashError::CouldNotReadFile),
+            }
+        }
+        if refreshed_hash(conn, &bk)? == hash {
+            names.push(bk.name);
+        }
+    }
+    Ok(names)
+}
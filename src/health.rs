@@ -0,0 +1,277 @@
+//! A module for finding the books whose file disappeared and pointing them to their new
+//! location.
+//!
+//! [`find_missing_books`] checks the path of every book. [`relocate_missing_books`] then looks
+//! for the missing files under some search roots: a file is a candidate for a book if it has the
+//! same file name, and a stronger one if it also has the size recorded for the book. A file with
+//! the content hash recorded for the book (see [`crate::hash`]) is the strongest candidate, even
+//! if it was renamed, as long as the hash was computed before the file disappeared. Only strong
+//! and unambiguous candidates are applied automatically, the others are returned as suggestions
+//! that can be applied with [`crate::update_path`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::health::{self, RelocateMode};
+//! # let connection = book_lib::db::setup();
+//!
+//! let roots = vec!["/home/me/Documents".to_string()];
+//! if let Ok(report) = health::relocate_missing_books(&connection, &roots, RelocateMode::Automatic) {
+//!     for moved in report.relocated {
+//!         println!("{} is now at {}", moved.name, moved.new_path);
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use walkdir::WalkDir;
+
+use crate::book::Book;
+use crate::db;
+use crate::errors::HealthError;
+use crate::hash;
+use crate::help;
+use crate::journal::JournalOp;
+use crate::watch::MovedBook;
+
+/// How well a file matches a missing book, from the weakest to the strongest match
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchKind {
+    /// the file has the same file name
+    FileName,
+    /// the file has the same file name and the recorded size
+    FileNameAndSize,
+    /// the file has the recorded content hash, whatever its name
+    Content,
+}
+
+/// A file that may be the moved file of a missing book
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Candidate {
+    pub path: String,
+    pub matched_by: MatchKind,
+}
+
+/// Candidates found for a missing book that weren't applied
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Suggestion {
+    /// name of the missing book
+    pub name: String,
+    /// candidates from the strongest to the weakest match
+    pub candidates: Vec<Candidate>,
+}
+
+/// What [`relocate_missing_books`] does with the candidates it finds
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RelocateMode {
+    /// update the path of a book when it has a single strongest candidate that matches more
+    /// than the file name, suggest the others
+    #[default]
+    Automatic,
+    /// never update a path, only suggest
+    SuggestOnly,
+}
+
+/// Report of [`relocate_missing_books`]
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelocationReport {
+    /// books whose path was updated
+    pub relocated: Vec<MovedBook>,
+    /// books with candidates that weren't applied
+    pub suggestions: Vec<Suggestion>,
+    /// names of the books without any candidate
+    pub not_found: Vec<String>,
+}
+
+fn get_all_books(conn: &Connection) -> Result<Vec<Book>, HealthError> {
+    match db::get_books(conn) {
+        Ok(books) => Ok(books),
+        Err(_) => Err(HealthError::CouldNotReadBooks),
+    }
+}
+
+/// Checks the file of every book and returns the books whose file doesn't exist.
+///
+/// The missing flag of every book is updated, and the size of the existing files is recorded
+/// for the books that don't have one yet.
+pub fn find_missing_books(conn: &Connection) -> Result<Vec<Book>, HealthError> {
+    let mut missing = Vec::new();
+    for mut bk in get_all_books(conn)? {
+        let exists = bk.file_path().is_file();
+        if exists == bk.missing && db::set_missing(conn, &bk.name, !exists).is_err() {
+            return Err(HealthError::DatabaseError);
+        }
+        if exists {
+            if let Ok(None) = db::get_file_size(conn, &bk.name) {
+                let _ = db::set_file_size(conn, &bk.name, db::file_size(&bk.path));
+            }
+        } else {
+            bk.missing = true;
+            missing.push(bk);
+        }
+    }
+    Ok(missing)
+}
+
+/// Files found under the search roots by file name and by size
+struct FileIndex {
+    by_name: HashMap<String, Vec<PathBuf>>,
+    by_size: HashMap<i64, Vec<PathBuf>>,
+}
+
+impl FileIndex {
+    fn build<P: AsRef<Path>>(roots: &[P]) -> FileIndex {
+        let mut by_name: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        let mut by_size: HashMap<i64, Vec<PathBuf>> = HashMap::new();
+        for root in roots {
+            for entry in WalkDir::new(root).into_iter().flatten() {
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let name = help::path_to_string(entry.file_name());
+                let path = entry.path().to_path_buf();
+                if let Ok(meta) = entry.metadata() {
+                    by_size
+                        .entry(meta.len() as i64)
+                        .or_default()
+                        .push(path.clone());
+                }
+                by_name.entry(name).or_default().push(path);
+            }
+        }
+        FileIndex { by_name, by_size }
+    }
+
+    fn candidates(&self, conn: &Connection, bk: &Book) -> Vec<Candidate> {
+        let file_name = bk.file_path().file_name().map(help::path_to_string);
+        // the file is gone, so the stored hash is used as is
+        let stored = match db::get_content_hash(conn, &bk.name) {
+            Ok(stored) => stored,
+            Err(_) => return Vec::new(),
+        };
+        let same_content = |path: &str| match &stored.hash {
+            Some(hash) => hash::hash_file(help::path_from_string(path)).ok().as_ref() == Some(hash),
+            None => false,
+        };
+        let mut candidates = Vec::new();
+        let same_name = file_name
+            .and_then(|file_name| self.by_name.get(&file_name))
+            .cloned()
+            .unwrap_or_default();
+        for path in &same_name {
+            let path = help::path_to_string(path);
+            let matched_by = match stored.size {
+                Some(size) if db::file_size(&path) == Some(size) => {
+                    if same_content(&path) {
+                        MatchKind::Content
+                    } else {
+                        MatchKind::FileNameAndSize
+                    }
+                }
+                _ => MatchKind::FileName,
+            };
+            candidates.push(Candidate { path, matched_by });
+        }
+        // renamed files can only be recognized by their content
+        if let (Some(size), Some(_)) = (stored.size, &stored.hash) {
+            for path in self.by_size.get(&size).into_iter().flatten() {
+                if same_name.contains(path) {
+                    continue;
+                }
+                let path = help::path_to_string(path);
+                if same_content(&path) {
+                    candidates.push(Candidate {
+                        path,
+                        matched_by: MatchKind::Content,
+                    });
+                }
+            }
+        }
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.matched_by));
+        candidates
+    }
+}
+
+/// Looks for the moved file of a book under the search roots.
+///
+/// Returns the candidates from the strongest to the weakest match, the book doesn't have to be
+/// missing.
+pub fn find_candidates<P: AsRef<Path>>(
+    conn: &Connection,
+    name: &String,
+    roots: &[P],
+) -> Result<Vec<Candidate>, HealthError> {
+    let bk = match db::get_book(conn, name) {
+        Ok(bk) => bk,
+        Err(_) => return Err(HealthError::BookDoesNotExist),
+    };
+    Ok(FileIndex::build(roots).candidates(conn, &bk))
+}
+
+/// Looks for the files of every missing book under the search roots, see the module
+/// documentation.
+///
+/// Every path update is written in a single transaction and recorded in the journal, see
+/// [`crate::journal`].
+pub fn relocate_missing_books<P: AsRef<Path>>(
+    conn: &Connection,
+    roots: &[P],
+    mode: RelocateMode,
+) -> Result<RelocationReport, HealthError> {
+    let missing = find_missing_books(conn)?;
+    let mut report = RelocationReport::default();
+    if missing.is_empty() {
+        return Ok(report);
+    }
+    let index = FileIndex::build(roots);
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(HealthError::DatabaseError),
+    };
+    for bk in missing {
+        let candidates = index.candidates(conn, &bk);
+        let best = match candidates.first() {
+            Some(best) => best,
+            None => {
+                report.not_found.push(bk.name);
+                continue;
+            }
+        };
+        let is_unique = candidates
+            .iter()
+            .filter(|candidate| candidate.matched_by == best.matched_by)
+            .count()
+            == 1;
+        if mode == RelocateMode::Automatic && is_unique && best.matched_by > MatchKind::FileName {
+            let relocate = || -> rusqlite::Result<()> {
+                db::update_path(conn, &bk.name, &best.path)?;
+                let (before, after) = (Some(bk.path.clone()), Some(best.path.clone()));
+                crate::record_change(conn, JournalOp::UpdatePath, &bk.name, before, after)
+            };
+            if relocate().is_err() {
+                return Err(HealthError::DatabaseError);
+            }
+            report.relocated.push(MovedBook {
+                name: bk.name,
+                old_path: bk.path,
+                new_path: best.path.clone(),
+            });
+        } else {
+            report.suggestions.push(Suggestion {
+                name: bk.name,
+                candidates,
+            });
+        }
+    }
+    match savepoint.release() {
+        Ok(_) => Ok(report),
+        Err(_) => Err(HealthError::DatabaseError),
+    }
+}
@@ -1,29 +1,262 @@
 //! Module for simple helping function
+//!
+//! ## Paths
+//! The path of a book is kept as a `String` (see [`book::Book::path`]). A path that isn't valid
+//! Unicode is converted with [`path_to_string`], which keeps the invalid bytes (UTF-16 units on
+//! Windows) as characters of a private use area, and [`path_from_string`] gives the original path
+//! back. The characters of that area found in a valid path are prefixed with `U+10FE00` so they
+//! are told apart from the invalid bytes. Valid paths without them are stored unchanged.
 
 use crate::book;
+use crate::errors::PathError;
 use std::{
-    fs,
+    fs, io,
     path::{Path, PathBuf},
 };
 
+/// First character used for the bytes of a Unix path that aren't valid UTF-8, the byte `b` is
+/// kept as `UNIX_ESCAPE + b`
+const UNIX_ESCAPE: u32 = 0x10FE00;
+
+/// First character used for the unpaired surrogates of a Windows path, the unit `u` is kept as
+/// `WINDOWS_ESCAPE + (u - 0xD800)`
+const WINDOWS_ESCAPE: u32 = 0x10E000;
+
+/// Put before a character of the escape ranges (or before itself) found in a valid path
+const LITERAL: char = '\u{10FE00}';
+
+fn is_escape(c: char) -> bool {
+    let c = c as u32;
+    (UNIX_ESCAPE + 0x80..=UNIX_ESCAPE + 0xFF).contains(&c)
+        || (WINDOWS_ESCAPE..WINDOWS_ESCAPE + 0x800).contains(&c)
+}
+
+/// Characters that are prefixed with [`LITERAL`] when they are part of a valid path
+fn needs_prefix(c: char) -> bool {
+    c == LITERAL || is_escape(c)
+}
+
+/// Pushes a character of a valid path
+fn push_literal(res: &mut String, c: char) {
+    if needs_prefix(c) {
+        res.push(LITERAL);
+    }
+    res.push(c);
+}
+
+/// Converts a path to a string without losing anything, see the module documentation
+///
+/// ```rust
+/// use book_lib::help::{path_from_string, path_to_string};
+/// use std::path::Path;
+///
+/// let path = Path::new("Книги/日本語/Café.pdf");
+/// assert_eq!(path_to_string(path), "Книги/日本語/Café.pdf");
+///
+/// #[cfg(unix)]
+/// {
+///     use std::ffi::OsStr;
+///     use std::os::unix::ffi::OsStrExt;
+///
+///     let latin1 = Path::new(OsStr::from_bytes(b"/books/caf\xe9\xe9.pdf"));
+///     let stored = path_to_string(latin1);
+///     assert!(stored.starts_with("/books/caf"));
+///     assert_eq!(path_from_string(&stored), latin1);
+/// }
+///
+/// // valid paths with the characters used for the invalid bytes are kept too
+/// let private = Path::new("/books/\u{10FEE9}\u{10FE00}\u{10E123}.pdf");
+/// let stored = path_to_string(private);
+/// assert_ne!(stored, "/books/\u{10FEE9}\u{10FE00}\u{10E123}.pdf");
+/// assert_eq!(path_from_string(&stored), private);
+/// ```
+pub fn path_to_string<P: AsRef<Path>>(path: P) -> String {
+    let os_str = path.as_ref().as_os_str();
+    if let Some(valid) = os_str.to_str() {
+        if !valid.chars().any(needs_prefix) {
+            return valid.to_string();
+        }
+        let mut res = String::with_capacity(valid.len());
+        for c in valid.chars() {
+            push_literal(&mut res, c);
+        }
+        return res;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut res = String::new();
+        for chunk in os_str.as_bytes().utf8_chunks() {
+            for c in chunk.valid().chars() {
+                push_literal(&mut res, c);
+            }
+            for byte in chunk.invalid() {
+                res.extend(char::from_u32(UNIX_ESCAPE + *byte as u32));
+            }
+        }
+        res
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        let mut res = String::new();
+        for unit in char::decode_utf16(os_str.encode_wide()) {
+            match unit {
+                Ok(c) => push_literal(&mut res, c),
+                Err(err) => {
+                    let surrogate = err.unpaired_surrogate() as u32 - 0xD800;
+                    res.extend(char::from_u32(WINDOWS_ESCAPE + surrogate));
+                }
+            }
+        }
+        res
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        os_str.to_string_lossy().to_string()
+    }
+}
+
+/// Converts a string given by [`path_to_string`] back to the path
+pub fn path_from_string(path: &str) -> PathBuf {
+    if !path.chars().any(needs_prefix) {
+        return PathBuf::from(path);
+    }
+    // characters of the path, with true for the ones that stand for an invalid byte or unit
+    let mut chars = Vec::with_capacity(path.len());
+    let mut iter = path.chars();
+    while let Some(c) = iter.next() {
+        match c {
+            LITERAL => chars.push((iter.next().unwrap_or(LITERAL), false)),
+            c => chars.push((c, is_escape(c))),
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut bytes = Vec::with_capacity(path.len());
+        for (c, escaped) in chars {
+            match (c as u32).checked_sub(UNIX_ESCAPE) {
+                Some(byte @ 0x80..=0xFF) if escaped => bytes.push(byte as u8),
+                _ => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        PathBuf::from(OsString::from_vec(bytes))
+    }
+    #[cfg(windows)]
+    {
+        use std::ffi::OsString;
+        use std::os::windows::ffi::OsStringExt;
+
+        let mut units = Vec::with_capacity(path.len());
+        for (c, escaped) in chars {
+            match (c as u32).checked_sub(WINDOWS_ESCAPE) {
+                Some(unit @ 0..=0x7FF) if escaped => units.push((unit + 0xD800) as u16),
+                _ => units.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+            }
+        }
+        PathBuf::from(OsString::from_wide(&units))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        PathBuf::from(chars.into_iter().map(|(c, _)| c).collect::<String>())
+    }
+}
+
+/// Returns the name of the file at the end of a path, the path itself if it has no file name
+///
+/// The separators of the current platform are used, `\` is only a separator on Windows.
+///
+/// ```rust
+/// use book_lib::help::file_name;
+///
+/// assert_eq!(file_name("path/to/my/file_unique_name.pdf"), "file_unique_name.pdf");
+/// assert_eq!(file_name("Книги/日本語.pdf"), "日本語.pdf");
+/// assert_eq!(file_name("file.pdf"), "file.pdf");
+/// if cfg!(windows) {
+///     assert_eq!(file_name(r"C:\Users\me\Books\file.pdf"), "file.pdf");
+/// } else {
+///     assert_eq!(file_name(r"C:\Users\me\Books\file.pdf"), r"C:\Users\me\Books\file.pdf");
+/// }
+/// ```
+pub fn file_name(path: &str) -> String {
+    match path_from_string(path).file_name() {
+        Some(name) => path_to_string(name),
+        None => path.to_string(),
+    }
+}
+
+/// Returns the absolute path of an existing file, with the symbolic links resolved
+///
+/// On Windows the `\\?\` prefix added by the system is removed when the path doesn't need it.
+///
+/// ```rust
+/// use book_lib::errors::PathError;
+/// use book_lib::help::canonical_path;
+///
+/// let dir = std::env::temp_dir();
+/// assert!(canonical_path(&dir.to_string_lossy()).unwrap().is_absolute());
+/// assert!(matches!(
+///     canonical_path("/this/path/does/not/exist.pdf"),
+///     Err(PathError::DoesNotExist)
+/// ));
+/// ```
+pub fn canonical_path(path: &str) -> Result<PathBuf, PathError> {
+    match fs::canonicalize(path_from_string(path)) {
+        Ok(canonical) => Ok(simplify_verbatim(canonical)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(PathError::DoesNotExist),
+        Err(_) => Err(PathError::CouldNotResolve),
+    }
+}
+
+#[cfg(windows)]
+fn simplify_verbatim(path: PathBuf) -> PathBuf {
+    use std::path::{Component, Prefix};
+
+    let simple = match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::VerbatimDisk(disk) => {
+                let mut simple = PathBuf::from(format!("{}:\\", disk as char));
+                simple.extend(path.components().skip(2));
+                Some(simple)
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    simple.unwrap_or(path)
+}
+
+#[cfg(not(windows))]
+fn simplify_verbatim(path: PathBuf) -> PathBuf {
+    path
+}
+
 /// Takes a path (as &str) to the parameter and returns true if the path supposed to be a pdf and
 /// false otherwise
+///
+/// Only the extension is looked at (case insensitive), see [`crate::pdf::check_pdf`] to check the
+/// content of the file.
 pub fn is_pdf(path: &str) -> bool {
-    let str_path = path;
-    match str_path.split(".").last() {
-        Some(val) => val == "pdf",
+    match Path::new(path).extension() {
+        Some(ext) => ext.eq_ignore_ascii_case("pdf"),
         None => false,
     }
 }
 
-/// Takes path (as &String) to the parameter and returns (true, Some(Path)) if the path is correct (the
+/// Takes path (as &str) to the parameter and returns (true, Some(Path)) if the path is correct (the
 /// file on the path exists) and (false, None) otherwise
-pub fn is_correct_path(path: &String) -> (bool, Option<PathBuf>) {
-    let path = Path::new(path);
-    if !path.exists() {
-        (false, None)
-    } else {
-        (true, Some(fs::canonicalize(path).unwrap()))
+///
+/// The path is canonicalized with [`canonical_path`], a path that can't be resolved is reported as
+/// incorrect, use [`canonical_path`] to know why.
+pub fn is_correct_path(path: &str) -> (bool, Option<PathBuf>) {
+    match canonical_path(path) {
+        Ok(canonical) => (true, Some(canonical)),
+        Err(_) => (false, None),
     }
 }
 
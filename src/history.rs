@@ -0,0 +1,388 @@
+//! A module for the reading history of the library.
+//!
+//! Every time a book is opened a reading session is recorded with the time it was opened at and,
+//! once it's known, how long the book stayed open. [`crate::open::open_book`] records the
+//! sessions of the books it opens, a front-end that opens books by itself reports them with
+//! [`record_open`] and [`record_close`], or with [`record_session`] once the session is over.
+//!
+//! Times are in seconds since the Unix epoch. The history is kept forever unless a retention is
+//! set with [`set_retention`], older sessions are then removed when a new one is recorded, at most
+//! once an hour, or right away with [`prune_history`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::history;
+//! # let connection = book_lib::db::setup();
+//!
+//! if let Ok(id) = history::record_open(&connection, &"book_name".to_string(), None) {
+//!     // ... the user reads the book ...
+//!     let _ = history::record_close(&connection, id, None);
+//! }
+//! for activity in history::recently_opened(&connection, 10).unwrap_or_default() {
+//!     println!("{} opened {} times", activity.book.name, activity.opens);
+//! }
+//! ```
+
+use rusqlite::{params, Connection};
+
+use crate::book::Book;
+use crate::db;
+use crate::errors::HistoryError;
+
+const RETENTION_KEY: &str = "history.retention_days";
+const PRUNED_KEY: &str = "history.pruned_at";
+
+/// Minimal time between two prunings done when a session is recorded
+const PRUNE_INTERVAL: i64 = 60 * 60;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A reading session
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenEvent {
+    pub id: i64,
+    /// name of the book
+    pub book: String,
+    /// when the book was opened, in seconds since the Unix epoch
+    pub opened_at: i64,
+    /// how long the book stayed open in seconds, None while it's open or if it isn't known
+    pub duration: Option<i64>,
+}
+
+/// How much a book was read
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookActivity {
+    pub book: Book,
+    /// number of reading sessions
+    pub opens: i64,
+    /// total known reading time in seconds
+    pub total_time: i64,
+    /// when the book was last opened
+    pub last_opened: i64,
+}
+
+/// Reading time of a day
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DailyReading {
+    /// local date as `YYYY-MM-DD`
+    pub day: String,
+    /// total known reading time in seconds
+    pub seconds: i64,
+    /// number of reading sessions
+    pub opens: i64,
+}
+
+fn book_id(conn: &Connection, name: &String) -> Result<i64, HistoryError> {
+    match db::get_book_id(conn, name) {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(HistoryError::BookDoesNotExist),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+/// Records that a book was opened, now if `opened_at` is None.
+///
+/// Returns the id of the session, to give to [`record_close`].
+pub fn record_open(
+    conn: &Connection,
+    name: &String,
+    opened_at: Option<i64>,
+) -> Result<i64, HistoryError> {
+    let book_id = book_id(conn, name)?;
+    let opened_at = opened_at.unwrap_or_else(db::now);
+    let record = || -> rusqlite::Result<i64> {
+        let savepoint = db::Savepoint::new(conn)?;
+        conn.execute(
+            "INSERT INTO reading_history (book_id, opened_at) VALUES (?, ?)",
+            params![book_id, opened_at],
+        )?;
+        let id = conn.last_insert_rowid();
+        conn.execute(
+            "UPDATE books SET last_opened_at = MAX(COALESCE(last_opened_at, ?1), ?1) WHERE id = ?2",
+            params![opened_at, book_id],
+        )?;
+        let pruned_at = db::get_setting(conn, PRUNED_KEY)?.and_then(|at| at.parse::<i64>().ok());
+        if pruned_at.is_none_or(|at| db::now() - at >= PRUNE_INTERVAL) {
+            prune(conn)?;
+        }
+        savepoint.release()?;
+        Ok(id)
+    };
+    match record() {
+        Ok(id) => Ok(id),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+/// Records that the book of a session was closed, now if `closed_at` is None
+pub fn record_close(
+    conn: &Connection,
+    id: i64,
+    closed_at: Option<i64>,
+) -> Result<OpenEvent, HistoryError> {
+    let mut event = get_event(conn, id)?;
+    let duration = closed_at.unwrap_or_else(db::now) - event.opened_at;
+    if duration < 0 {
+        return Err(HistoryError::InvalidDuration);
+    }
+    if conn
+        .execute(
+            "UPDATE reading_history SET duration = ? WHERE id = ?",
+            params![duration, id],
+        )
+        .is_err()
+    {
+        return Err(HistoryError::DatabaseError);
+    }
+    event.duration = Some(duration);
+    Ok(event)
+}
+
+/// Records a whole reading session, for front-ends that report sessions once they're over
+pub fn record_session(
+    conn: &Connection,
+    name: &String,
+    opened_at: i64,
+    duration: i64,
+) -> Result<i64, HistoryError> {
+    if duration < 0 {
+        return Err(HistoryError::InvalidDuration);
+    }
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(HistoryError::DatabaseError),
+    };
+    let id = record_open(conn, name, Some(opened_at))?;
+    record_close(conn, id, Some(opened_at + duration))?;
+    match savepoint.release() {
+        Ok(_) => Ok(id),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+const EVENT_QUERY: &str = "SELECT reading_history.id, books.name, opened_at, duration
+    FROM reading_history JOIN books ON books.id = reading_history.book_id";
+
+fn event_from_row(row: &rusqlite::Row) -> rusqlite::Result<OpenEvent> {
+    Ok(OpenEvent {
+        id: row.get(0)?,
+        book: row.get(1)?,
+        opened_at: row.get(2)?,
+        duration: row.get(3)?,
+    })
+}
+
+/// Returns a reading session by its id
+pub fn get_event(conn: &Connection, id: i64) -> Result<OpenEvent, HistoryError> {
+    match conn.query_row(
+        &format!("{} WHERE reading_history.id = ?", EVENT_QUERY),
+        params![id],
+        event_from_row,
+    ) {
+        Ok(event) => Ok(event),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(HistoryError::EventDoesNotExist),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+/// Returns the last session of a book that wasn't closed, if any
+pub fn get_open_session(
+    conn: &Connection,
+    name: &String,
+) -> Result<Option<OpenEvent>, HistoryError> {
+    let book_id = book_id(conn, name)?;
+    match conn.query_row(
+        &format!(
+            "{} WHERE book_id = ? AND duration IS NULL ORDER BY opened_at DESC, reading_history.id DESC LIMIT 1",
+            EVENT_QUERY
+        ),
+        params![book_id],
+        event_from_row,
+    ) {
+        Ok(event) => Ok(Some(event)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+/// Returns the reading sessions of a book, the most recent first
+pub fn get_history(conn: &Connection, name: &String) -> Result<Vec<OpenEvent>, HistoryError> {
+    let book_id = book_id(conn, name)?;
+    let query = || -> rusqlite::Result<Vec<OpenEvent>> {
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE book_id = ? ORDER BY opened_at DESC, reading_history.id DESC",
+            EVENT_QUERY
+        ))?;
+        let events = stmt.query_map(params![book_id], event_from_row)?.collect();
+        events
+    };
+    match query() {
+        Ok(events) => Ok(events),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+fn activity(
+    conn: &Connection,
+    order_by: &str,
+    limit: usize,
+) -> Result<Vec<BookActivity>, HistoryError> {
+    let query = || -> rusqlite::Result<Vec<BookActivity>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, COUNT(*), COALESCE(SUM(duration), 0), MAX(opened_at)
+            FROM books JOIN reading_history ON reading_history.book_id = books.id
+            WHERE books.deleted_at IS NULL
+            GROUP BY books.id ORDER BY {}, name LIMIT ?",
+            db::BOOK_COLUMNS,
+            order_by
+        ))?;
+        let columns = stmt.column_count();
+        let rows = stmt
+            .query_map(params![limit as i64], |row| {
+                Ok(BookActivity {
+                    book: db::book_from_row(row)?,
+                    opens: row.get(columns - 3)?,
+                    total_time: row.get(columns - 2)?,
+                    last_opened: row.get(columns - 1)?,
+                })
+            })?
+            .collect();
+        rows
+    };
+    match query() {
+        Ok(rows) => Ok(rows),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+/// Returns the books opened last, the most recent first
+pub fn recently_opened(conn: &Connection, limit: usize) -> Result<Vec<BookActivity>, HistoryError> {
+    activity(conn, "MAX(opened_at) DESC", limit)
+}
+
+/// Returns the books read the longest, then opened the most
+pub fn most_read(conn: &Connection, limit: usize) -> Result<Vec<BookActivity>, HistoryError> {
+    activity(
+        conn,
+        "COALESCE(SUM(duration), 0) DESC, COUNT(*) DESC",
+        limit,
+    )
+}
+
+/// Returns the reading time of each day with a session between `from` and `to` (seconds since the
+/// Unix epoch, `to` excluded), in order.
+///
+/// Days are local dates and a session counts for the day it started.
+pub fn reading_time_per_day(
+    conn: &Connection,
+    from: i64,
+    to: i64,
+) -> Result<Vec<DailyReading>, HistoryError> {
+    let query = || -> rusqlite::Result<Vec<DailyReading>> {
+        let mut stmt = conn.prepare(
+            "SELECT date(opened_at, 'unixepoch', 'localtime') AS day,
+                COALESCE(SUM(duration), 0), COUNT(*)
+            FROM reading_history JOIN books ON books.id = reading_history.book_id
+            WHERE opened_at >= ? AND opened_at < ? AND books.deleted_at IS NULL
+            GROUP BY day ORDER BY day",
+        )?;
+        let days = stmt
+            .query_map(params![from, to], |row| {
+                Ok(DailyReading {
+                    day: row.get(0)?,
+                    seconds: row.get(1)?,
+                    opens: row.get(2)?,
+                })
+            })?
+            .collect();
+        days
+    };
+    match query() {
+        Ok(days) => Ok(days),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+/// Returns the number of days the history is kept for, None if it's kept forever
+pub fn get_retention(conn: &Connection) -> Result<Option<u32>, HistoryError> {
+    match db::get_setting(conn, RETENTION_KEY) {
+        Ok(value) => Ok(value.and_then(|days| days.parse().ok())),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+/// Sets the number of days the history is kept for, None to keep it forever.
+///
+/// The sessions older than the new retention are removed right away.
+pub fn set_retention(conn: &Connection, days: Option<u32>) -> Result<(), HistoryError> {
+    let value = days.map(|days| days.to_string());
+    if db::set_setting(conn, RETENTION_KEY, value.as_deref()).is_err() {
+        return Err(HistoryError::DatabaseError);
+    }
+    prune_history(conn).map(|_| ())
+}
+
+/// Removes the sessions older than the retention, returns how many were removed
+pub fn prune_history(conn: &Connection) -> Result<usize, HistoryError> {
+    match prune(conn) {
+        Ok(removed) => Ok(removed),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+fn prune(conn: &Connection) -> rusqlite::Result<usize> {
+    let now = db::now();
+    db::set_setting(conn, PRUNED_KEY, Some(&now.to_string()))?;
+    let days = match db::get_setting(conn, RETENTION_KEY)?.and_then(|days| days.parse::<u32>().ok())
+    {
+        Some(days) => days,
+        None => return Ok(0),
+    };
+    let oldest = now - days as i64 * SECONDS_PER_DAY;
+    conn.execute(
+        "DELETE FROM reading_history WHERE opened_at < ?",
+        params![oldest],
+    )
+}
+
+/// Removes the history of a book, returns how many sessions were removed.
+///
+/// The book is no longer marked as opened, see [`crate::book::Book::last_opened_at`].
+pub fn clear_book_history(conn: &Connection, name: &String) -> Result<usize, HistoryError> {
+    let book_id = book_id(conn, name)?;
+    let clear = || -> rusqlite::Result<usize> {
+        let savepoint = db::Savepoint::new(conn)?;
+        let removed = conn.execute(
+            "DELETE FROM reading_history WHERE book_id = ?",
+            params![book_id],
+        )?;
+        conn.execute(
+            "UPDATE books SET last_opened_at = NULL WHERE id = ?",
+            params![book_id],
+        )?;
+        savepoint.release()?;
+        Ok(removed)
+    };
+    match clear() {
+        Ok(removed) => Ok(removed),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
+
+/// Removes the whole history, returns how many sessions were removed
+pub fn clear_history(conn: &Connection) -> Result<usize, HistoryError> {
+    let clear = || -> rusqlite::Result<usize> {
+        let savepoint = db::Savepoint::new(conn)?;
+        let removed = conn.execute("DELETE FROM reading_history", [])?;
+        conn.execute("UPDATE books SET last_opened_at = NULL", [])?;
+        savepoint.release()?;
+        Ok(removed)
+    };
+    match clear() {
+        Ok(removed) => Ok(removed),
+        Err(_) => Err(HistoryError::DatabaseError),
+    }
+}
@@ -0,0 +1,348 @@
+//! A module for bulk-creating books from bibliography files.
+//!
+//! Every entry goes through the same validation as [`crate::create_book`], so an imported book
+//! must point to an existing PDF file. The result of an import is an [`ImportReport`] that tells
+//! what happened to each entry of the file.
+
+pub mod bibtex;
+pub mod calibre;
+pub mod ris;
+pub mod zotero;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+
+use crate::book::Book;
+use crate::db;
+use crate::errors::{CreateBookError, ImportError};
+use crate::help;
+
+/// What to do with an entry whose name is already used by another book
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DuplicateStrategy {
+    /// don't import the entry
+    #[default]
+    Skip,
+    /// import the entry under the first free name of the form `name (2)`, `name (3)`, ...
+    Rename,
+}
+
+/// Options shared by all the importers
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportOptions {
+    /// section given to every imported book, None to leave books without a section
+    pub section: Option<String>,
+    /// name books after their title instead of their citation key
+    pub name_from_title: bool,
+    /// what to do with entries whose name is already in use
+    pub duplicates: DuplicateStrategy,
+    /// validate the entries and build the report without writing anything to the database
+    pub dry_run: bool,
+}
+
+/// Why an entry couldn't be imported
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImportFailure {
+    /// the entry has no file attached to it
+    NoFile,
+    /// the entry has neither a key nor a title to name the book after
+    NoName,
+    /// the book was rejected by [`crate::create_book`]
+    Create(CreateBookError),
+    /// the book or the entry it comes from couldn't be written, nothing was written
+    DatabaseError,
+}
+
+impl std::fmt::Display for ImportFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportFailure::NoFile => write!(f, "The entry has no file attached!"),
+            ImportFailure::NoName => write!(f, "The entry has neither a key nor a title!"),
+            ImportFailure::Create(err) => write!(f, "{}", err),
+            ImportFailure::DatabaseError => write!(f, "Unexpected database error!"),
+        }
+    }
+}
+
+/// What happened to a single entry of an imported file
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImportOutcome {
+    /// the book was created, or would be for a dry run
+    Created,
+    /// a book with the same name already exists, nothing was written
+    Skipped,
+    /// the entry was imported by a previous run as the book with the given name
+    AlreadyImported(String),
+    /// the entry was imported by a previous run as the book with the given name, which is now in
+    /// the trash, nothing was written
+    InTrash(String),
+    /// the book couldn't be created
+    Failed(ImportFailure),
+}
+
+/// A report line for a single entry of an imported file
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportEntry {
+    /// citation key of the entry, or its position in the file if it has none
+    pub key: String,
+    /// name of the book, empty if it couldn't be determined
+    pub name: String,
+    /// path to the file of the book if the entry has one
+    pub path: Option<String>,
+    pub outcome: ImportOutcome,
+}
+
+/// Per-entry report of an import
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportReport {
+    pub entries: Vec<ImportEntry>,
+    /// true if the import was a dry run and nothing was written
+    pub dry_run: bool,
+}
+
+impl ImportReport {
+    /// Entries that were created as books
+    pub fn created(&self) -> impl Iterator<Item = &ImportEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.outcome, ImportOutcome::Created))
+    }
+
+    /// Entries that were skipped because their name is already in use
+    pub fn skipped(&self) -> impl Iterator<Item = &ImportEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.outcome, ImportOutcome::Skipped))
+    }
+
+    /// Entries that were imported by a previous run
+    pub fn already_imported(&self) -> impl Iterator<Item = &ImportEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.outcome, ImportOutcome::AlreadyImported(_)))
+    }
+
+    /// Entries whose book was moved to the trash since a previous run imported it
+    pub fn in_trash(&self) -> impl Iterator<Item = &ImportEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.outcome, ImportOutcome::InTrash(_)))
+    }
+
+    /// Entries that couldn't be imported
+    pub fn failed(&self) -> impl Iterator<Item = &ImportEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.outcome, ImportOutcome::Failed(_)))
+    }
+}
+
+/// Reads a BibTeX file and creates a book for each entry that has a PDF attached in its `file`
+/// field.
+///
+/// Relative file paths are resolved against the directory of the BibTeX file.
+pub fn import_bibtex<P: AsRef<Path>>(
+    conn: &Connection,
+    path: P,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    let path = path.as_ref();
+    let content = read_file(path)?;
+    let entries = bibtex::parse(&content)?;
+    let base_dir = base_dir(path);
+    let books = entries
+        .iter()
+        .map(|entry| (entry.key.clone(), entry.to_book(&base_dir, options)))
+        .collect();
+    Ok(import_books(conn, books, None, options))
+}
+
+/// Reads a RIS file and creates a book for each record that has a PDF attached in its `L1`
+/// field.
+///
+/// Relative file paths are resolved against the directory of the RIS file.
+pub fn import_ris<P: AsRef<Path>>(
+    conn: &Connection,
+    path: P,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    let path = path.as_ref();
+    let content = read_file(path)?;
+    let records = ris::parse(&content)?;
+    let base_dir = base_dir(path);
+    let books = records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| (record.key(i), record.to_book(&base_dir, options)))
+        .collect();
+    Ok(import_books(conn, books, None, options))
+}
+
+fn read_file(path: &Path) -> Result<String, ImportError> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(_) => Err(ImportError::CouldNotReadFile),
+    }
+}
+
+fn base_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::new(),
+    }
+}
+
+/// Resolves a path found in a bibliography file against the directory of that file.
+pub(crate) fn resolve_path(base_dir: &Path, path: &str) -> String {
+    let file_path = Path::new(path);
+    if file_path.is_absolute() {
+        path.to_string()
+    } else {
+        help::path_to_string(base_dir.join(file_path))
+    }
+}
+
+/// Builds the name of a book from the key and the title of an entry according to the options.
+pub(crate) fn book_name(
+    key: Option<&str>,
+    title: Option<&str>,
+    options: &ImportOptions,
+) -> Option<String> {
+    let (first, second) = if options.name_from_title {
+        (title, key)
+    } else {
+        (key, title)
+    };
+    first
+        .or(second)
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Returns the first name of the form `name`, `name (2)`, `name (3)`, ... that is neither used
+/// by a book in the database nor contained in `taken`.
+pub(crate) fn unique_name(conn: &Connection, name: &str, taken: &HashSet<String>) -> String {
+    let is_free =
+        |candidate: &String| !taken.contains(candidate) && db::get_book(conn, candidate).is_err();
+    let mut candidate = name.to_string();
+    let mut i = 2;
+    while !is_free(&candidate) {
+        candidate = format!("{} ({})", name, i);
+        i += 1;
+    }
+    candidate
+}
+
+/// Creates the book of an entry and remembers the entry it comes from, in a single transaction
+/// so that a book is never created without its source.
+fn create_entry(
+    conn: &Connection,
+    bk: &Book,
+    source: Option<&str>,
+    key: &str,
+) -> Result<(), ImportFailure> {
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(ImportFailure::DatabaseError),
+    };
+    crate::create_book(conn, bk).map_err(ImportFailure::Create)?;
+    if let Some(src) = source {
+        if db::add_book_source(conn, &bk.name, src, key).is_err() {
+            return Err(ImportFailure::DatabaseError);
+        }
+    }
+    match savepoint.release() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ImportFailure::DatabaseError),
+    }
+}
+
+/// Creates a book for each entry that was mapped successfully, according to the options.
+///
+/// With a `source`, the key of each created entry is remembered as coming from that source and
+/// the entries imported by a previous run are reported as [`ImportOutcome::AlreadyImported`], or
+/// as [`ImportOutcome::InTrash`] when their book was removed since. They are imported again once
+/// the trash is emptied.
+pub(crate) fn import_books(
+    conn: &Connection,
+    books: Vec<(String, Result<Book, ImportFailure>)>,
+    source: Option<&str>,
+    options: &ImportOptions,
+) -> ImportReport {
+    let mut report = ImportReport {
+        entries: Vec::new(),
+        dry_run: options.dry_run,
+    };
+    // names planned during a dry run, they aren't in the database
+    let mut taken: HashSet<String> = HashSet::new();
+    for (key, bk_res) in books {
+        if let Some((name, trashed)) =
+            source.and_then(|src| db::get_book_by_source(conn, src, &key))
+        {
+            let outcome = match trashed {
+                true => ImportOutcome::InTrash(name.clone()),
+                false => ImportOutcome::AlreadyImported(name.clone()),
+            };
+            report.entries.push(ImportEntry {
+                key,
+                path: bk_res.ok().map(|bk| bk.path),
+                name,
+                outcome,
+            });
+            continue;
+        }
+        let entry = match bk_res {
+            Ok(mut bk) => {
+                if options.duplicates == DuplicateStrategy::Rename {
+                    bk.name = unique_name(conn, &bk.name, &taken);
+                }
+                let res = if options.dry_run {
+                    crate::validate_book(conn, &bk)
+                        .and_then(|_| {
+                            if taken.contains(&bk.name) || db::get_book(conn, &bk.name).is_ok() {
+                                Err(CreateBookError::BookNameAlreadyUsed)
+                            } else {
+                                Ok(())
+                            }
+                        })
+                        .map_err(ImportFailure::Create)
+                } else {
+                    create_entry(conn, &bk, source, &key)
+                };
+                let outcome = match res {
+                    Ok(_) => {
+                        taken.insert(bk.name.clone());
+                        ImportOutcome::Created
+                    }
+                    Err(ImportFailure::Create(CreateBookError::BookNameAlreadyUsed)) => {
+                        ImportOutcome::Skipped
+                    }
+                    Err(failure) => ImportOutcome::Failed(failure),
+                };
+                ImportEntry {
+                    key,
+                    name: bk.name,
+                    path: Some(bk.path),
+                    outcome,
+                }
+            }
+            Err(failure) => ImportEntry {
+                key,
+                name: String::new(),
+                path: None,
+                outcome: ImportOutcome::Failed(failure),
+            },
+        };
+        report.entries.push(entry);
+    }
+    report
+}
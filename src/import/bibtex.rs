@@ -0,0 +1,423 @@
+//! A minimal BibTeX parser.
+//!
+//! The parser understands regular entries, `@string` macros with `#` concatenation and skips
+//! `@comment` and `@preamble` blocks. Field names are lowercased and field values are returned
+//! without the enclosing braces or quotes.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{book_name, resolve_path, ImportFailure, ImportOptions};
+use crate::book::{Book, Metadata};
+use crate::errors::ImportError;
+use crate::help;
+
+/// A single entry of a BibTeX file
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// entry type in lowercase, e.g. `article` or `book`
+    pub kind: String,
+    /// citation key
+    pub key: String,
+    /// fields in the order they appear in the entry
+    pub fields: Vec<(String, String)>,
+}
+
+impl Entry {
+    /// Returns the value of a field by its lowercase name
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the paths listed in the `file` field.
+    ///
+    /// Both the JabRef format (`description:path:type` separated by `;`) and plain lists of
+    /// paths separated by `;` are supported.
+    pub fn files(&self) -> Vec<String> {
+        match self.field("file") {
+            Some(value) => split_unescaped(value, ';')
+                .iter()
+                .filter_map(|file| parse_file(file))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Maps the entry onto a book, the first PDF of the `file` field becomes the book's path.
+    pub(crate) fn to_book(
+        &self,
+        base_dir: &Path,
+        options: &ImportOptions,
+    ) -> Result<Book, ImportFailure> {
+        let title = self.field("title").map(clean_value);
+        let name = match book_name(Some(&self.key), title.as_deref(), options) {
+            Some(name) => name,
+            None => return Err(ImportFailure::NoName),
+        };
+        let files = self.files();
+        let file = match files
+            .iter()
+            .find(|file| help::is_pdf(file))
+            .or(files.first())
+        {
+            Some(file) => resolve_path(base_dir, file),
+            None => return Err(ImportFailure::NoFile),
+        };
+        let publisher = self
+            .field("publisher")
+            .or(self.field("journal"))
+            .or(self.field("booktitle"));
+        let mut bk = Book::init(name, file, options.section.clone(), false);
+        bk.metadata = Metadata {
+            title,
+            authors: self
+                .field("author")
+                .map(|authors| {
+                    clean_value(authors)
+                        .split(" and ")
+                        .map(|author| author.trim().to_string())
+                        .filter(|author| !author.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            year: self.field("year").and_then(parse_year),
+            publisher: publisher.map(clean_value),
+            series: self.field("series").map(clean_value),
+            doi: self.field("doi").map(clean_value),
+            isbn: self.field("isbn").or(self.field("issn")).map(clean_value),
+            tags: self
+                .field("keywords")
+                .map(|keywords| {
+                    clean_value(keywords)
+                        .split([',', ';'])
+                        .map(|tag| tag.trim().to_string())
+                        .filter(|tag| !tag.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        Ok(bk)
+    }
+}
+
+/// Takes the first four consecutive digits of a value as a year
+pub(crate) fn parse_year(value: &str) -> Option<i32> {
+    let digits: String = value
+        .chars()
+        .skip_while(|ch| !ch.is_ascii_digit())
+        .take_while(|ch| ch.is_ascii_digit())
+        .collect();
+    if digits.len() == 4 {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Removes the LaTeX grouping braces and the common escapes from a value and collapses the
+/// whitespace.
+fn clean_value(value: &str) -> String {
+    let mut res = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' | '}' => {}
+            '\\' => match chars.peek() {
+                Some(&next) if "&%_$#{}".contains(next) => {
+                    res.push(next);
+                    chars.next();
+                }
+                _ => res.push(ch),
+            },
+            _ => res.push(ch),
+        }
+    }
+    res.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Splits a value on a separator that isn't escaped with a backslash, keeping the escapes.
+fn split_unescaped(value: &str, separator: char) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut curr = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            curr.push(ch);
+            if let Some(next) = chars.next() {
+                curr.push(next);
+            }
+        } else if ch == separator {
+            res.push(curr);
+            curr = String::new();
+        } else {
+            curr.push(ch);
+        }
+    }
+    res.push(curr);
+    res
+}
+
+fn unescape(value: &str) -> String {
+    let mut res = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            if let Some(next) = chars.next() {
+                if next != ':' && next != ';' && next != '\\' {
+                    res.push(ch);
+                }
+                res.push(next);
+            }
+        } else {
+            res.push(ch);
+        }
+    }
+    res
+}
+
+/// Parses one file description of a `file` field and returns its path
+fn parse_file(file: &str) -> Option<String> {
+    let parts = split_unescaped(file.trim(), ':');
+    let path = if parts.len() >= 3 {
+        // JabRef format: description:path:type, a Windows drive letter adds another part
+        parts[1..parts.len() - 1].join(":")
+    } else {
+        file.trim().to_string()
+    };
+    let path = unescape(path.trim());
+    if path.is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    macros: HashMap<String, String>,
+}
+
+impl Parser {
+    fn new(content: &str) -> Parser {
+        let months = [
+            "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+        ];
+        let mut macros = HashMap::new();
+        for (i, month) in months.iter().enumerate() {
+            macros.insert(month.to_string(), (i + 1).to_string());
+        }
+        Parser {
+            chars: content.chars().collect(),
+            pos: 0,
+            line: 1,
+            macros,
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ImportError> {
+        Err(ImportError::InvalidFormat {
+            line: self.line,
+            message: message.to_string(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += 1;
+        if ch == '\n' {
+            self.line += 1;
+        }
+        Some(ch)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(ch) = self.peek() {
+            if !ch.is_whitespace() {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ImportError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(ch) if ch == expected => Ok(()),
+            _ => self.error(&format!("expected '{}'", expected)),
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let mut res = String::new();
+        while let Some(ch) = self.peek() {
+            if ch.is_whitespace() || "{}(),=#\"".contains(ch) {
+                break;
+            }
+            res.push(ch);
+            self.next();
+        }
+        res
+    }
+
+    /// Reads the content of a balanced group, the opening delimiter is already consumed
+    fn balanced(&mut self, close: char) -> Result<String, ImportError> {
+        let open = if close == '}' { '{' } else { '(' };
+        let mut depth = 0;
+        let mut res = String::new();
+        loop {
+            match self.next() {
+                None => return self.error(&format!("missing '{}'", close)),
+                Some(ch) if ch == close && depth == 0 => return Ok(res),
+                Some(ch) => {
+                    if ch == open {
+                        depth += 1;
+                    } else if ch == close {
+                        depth -= 1;
+                    }
+                    res.push(ch);
+                }
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, ImportError> {
+        let mut depth = 0;
+        let mut res = String::new();
+        loop {
+            match self.next() {
+                None => return self.error("missing '\"'"),
+                Some('"') if depth == 0 => return Ok(res),
+                Some(ch) => {
+                    if ch == '{' {
+                        depth += 1;
+                    } else if ch == '}' {
+                        depth -= 1;
+                    }
+                    res.push(ch);
+                }
+            }
+        }
+    }
+
+    /// Reads a value made of parts concatenated with `#`
+    fn value(&mut self) -> Result<String, ImportError> {
+        let mut res = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => {
+                    self.next();
+                    res.push_str(&self.balanced('}')?);
+                }
+                Some('"') => {
+                    self.next();
+                    res.push_str(&self.quoted()?);
+                }
+                Some(_) => {
+                    let ident = self.identifier();
+                    if ident.is_empty() {
+                        return self.error("expected a value");
+                    }
+                    match self.macros.get(&ident.to_lowercase()) {
+                        Some(val) => res.push_str(val),
+                        None => res.push_str(&ident),
+                    }
+                }
+                None => return self.error("expected a value"),
+            }
+            self.skip_whitespace();
+            if self.peek() == Some('#') {
+                self.next();
+            } else {
+                return Ok(res);
+            }
+        }
+    }
+
+    /// Reads `name = value` pairs until the closing delimiter
+    fn fields(&mut self, close: char) -> Result<Vec<(String, String)>, ImportError> {
+        let mut fields = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(ch) if ch == close => {
+                    self.next();
+                    return Ok(fields);
+                }
+                Some(',') => {
+                    self.next();
+                }
+                Some(_) => {
+                    let name = self.identifier().to_lowercase();
+                    if name.is_empty() {
+                        return self.error("expected a field name");
+                    }
+                    self.expect('=')?;
+                    let value = self.value()?;
+                    fields.push((name, value));
+                }
+                None => return self.error(&format!("missing '{}'", close)),
+            }
+        }
+    }
+
+    fn entries(&mut self) -> Result<Vec<Entry>, ImportError> {
+        let mut entries = Vec::new();
+        while let Some(ch) = self.next() {
+            if ch != '@' {
+                continue;
+            }
+            self.skip_whitespace();
+            let kind = self.identifier().to_lowercase();
+            self.skip_whitespace();
+            let close = match self.next() {
+                Some('{') => '}',
+                Some('(') => ')',
+                _ => return self.error("expected '{' or '('"),
+            };
+            match kind.as_str() {
+                "comment" | "preamble" => {
+                    self.balanced(close)?;
+                }
+                "string" => {
+                    for (name, value) in self.fields(close)? {
+                        self.macros.insert(name, value);
+                    }
+                }
+                _ => {
+                    self.skip_whitespace();
+                    let key = self.identifier();
+                    let fields = self.fields(close)?;
+                    entries.push(Entry { kind, key, fields });
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Parses the content of a BibTeX file into a list of entries.
+///
+/// ## Example
+/// ```rust
+/// let entries = book_lib::import::bibtex::parse(
+///     "@book{knuth1984, title = {The {\\TeX}book}, author = \"Donald Knuth\", year = 1984,
+///       file = {:books/texbook.pdf:PDF}}",
+/// )
+/// .unwrap_or_default();
+/// assert_eq!(entries[0].key, "knuth1984");
+/// assert_eq!(entries[0].field("year"), Some("1984"));
+/// assert_eq!(entries[0].files(), vec!["books/texbook.pdf".to_string()]);
+/// ```
+pub fn parse(content: &str) -> Result<Vec<Entry>, ImportError> {
+    Parser::new(content).entries()
+}
@@ -0,0 +1,202 @@
+//! An importer for Calibre libraries.
+//!
+//! The importer opens the `metadata.db` of a Calibre library read-only and creates a book for
+//! each PDF format of a Calibre book. The file of a format is located in the library root under
+//! `<book path>/<format name>.pdf`.
+//!
+//! The Calibre data is mapped onto the book as follows:
+//! - title: the name of the book and its title
+//! - authors, series, publisher, ISBN and DOI identifiers: the book's metadata
+//! - tags: the book's tags, the first tag is also used as the section unless a section is given
+//!   in the options
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use super::bibtex::parse_year;
+use super::{import_books, ImportFailure, ImportOptions, ImportReport};
+use crate::book::{Book, Metadata};
+use crate::errors::ImportError;
+use crate::help;
+
+/// Name of the Calibre database file inside a library root
+pub const METADATA_DB: &str = "metadata.db";
+
+/// A PDF format of a book stored in a Calibre library
+#[derive(Clone, Debug)]
+pub struct CalibreBook {
+    /// id of the book in the Calibre database
+    pub id: i64,
+    pub title: String,
+    /// directory of the book, relative to the library root
+    pub dir: String,
+    /// name of the format file without extension
+    pub file_name: String,
+    pub pubdate: Option<String>,
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+    pub series: Option<String>,
+    pub publisher: Option<String>,
+    pub isbn: Option<String>,
+    pub doi: Option<String>,
+}
+
+impl CalibreBook {
+    /// Returns the path to the PDF inside the library root
+    pub fn file_path(&self, library_root: &Path) -> String {
+        help::path_to_string(
+            library_root
+                .join(&self.dir)
+                .join(format!("{}.pdf", self.file_name)),
+        )
+    }
+
+    fn to_book(&self, library_root: &Path, options: &ImportOptions) -> Result<Book, ImportFailure> {
+        let name = self.title.trim().to_string();
+        if name.is_empty() {
+            return Err(ImportFailure::NoName);
+        }
+        let section = match &options.section {
+            Some(section) => Some(section.clone()),
+            None => self.tags.first().cloned(),
+        };
+        let mut bk = Book::init(name, self.file_path(library_root), section, false);
+        bk.metadata = Metadata {
+            title: Some(self.title.clone()),
+            authors: self.authors.clone(),
+            // Calibre stores an undefined date as the year 101
+            year: self
+                .pubdate
+                .as_deref()
+                .and_then(parse_year)
+                .filter(|year| *year > 1000),
+            publisher: self.publisher.clone(),
+            series: self.series.clone(),
+            doi: self.doi.clone(),
+            isbn: self.isbn.clone(),
+            tags: self.tags.clone(),
+        };
+        Ok(bk)
+    }
+}
+
+fn invalid_database(err: rusqlite::Error) -> ImportError {
+    ImportError::InvalidDatabase(err.to_string())
+}
+
+fn linked_names(
+    calibre: &Connection,
+    table: &str,
+    column: &str,
+    id: i64,
+) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = calibre.prepare(&format!(
+        "SELECT {table}.name FROM books_{table}_link
+        JOIN {table} ON {table}.id = books_{table}_link.{column}
+        WHERE books_{table}_link.book = ?
+        ORDER BY books_{table}_link.id",
+        table = table,
+        column = column
+    ))?;
+    let names = stmt
+        .query_map(params![id], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+fn identifier(calibre: &Connection, kind: &str, id: i64) -> rusqlite::Result<Option<String>> {
+    let mut stmt = calibre.prepare("SELECT val FROM identifiers WHERE book = ? AND type = ?")?;
+    let mut rows = stmt.query(params![id, kind])?;
+    match rows.next()? {
+        Some(row) => row.get(0),
+        None => Ok(None),
+    }
+}
+
+fn read_books(calibre: &Connection) -> rusqlite::Result<Vec<CalibreBook>> {
+    let mut stmt = calibre.prepare(
+        "SELECT books.id, books.title, books.path, books.pubdate, books.isbn, data.name
+        FROM books JOIN data ON data.book = books.id
+        WHERE upper(data.format) = 'PDF'
+        ORDER BY books.id",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut books = Vec::new();
+    for (id, title, dir, pubdate, isbn, file_name) in rows {
+        let authors = linked_names(calibre, "authors", "author", id)?
+            .iter()
+            // Calibre replaces the commas in author names with a pipe
+            .map(|author| author.replace('|', ","))
+            .collect();
+        let isbn = match isbn.filter(|isbn| !isbn.is_empty()) {
+            Some(isbn) => Some(isbn),
+            None => identifier(calibre, "isbn", id)?,
+        };
+        books.push(CalibreBook {
+            id,
+            title,
+            dir,
+            file_name,
+            pubdate,
+            authors,
+            tags: linked_names(calibre, "tags", "tag", id)?,
+            series: linked_names(calibre, "series", "series", id)?.pop(),
+            publisher: linked_names(calibre, "publishers", "publisher", id)?.pop(),
+            isbn,
+            doi: identifier(calibre, "doi", id)?,
+        });
+    }
+    Ok(books)
+}
+
+/// Opens the `metadata.db` of a Calibre library read-only and returns its PDF formats
+pub fn read_library<P: AsRef<Path>>(library_root: P) -> Result<Vec<CalibreBook>, ImportError> {
+    let db_path = library_root.as_ref().join(METADATA_DB);
+    if !db_path.exists() {
+        return Err(ImportError::CouldNotReadFile);
+    }
+    let calibre = match Connection::open_with_flags(
+        &db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    ) {
+        Ok(calibre) => calibre,
+        Err(_) => return Err(ImportError::CouldNotReadFile),
+    };
+    read_books(&calibre).map_err(invalid_database)
+}
+
+/// Creates a book for each PDF of the Calibre library located at `library_root`.
+///
+/// The Calibre database is never written to. Books whose title is already used as a name are
+/// handled according to `options.duplicates`, and nothing is written with `options.dry_run`.
+pub fn import_calibre<P: AsRef<Path>>(
+    conn: &Connection,
+    library_root: P,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    let library_root = library_root.as_ref();
+    let calibre_books = read_library(library_root)?;
+    let books = calibre_books
+        .iter()
+        .map(|cb| {
+            (
+                format!("calibre:{}", cb.id),
+                cb.to_book(library_root, options),
+            )
+        })
+        .collect();
+    Ok(import_books(conn, books, None, options))
+}
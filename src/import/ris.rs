@@ -0,0 +1,192 @@
+//! A minimal RIS parser.
+//!
+//! A RIS file is a list of records made of `XX  - value` lines, each record starts with a `TY`
+//! tag and ends with an `ER` tag.
+
+use std::path::Path;
+
+use super::bibtex::parse_year;
+use super::{book_name, resolve_path, ImportFailure, ImportOptions};
+use crate::book::{Book, Metadata};
+use crate::errors::ImportError;
+use crate::help;
+
+/// A single record of a RIS file
+#[derive(Clone, Debug)]
+pub struct Record {
+    /// tags in the order they appear in the record, including `TY`
+    pub tags: Vec<(String, String)>,
+}
+
+impl Record {
+    /// Returns the first value of the first tag from the list that the record has
+    pub fn first(&self, names: &[&str]) -> Option<&str> {
+        names.iter().find_map(|name| {
+            self.tags
+                .iter()
+                .find(|(tag, value)| tag == name && !value.is_empty())
+                .map(|(_, value)| value.as_str())
+        })
+    }
+
+    /// Returns every value of the given tags
+    pub fn all(&self, names: &[&str]) -> Vec<String> {
+        self.tags
+            .iter()
+            .filter(|(tag, value)| names.contains(&tag.as_str()) && !value.is_empty())
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    /// Returns the citation key of the record (the `ID` tag) or its position in the file
+    pub fn key(&self, index: usize) -> String {
+        match self.first(&["ID"]) {
+            Some(id) => id.to_string(),
+            None => format!("#{}", index + 1),
+        }
+    }
+
+    /// Returns the local files attached to the record with the `L1` tag, and the `UR` links
+    /// that point to local files.
+    pub fn files(&self) -> Vec<String> {
+        let mut res: Vec<String> = self
+            .all(&["L1"])
+            .iter()
+            .flat_map(|value| value.split(';'))
+            .map(|file| file.trim())
+            .filter(|file| !file.is_empty())
+            .map(|file| file.strip_prefix("file://").unwrap_or(file).to_string())
+            .collect();
+        for url in self.all(&["UR"]) {
+            if let Some(file) = url.strip_prefix("file://") {
+                res.push(file.to_string());
+            }
+        }
+        res
+    }
+
+    /// Maps the record onto a book, the first attached PDF becomes the book's path.
+    pub(crate) fn to_book(
+        &self,
+        base_dir: &Path,
+        options: &ImportOptions,
+    ) -> Result<Book, ImportFailure> {
+        let title = self
+            .first(&["TI", "T1", "CT"])
+            .map(|title| title.to_string());
+        let name = match book_name(self.first(&["ID"]), title.as_deref(), options) {
+            Some(name) => name,
+            None => return Err(ImportFailure::NoName),
+        };
+        let files = self.files();
+        let file = match files
+            .iter()
+            .find(|file| help::is_pdf(file))
+            .or(files.first())
+        {
+            Some(file) => resolve_path(base_dir, file),
+            None => return Err(ImportFailure::NoFile),
+        };
+        let mut bk = Book::init(name, file, options.section.clone(), false);
+        bk.metadata = Metadata {
+            title,
+            authors: self.all(&["AU", "A1"]),
+            year: self.first(&["PY", "Y1", "DA"]).and_then(parse_year),
+            publisher: self
+                .first(&["PB", "JO", "JF", "T2"])
+                .map(|publisher| publisher.to_string()),
+            series: self.first(&["T3"]).map(|series| series.to_string()),
+            doi: self.first(&["DO"]).map(|doi| doi.to_string()),
+            isbn: self.first(&["SN"]).map(|isbn| isbn.to_string()),
+            tags: self.all(&["KW"]),
+        };
+        Ok(bk)
+    }
+}
+
+/// Splits a RIS line into its tag and value, returns None for lines that aren't tagged
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim_end();
+    let tag = line.get(0..2)?;
+    let rest = line.get(2..)?.trim_start();
+    if !tag
+        .chars()
+        .all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit())
+    {
+        return None;
+    }
+    let value = rest.strip_prefix('-')?;
+    Some((tag.to_string(), value.trim().to_string()))
+}
+
+/// Parses the content of a RIS file into a list of records.
+///
+/// ## Example
+/// ```rust
+/// let records = book_lib::import::ris::parse(
+///     "TY  - BOOK\nTI  - The TeXbook\nAU  - Knuth, Donald\nL1  - books/texbook.pdf\nER  - \n",
+/// )
+/// .unwrap_or_default();
+/// assert_eq!(records[0].first(&["TI"]), Some("The TeXbook"));
+/// assert_eq!(records[0].files(), vec!["books/texbook.pdf".to_string()]);
+/// ```
+pub fn parse(content: &str) -> Result<Vec<Record>, ImportError> {
+    let mut records = Vec::new();
+    let mut curr: Option<Record> = None;
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}');
+        let (tag, value) = match parse_line(line) {
+            Some(res) => res,
+            None => {
+                // a line without a tag continues the value of the previous tag
+                if let Some(record) = curr.as_mut() {
+                    if let Some((_, last)) = record.tags.last_mut() {
+                        if !line.trim().is_empty() {
+                            last.push(' ');
+                            last.push_str(line.trim());
+                        }
+                    }
+                }
+                continue;
+            }
+        };
+        match tag.as_str() {
+            "TY" => {
+                if curr.is_some() {
+                    return Err(ImportError::InvalidFormat {
+                        line: i + 1,
+                        message: "a record starts before the previous one ends".to_string(),
+                    });
+                }
+                curr = Some(Record {
+                    tags: vec![(tag, value)],
+                });
+            }
+            "ER" => match curr.take() {
+                Some(record) => records.push(record),
+                None => {
+                    return Err(ImportError::InvalidFormat {
+                        line: i + 1,
+                        message: "a record ends before it starts".to_string(),
+                    })
+                }
+            },
+            _ => match curr.as_mut() {
+                Some(record) => record.tags.push((tag, value)),
+                None => {
+                    return Err(ImportError::InvalidFormat {
+                        line: i + 1,
+                        message: "a tag outside of a record".to_string(),
+                    })
+                }
+            },
+        }
+    }
+    if curr.is_some() {
+        return Err(ImportError::InvalidFormat {
+            line: content.lines().count(),
+            message: "the last record doesn't end with 'ER'".to_string(),
+        });
+    }
+    Ok(records)
+}
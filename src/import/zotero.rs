@@ -0,0 +1,327 @@
+//! An importer for local Zotero libraries.
+//!
+//! Zotero keeps its database in `zotero.sqlite` and the stored attachments in
+//! `storage/<attachment key>/` inside its data directory. The importer reads both offline, the
+//! database is opened as immutable so it can be read while Zotero is running.
+//!
+//! Every PDF attachment becomes a book. The metadata is taken from the parent item of the
+//! attachment:
+//! - title: the name of the book and its title
+//! - creators, date, publisher (or publication title), series, DOI, ISBN/ISSN: the metadata
+//! - tags: the book's tags
+//! - collections: the first collection (as `parent/child` for subcollections) is the section
+//!   unless a section is given in the options
+//!
+//! The attachment keys of the imported books are remembered, so running the import again only
+//! adds the attachments that weren't imported yet.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use super::bibtex::parse_year;
+use super::{import_books, ImportFailure, ImportOptions, ImportReport};
+use crate::book::{Book, Metadata};
+use crate::errors::ImportError;
+use crate::help;
+
+/// Name of the Zotero database file inside the data directory
+pub const ZOTERO_DB: &str = "zotero.sqlite";
+
+/// Source name under which the imported attachment keys are remembered
+pub const SOURCE: &str = "zotero";
+
+/// A PDF attachment of a Zotero library with the metadata of its parent item
+#[derive(Clone, Debug)]
+pub struct ZoteroAttachment {
+    /// unique key of the attachment item
+    pub key: String,
+    /// resolved path to the file, None for attachments whose location can't be resolved
+    pub path: Option<PathBuf>,
+    /// fields of the parent item (or the attachment itself for standalone attachments)
+    pub fields: HashMap<String, String>,
+    /// creators as `last name, first name`
+    pub creators: Vec<String>,
+    pub tags: Vec<String>,
+    /// collections of the item as `parent/child` paths
+    pub collections: Vec<String>,
+}
+
+impl ZoteroAttachment {
+    fn field(&self, name: &str) -> Option<String> {
+        self.fields
+            .get(name)
+            .filter(|value| !value.is_empty())
+            .cloned()
+    }
+
+    fn to_book(&self, options: &ImportOptions) -> Result<Book, ImportFailure> {
+        let title = self.field("title");
+        let name = match &title {
+            Some(title) => title.trim().to_string(),
+            None => return Err(ImportFailure::NoName),
+        };
+        let path = match &self.path {
+            Some(path) => help::path_to_string(path),
+            None => return Err(ImportFailure::NoFile),
+        };
+        let section = match &options.section {
+            Some(section) => Some(section.clone()),
+            None => self.collections.first().cloned(),
+        };
+        let mut bk = Book::init(name, path, section, false);
+        bk.metadata = Metadata {
+            title,
+            authors: self.creators.clone(),
+            year: self.field("date").as_deref().and_then(parse_year),
+            publisher: self.field("publisher").or(self.field("publicationTitle")),
+            series: self.field("series"),
+            doi: self.field("DOI"),
+            isbn: self.field("ISBN").or(self.field("ISSN")),
+            tags: self.tags.clone(),
+        };
+        Ok(bk)
+    }
+}
+
+/// Resolves the `path` column of an attachment
+///
+/// Stored files are written as `storage:<file name>` and live in the attachment's directory,
+/// linked files have an absolute path. Paths relative to the base attachment directory
+/// (`attachments:`) can't be resolved since that directory is a Zotero preference.
+fn resolve_path(data_dir: &Path, key: &str, path: &str) -> Option<PathBuf> {
+    if let Some(file_name) = path.strip_prefix("storage:") {
+        Some(data_dir.join("storage").join(key).join(file_name))
+    } else if path.starts_with("attachments:") {
+        None
+    } else {
+        let path = PathBuf::from(path);
+        if path.is_absolute() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
+
+fn read_collections(zotero: &Connection) -> rusqlite::Result<HashMap<i64, String>> {
+    let mut stmt = zotero
+        .prepare("SELECT collectionID, collectionName, parentCollectionID FROM collections")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                (row.get::<_, String>(1)?, row.get::<_, Option<i64>>(2)?),
+            ))
+        })?
+        .collect::<rusqlite::Result<HashMap<i64, (String, Option<i64>)>>>()?;
+
+    let mut paths = HashMap::new();
+    for id in rows.keys() {
+        let mut names = Vec::new();
+        let mut curr = Some(*id);
+        // the depth limit protects against a corrupted tree with a cycle
+        while let (Some(curr_id), true) = (curr, names.len() <= rows.len()) {
+            match rows.get(&curr_id) {
+                Some((name, parent)) => {
+                    names.push(name.clone());
+                    curr = *parent;
+                }
+                None => curr = None,
+            }
+        }
+        names.reverse();
+        paths.insert(*id, names.join("/"));
+    }
+    Ok(paths)
+}
+
+fn read_fields(zotero: &Connection, item_id: i64) -> rusqlite::Result<HashMap<String, String>> {
+    let mut stmt = zotero.prepare(
+        "SELECT fields.fieldName, itemDataValues.value FROM itemData
+        JOIN fields ON fields.fieldID = itemData.fieldID
+        JOIN itemDataValues ON itemDataValues.valueID = itemData.valueID
+        WHERE itemData.itemID = ?",
+    )?;
+    let fields = stmt
+        .query_map(params![item_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<HashMap<String, String>>>()?;
+    Ok(fields)
+}
+
+fn read_creators(zotero: &Connection, item_id: i64) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = zotero.prepare(
+        "SELECT creators.firstName, creators.lastName FROM itemCreators
+        JOIN creators ON creators.creatorID = itemCreators.creatorID
+        WHERE itemCreators.itemID = ?
+        ORDER BY itemCreators.orderIndex",
+    )?;
+    let creators = stmt
+        .query_map(params![item_id], |row| {
+            let first: Option<String> = row.get(0)?;
+            let last: Option<String> = row.get(1)?;
+            Ok(match (first.filter(|f| !f.is_empty()), last) {
+                (Some(first), Some(last)) => format!("{}, {}", last, first),
+                (None, Some(last)) => last,
+                (Some(first), None) => first,
+                (None, None) => String::new(),
+            })
+        })?
+        .filter(|creator| !matches!(creator, Ok(c) if c.is_empty()))
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(creators)
+}
+
+fn read_tags(zotero: &Connection, item_id: i64) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = zotero.prepare(
+        "SELECT tags.name FROM itemTags JOIN tags ON tags.tagID = itemTags.tagID
+        WHERE itemTags.itemID = ?
+        ORDER BY tags.name",
+    )?;
+    let tags = stmt
+        .query_map(params![item_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(tags)
+}
+
+fn read_item_collections(
+    zotero: &Connection,
+    item_id: i64,
+    collections: &HashMap<i64, String>,
+) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = zotero
+        .prepare("SELECT collectionID FROM collectionItems WHERE itemID = ? ORDER BY orderIndex")?;
+    let ids = stmt
+        .query_map(params![item_id], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?;
+    Ok(ids
+        .iter()
+        .filter_map(|id| collections.get(id).cloned())
+        .collect())
+}
+
+fn read_attachments(
+    zotero: &Connection,
+    data_dir: &Path,
+) -> rusqlite::Result<Vec<ZoteroAttachment>> {
+    let collections = read_collections(zotero)?;
+    let mut stmt = zotero.prepare(
+        "SELECT att.itemID, items.key, att.parentItemID, att.path
+        FROM itemAttachments att JOIN items ON items.itemID = att.itemID
+        WHERE att.contentType = 'application/pdf' AND att.path IS NOT NULL
+        AND att.itemID NOT IN (SELECT itemID FROM deletedItems)
+        AND (att.parentItemID IS NULL OR att.parentItemID NOT IN (SELECT itemID FROM deletedItems))
+        ORDER BY att.itemID",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut attachments = Vec::new();
+    for (item_id, key, parent_id, path) in rows {
+        let meta_id = parent_id.unwrap_or(item_id);
+        let mut fields = read_fields(zotero, meta_id)?;
+        if !fields.contains_key("title") {
+            // an item without a title is named after its attachment
+            if let Some(title) = read_fields(zotero, item_id)?.remove("title") {
+                fields.insert("title".to_string(), title);
+            }
+        }
+        attachments.push(ZoteroAttachment {
+            path: resolve_path(data_dir, &key, &path),
+            key,
+            fields,
+            creators: read_creators(zotero, meta_id)?,
+            tags: read_tags(zotero, meta_id)?,
+            collections: read_item_collections(zotero, meta_id, &collections)?,
+        });
+    }
+    Ok(attachments)
+}
+
+/// Returns the SQLite URI of a database file, None if the path can't be written in a URI.
+///
+/// Every byte of the path that isn't a plain character is percent-encoded, so file names that
+/// aren't valid Unicode are kept as they are. Windows paths are written with slashes, with an
+/// empty authority before a drive letter: `file:///C:/Users/...`.
+fn database_uri(path: &Path) -> Option<String> {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = path.to_str()?.replace('\\', "/").into_bytes();
+    let has_drive =
+        cfg!(windows) && bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    let mut uri = String::from("file:");
+    if bytes.first() == Some(&b'/') {
+        uri.push_str("//");
+    } else if has_drive {
+        uri.push_str("///");
+    }
+    for byte in bytes {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Some(uri)
+}
+
+/// Opens the `zotero.sqlite` of a Zotero data directory and returns its PDF attachments
+pub fn read_library<P: AsRef<Path>>(data_dir: P) -> Result<Vec<ZoteroAttachment>, ImportError> {
+    let data_dir = data_dir.as_ref();
+    let db_path = data_dir.join(ZOTERO_DB);
+    if !db_path.exists() {
+        return Err(ImportError::CouldNotReadFile);
+    }
+    // immutable databases are read without locking, Zotero keeps an exclusive lock while running
+    let uri = match database_uri(&db_path) {
+        Some(uri) => format!("{}?immutable=1", uri),
+        None => return Err(ImportError::CouldNotReadFile),
+    };
+    let zotero = match Connection::open_with_flags(
+        uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    ) {
+        Ok(zotero) => zotero,
+        Err(_) => return Err(ImportError::CouldNotReadFile),
+    };
+    match read_attachments(&zotero, data_dir) {
+        Ok(attachments) => Ok(attachments),
+        Err(err) => Err(ImportError::InvalidDatabase(err.to_string())),
+    }
+}
+
+/// Creates a book for each PDF attachment of the Zotero library located in `data_dir`.
+///
+/// The import is incremental: attachments imported by a previous run are reported as
+/// [`super::ImportOutcome::AlreadyImported`] (or [`super::ImportOutcome::InTrash`] when their
+/// book was removed since) and left untouched. Books whose title is already
+/// used as a name are handled according to `options.duplicates`, and nothing is written with
+/// `options.dry_run`.
+pub fn import_zotero<P: AsRef<Path>>(
+    conn: &Connection,
+    data_dir: P,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    let attachments = read_library(data_dir)?;
+    let books = attachments
+        .iter()
+        .map(|att| (att.key.clone(), att.to_book(options)))
+        .collect();
+    Ok(import_books(conn, books, Some(SOURCE), options))
+}
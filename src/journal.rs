@@ -0,0 +1,413 @@
+//! A module for undoing and redoing the changes of the library.
+//!
+//! Every change made with [`crate::create_book`], [`crate::remove_book`],
+//! [`crate::update_favourite`], [`crate::rename_book`], [`crate::update_section`],
+//! [`crate::update_path`] and [`crate::trash::restore_book`], and the paths updated by
+//! [`crate::health::relocate_missing_books`] and [`crate::watch::reconcile`], are recorded in a journal kept in the database with
+//! the value before and after the change. A change and its record are written in the same
+//! transaction. [`undo`] reverts the last change and [`redo`] applies again the last undone one, a
+//! new change forgets the undone ones.
+//!
+//! A change is replayed in a single transaction and only if the book is still in the state the
+//! change left it in, otherwise nothing is written and [`JournalError::Diverged`] is returned.
+//! Undoing the creation of a book moves it to the trash, see [`crate::trash`].
+//!
+//! Some changes aren't recorded and are never reverted by [`undo`]:
+//! - the reading progress and history ([`crate::progress`], [`crate::history`])
+//! - the openers preferred for a book ([`crate::open::set_book_opener`])
+//! - [`crate::roots::relativize_paths`], which changes how a path is stored but not the file it
+//!   points to
+//! - the missing flags, which follow the files ([`crate::watch::reconcile`], [`crate::health`])
+//! - the paths updated by [`crate::storage::consolidate`], undoing a consolidation in move mode
+//!   would point the books to files that are gone
+//!
+//! [`crate::trash::empty_trash`] deletes books for good, the changes of the deleted books are
+//! forgotten with them.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::journal;
+//! # let connection = book_lib::db::setup();
+//!
+//! let _ = book_lib::update_favourite(&connection, &"book_name".to_string(), false);
+//! if let Ok(entry) = journal::undo(&connection) {
+//!     println!("undone: {} of {}", entry.op.as_str(), entry.book);
+//! }
+//! ```
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::audit::{self, AuditOp};
+use crate::book::Book;
+use crate::db;
+use crate::errors::JournalError;
+use crate::storage;
+
+/// Number of changes kept in the journal, the oldest are forgotten first
+pub const JOURNAL_SIZE: usize = 500;
+
+/// A change of the library
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JournalOp {
+    CreateBook,
+    RemoveBook,
+    UpdateFavourite,
+    RenameBook,
+    UpdateSection,
+    UpdatePath,
+    /// a book brought back from the trash, possibly under another name
+    RestoreBook,
+}
+
+impl JournalOp {
+    /// Name of the change as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalOp::CreateBook => "create_book",
+            JournalOp::RemoveBook => "remove_book",
+            JournalOp::UpdateFavourite => "update_favourite",
+            JournalOp::RenameBook => "rename_book",
+            JournalOp::UpdateSection => "update_section",
+            JournalOp::UpdatePath => "update_path",
+            JournalOp::RestoreBook => "restore_book",
+        }
+    }
+
+    /// Returns the change with the given name, None if it's unknown
+    ///
+    /// ```rust
+    /// use book_lib::journal::JournalOp;
+    ///
+    /// assert_eq!(JournalOp::from_name("rename_book"), Some(JournalOp::RenameBook));
+    /// assert_eq!(JournalOp::from_name("burn_book"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<JournalOp> {
+        match name {
+            "create_book" => Some(JournalOp::CreateBook),
+            "remove_book" => Some(JournalOp::RemoveBook),
+            "update_favourite" => Some(JournalOp::UpdateFavourite),
+            "rename_book" => Some(JournalOp::RenameBook),
+            "update_section" => Some(JournalOp::UpdateSection),
+            "update_path" => Some(JournalOp::UpdatePath),
+            "restore_book" => Some(JournalOp::RestoreBook),
+            _ => None,
+        }
+    }
+}
+
+/// A change recorded in the journal
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JournalEntry {
+    pub id: i64,
+    pub op: JournalOp,
+    /// id of the row of the book, it stays the same when the book is renamed or trashed
+    pub book_id: i64,
+    /// name of the book after the change
+    pub book: String,
+    /// value before the change: "0" or "1" for the favourite state, the name (also for a
+    /// restoration), the section or the path, None for a creation, a removal or an empty section
+    pub before: Option<String>,
+    /// value after the change, like `before`
+    pub after: Option<String>,
+    /// when the change was made, in seconds since the Unix epoch
+    pub at: i64,
+    /// true if the change was undone
+    pub undone: bool,
+}
+
+const ENTRY_QUERY: &str = "SELECT id, op, book_id, book, before, after, at, undone FROM journal";
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<JournalEntry> {
+    let op: String = row.get(1)?;
+    Ok(JournalEntry {
+        id: row.get(0)?,
+        op: JournalOp::from_name(&op).ok_or(rusqlite::Error::InvalidColumnType(
+            1,
+            op,
+            rusqlite::types::Type::Text,
+        ))?,
+        book_id: row.get(2)?,
+        book: row.get(3)?,
+        before: row.get(4)?,
+        after: row.get(5)?,
+        at: row.get(6)?,
+        undone: row.get(7)?,
+    })
+}
+
+/// Records a change of the book of the row `book_id` named `name` after the change, the undone
+/// changes are forgotten.
+pub(crate) fn record(
+    conn: &Connection,
+    op: JournalOp,
+    book_id: i64,
+    name: &String,
+    before: Option<String>,
+    after: Option<String>,
+) -> rusqlite::Result<()> {
+    let savepoint = db::Savepoint::new(conn)?;
+    conn.execute("DELETE FROM journal WHERE undone = 1", [])?;
+    conn.execute(
+        "INSERT INTO journal (op, book_id, book, before, after, at) VALUES (?, ?, ?, ?, ?, ?)",
+        params![op.as_str(), book_id, name, before, after, db::now()],
+    )?;
+    conn.execute(
+        "DELETE FROM journal WHERE id <= (SELECT MAX(id) FROM journal) - ?",
+        params![JOURNAL_SIZE as i64],
+    )?;
+    savepoint.release()
+}
+
+/// Value of the favourite state stored in the journal
+pub(crate) fn favourite_value(favourite: bool) -> Option<String> {
+    Some((favourite as u8).to_string())
+}
+
+/// Returns the changes of the journal, the most recent first
+pub fn get_journal(conn: &Connection, limit: usize) -> Result<Vec<JournalEntry>, JournalError> {
+    let query = || -> rusqlite::Result<Vec<JournalEntry>> {
+        let mut stmt = conn.prepare(&format!("{} ORDER BY id DESC LIMIT ?", ENTRY_QUERY))?;
+        let entries = stmt
+            .query_map(params![limit as i64], entry_from_row)?
+            .collect();
+        entries
+    };
+    match query() {
+        Ok(entries) => Ok(entries),
+        Err(_) => Err(JournalError::DatabaseError),
+    }
+}
+
+/// Returns the change [`undo`] would revert, if any
+pub fn next_undo(conn: &Connection) -> Result<Option<JournalEntry>, JournalError> {
+    next_entry(conn, "undone = 0 ORDER BY id DESC")
+}
+
+/// Returns the change [`redo`] would apply again, if any
+pub fn next_redo(conn: &Connection) -> Result<Option<JournalEntry>, JournalError> {
+    next_entry(conn, "undone = 1 ORDER BY id")
+}
+
+fn next_entry(conn: &Connection, filter: &str) -> Result<Option<JournalEntry>, JournalError> {
+    match conn
+        .query_row(
+            &format!("{} WHERE {} LIMIT 1", ENTRY_QUERY, filter),
+            [],
+            entry_from_row,
+        )
+        .optional()
+    {
+        Ok(entry) => Ok(entry),
+        Err(_) => Err(JournalError::DatabaseError),
+    }
+}
+
+/// Forgets every change of the journal
+pub fn clear_journal(conn: &Connection) -> Result<(), JournalError> {
+    match conn.execute("DELETE FROM journal", []) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(JournalError::DatabaseError),
+    }
+}
+
+/// Reverts the last change that wasn't undone, returns it
+pub fn undo(conn: &Connection) -> Result<JournalEntry, JournalError> {
+    match next_undo(conn)? {
+        Some(entry) => replay(conn, entry, true),
+        None => Err(JournalError::NothingToUndo),
+    }
+}
+
+/// Applies again the last undone change, returns it
+pub fn redo(conn: &Connection) -> Result<JournalEntry, JournalError> {
+    match next_redo(conn)? {
+        Some(entry) => replay(conn, entry, false),
+        None => Err(JournalError::NothingToRedo),
+    }
+}
+
+fn replay(
+    conn: &Connection,
+    entry: JournalEntry,
+    undo: bool,
+) -> Result<JournalEntry, JournalError> {
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(JournalError::DatabaseError),
+    };
+    apply(conn, &entry, undo)?;
+    let marked = conn.execute(
+        "UPDATE journal SET undone = ? WHERE id = ?",
+        params![undo, entry.id],
+    );
+    if marked.is_err() || savepoint.release().is_err() {
+        return Err(JournalError::DatabaseError);
+    }
+    Ok(JournalEntry {
+        undone: undo,
+        ..entry
+    })
+}
+
+/// Returns the book of a row and whether it's in the trash
+fn book_by_id(conn: &Connection, id: i64) -> Result<Option<(Book, bool)>, JournalError> {
+    match conn
+        .query_row(
+            &format!(
+                "SELECT {}, deleted_at IS NOT NULL FROM books WHERE id = ?",
+                db::BOOK_COLUMNS
+            ),
+            params![id],
+            |row| {
+                let columns = row.as_ref().column_count();
+                Ok((db::book_from_row(row)?, row.get(columns - 1)?))
+            },
+        )
+        .optional()
+    {
+        Ok(book) => Ok(book),
+        Err(_) => Err(JournalError::DatabaseError),
+    }
+}
+
+/// Runs an update of a column of the book `name` and logs it in the audit log, see [`audit`]
+fn execute(
+    conn: &Connection,
+    op: AuditOp,
+    name: &String,
+    column: &str,
+    update: impl FnOnce() -> rusqlite::Result<usize>,
+) -> Result<(), JournalError> {
+    match db::audited_update(conn, op, name, column, update) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(JournalError::DatabaseError),
+    }
+}
+
+/// Writes the change of an entry, or its inverse if `undo` is true, after checking that the book
+/// is in the state the change (or its inverse) expects.
+fn apply(conn: &Connection, entry: &JournalEntry, undo: bool) -> Result<(), JournalError> {
+    let (from, to) = match undo {
+        true => (&entry.after, &entry.before),
+        false => (&entry.before, &entry.after),
+    };
+    let (book, trashed) = match book_by_id(conn, entry.book_id)? {
+        Some(book) => book,
+        None => return Err(JournalError::Diverged),
+    };
+    let name_used = |name: &String| db::get_book(conn, name).is_ok();
+    let untrash = match (entry.op, undo) {
+        (JournalOp::CreateBook, false)
+        | (JournalOp::RemoveBook, true)
+        | (JournalOp::RestoreBook, false) => Some(true),
+        (JournalOp::CreateBook, true)
+        | (JournalOp::RemoveBook, false)
+        | (JournalOp::RestoreBook, true) => Some(false),
+        _ => None,
+    };
+    if let Some(untrash) = untrash {
+        // a restored book can have been renamed, the other changes keep the name
+        if from.as_ref().is_some_and(|from| from != &book.name) {
+            return Err(JournalError::Diverged);
+        }
+        let name = to.clone().unwrap_or_else(|| book.name.clone());
+        if trashed != untrash || (untrash && name_used(&name)) {
+            return Err(JournalError::Diverged);
+        }
+        let (op, deleted_at, audited) = match untrash {
+            true => (AuditOp::RestoreBook, None, &name),
+            false => (AuditOp::RemoveBook, Some(db::now()), &book.name),
+        };
+        execute(conn, op, audited, "path", || {
+            conn.execute(
+                "UPDATE books SET name = ?, deleted_at = ? WHERE id = ?",
+                params![name, deleted_at, entry.book_id],
+            )
+        })?;
+        return Ok(());
+    }
+    if trashed {
+        return Err(JournalError::Diverged);
+    }
+    match entry.op {
+        JournalOp::UpdateFavourite => {
+            if &favourite_value(book.favourite) != from {
+                return Err(JournalError::Diverged);
+            }
+            let favourite = to.as_deref() == Some("1");
+            execute(
+                conn,
+                AuditOp::UpdateFavourite,
+                &book.name,
+                "favourite",
+                || {
+                    conn.execute(
+                        "UPDATE books SET favourite = ? WHERE id = ?",
+                        params![favourite, entry.book_id],
+                    )
+                },
+            )?;
+            Ok(())
+        }
+        JournalOp::RenameBook => {
+            let (Some(from), Some(to)) = (from, to) else {
+                return Err(JournalError::Diverged);
+            };
+            if &book.name != from || name_used(to) {
+                return Err(JournalError::Diverged);
+            }
+            let rename = || -> rusqlite::Result<()> {
+                conn.execute(
+                    "UPDATE books SET name = ? WHERE id = ?",
+                    params![to, entry.book_id],
+                )?;
+                audit::log(
+                    conn,
+                    AuditOp::RenameBook,
+                    to,
+                    Some(from.clone()),
+                    Some(to.clone()),
+                )
+            };
+            if rename().is_err() {
+                return Err(JournalError::DatabaseError);
+            }
+            sync_file(conn, to)
+        }
+        JournalOp::UpdateSection => {
+            if &book.section != from {
+                return Err(JournalError::Diverged);
+            }
+            execute(conn, AuditOp::UpdateSection, &book.name, "section", || {
+                conn.execute(
+                    "UPDATE books SET section = ? WHERE id = ?",
+                    params![to, entry.book_id],
+                )
+            })?;
+            sync_file(conn, &book.name)
+        }
+        JournalOp::UpdatePath => {
+            let (Some(from), Some(to)) = (from, to) else {
+                return Err(JournalError::Diverged);
+            };
+            if &book.path != from {
+                return Err(JournalError::Diverged);
+            }
+            match db::update_path(conn, &book.name, to) {
+                Ok(_) => Ok(()),
+                Err(_) => Err(JournalError::DatabaseError),
+            }
+        }
+        JournalOp::CreateBook | JournalOp::RemoveBook | JournalOp::RestoreBook => Ok(()),
+    }
+}
+
+/// Moves the file of a managed book, the move is undone if the replay is rolled back
+fn sync_file(conn: &Connection, name: &String) -> Result<(), JournalError> {
+    match storage::sync_book_file(conn, name) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(JournalError::CouldNotMoveFile),
+    }
+}
@@ -0,0 +1,193 @@
+//! A module for the kinds of documents the library can hold.
+//!
+//! Every book has a [`DocumentKind`]. The kind of a file is detected from its first bytes when
+//! the format has a signature (PDF, EPUB, DjVu, PostScript, HTML), and from its extension
+//! otherwise (Markdown) or when the file can't be read.
+//!
+//! Only the allowed kinds can be added to the library, by default only PDFs are allowed. The
+//! allowed kinds are a setting of the library, changed with [`set_allowed_kinds`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::kind::{self, DocumentKind};
+//! # let connection = book_lib::db::setup();
+//!
+//! let _ = kind::set_allowed_kinds(&connection, &[DocumentKind::Pdf, DocumentKind::Epub]);
+//! ```
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::db;
+use crate::errors::KindError;
+
+const ALLOWED_KINDS_KEY: &str = "documents.allowed_kinds";
+
+/// Number of bytes read from a file to detect its kind
+const SNIFF_LEN: usize = 1024;
+
+/// Kind of a document
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DocumentKind {
+    #[default]
+    Pdf,
+    Epub,
+    Djvu,
+    #[cfg_attr(feature = "serde", serde(rename = "ps"))]
+    PostScript,
+    #[cfg_attr(feature = "serde", serde(rename = "md"))]
+    Markdown,
+    Html,
+}
+
+impl DocumentKind {
+    /// Every document kind
+    pub const ALL: [DocumentKind; 6] = [
+        DocumentKind::Pdf,
+        DocumentKind::Epub,
+        DocumentKind::Djvu,
+        DocumentKind::PostScript,
+        DocumentKind::Markdown,
+        DocumentKind::Html,
+    ];
+
+    /// Returns the name under which the kind is stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentKind::Pdf => "pdf",
+            DocumentKind::Epub => "epub",
+            DocumentKind::Djvu => "djvu",
+            DocumentKind::PostScript => "ps",
+            DocumentKind::Markdown => "md",
+            DocumentKind::Html => "html",
+        }
+    }
+
+    /// Returns the kind with the given stored name
+    pub fn from_name(name: &str) -> Option<DocumentKind> {
+        DocumentKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+    }
+
+    /// Returns the file extensions of the kind, the first one is the usual one
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            DocumentKind::Pdf => &["pdf"],
+            DocumentKind::Epub => &["epub"],
+            DocumentKind::Djvu => &["djvu", "djv"],
+            DocumentKind::PostScript => &["ps", "eps"],
+            DocumentKind::Markdown => &["md", "markdown"],
+            DocumentKind::Html => &["html", "htm"],
+        }
+    }
+
+    /// Returns the kind of a path by its extension, case insensitive
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<DocumentKind> {
+        let ext = path.as_ref().extension()?.to_string_lossy().to_lowercase();
+        DocumentKind::ALL
+            .into_iter()
+            .find(|kind| kind.extensions().contains(&ext.as_str()))
+    }
+
+    /// Returns the kind of a document from its first bytes, None if they don't have a known
+    /// signature
+    ///
+    /// ```rust
+    /// use book_lib::kind::DocumentKind;
+    ///
+    /// assert_eq!(DocumentKind::from_magic(b"%PDF-1.7\n"), Some(DocumentKind::Pdf));
+    /// assert_eq!(DocumentKind::from_magic(b"AT&TFORM\0\0\0\0DJVU"), Some(DocumentKind::Djvu));
+    /// assert_eq!(DocumentKind::from_magic(b"  <!DOCTYPE html><html>"), Some(DocumentKind::Html));
+    /// assert_eq!(DocumentKind::from_magic(b"# A title"), None);
+    /// ```
+    pub fn from_magic(bytes: &[u8]) -> Option<DocumentKind> {
+        if bytes.starts_with(b"%PDF-") {
+            return Some(DocumentKind::Pdf);
+        }
+        if bytes.starts_with(b"AT&TFORM") {
+            return Some(DocumentKind::Djvu);
+        }
+        if bytes.starts_with(b"%!PS") || bytes.starts_with(b"\xc5\xd0\xd3\xc6") {
+            return Some(DocumentKind::PostScript);
+        }
+        // an EPUB is a zip whose first entry is an uncompressed `mimetype` file
+        if bytes.starts_with(b"PK\x03\x04") && find(bytes, b"application/epub+zip").is_some() {
+            return Some(DocumentKind::Epub);
+        }
+        let text = String::from_utf8_lossy(bytes);
+        let start = text
+            .trim_start_matches('\u{feff}')
+            .trim_start()
+            .to_lowercase();
+        if start.starts_with("<!doctype html") || start.starts_with("<html") {
+            return Some(DocumentKind::Html);
+        }
+        // PDF readers accept the header anywhere in the first kilobyte
+        if find(bytes, b"%PDF-").is_some() {
+            return Some(DocumentKind::Pdf);
+        }
+        None
+    }
+
+    /// Detects the kind of a file from its first bytes, falls back to its extension
+    pub fn detect<P: AsRef<Path>>(path: P) -> Option<DocumentKind> {
+        let path = path.as_ref();
+        let mut bytes = Vec::with_capacity(SNIFF_LEN);
+        if let Ok(file) = File::open(path) {
+            let _ = file.take(SNIFF_LEN as u64).read_to_end(&mut bytes);
+        }
+        DocumentKind::from_magic(&bytes).or_else(|| DocumentKind::from_extension(path))
+    }
+}
+
+impl std::fmt::Display for DocumentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DocumentKind::Pdf => write!(f, "PDF"),
+            DocumentKind::Epub => write!(f, "EPUB"),
+            DocumentKind::Djvu => write!(f, "DjVu"),
+            DocumentKind::PostScript => write!(f, "PostScript"),
+            DocumentKind::Markdown => write!(f, "Markdown"),
+            DocumentKind::Html => write!(f, "HTML"),
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Returns the kinds that can be added to the library, only PDFs by default
+pub fn get_allowed_kinds(conn: &Connection) -> Result<Vec<DocumentKind>, KindError> {
+    match db::get_setting(conn, ALLOWED_KINDS_KEY) {
+        Ok(Some(value)) => Ok(value
+            .split(',')
+            .filter_map(DocumentKind::from_name)
+            .collect()),
+        Ok(None) => Ok(vec![DocumentKind::Pdf]),
+        Err(_) => Err(KindError::DatabaseError),
+    }
+}
+
+/// Sets the kinds that can be added to the library, the books already added are kept
+pub fn set_allowed_kinds(conn: &Connection, kinds: &[DocumentKind]) -> Result<(), KindError> {
+    if kinds.is_empty() {
+        return Err(KindError::NoKindAllowed);
+    }
+    let mut kinds = kinds.to_vec();
+    kinds.sort();
+    kinds.dedup();
+    let value: Vec<&str> = kinds.iter().map(DocumentKind::as_str).collect();
+    match db::set_setting(conn, ALLOWED_KINDS_KEY, Some(&value.join(","))) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(KindError::DatabaseError),
+    }
+}
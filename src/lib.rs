@@ -10,7 +10,7 @@
 //!
 //! ## Usage
 //! 1. Create a connection to the database:
-//! ```rust
+//! ```rust,no_run
 //! use book_lib::{db, book};
 //!
 //! let connection = book_lib::db::setup();
@@ -18,32 +18,74 @@
 //! ```
 //!
 //! 2. Create a new book
-//! ```rust
+//! ```rust,no_run
+//! # use book_lib::book;
+//! # let connection = book_lib::db::setup();
 //! let new_book = book::Book::init("book_name".to_string(), "path_to/your/file.pdf".to_string(), None, false);
 //! book_lib::create_book(&connection, &new_book); //creating new book in the DB
 //! ```
 //!
 //! 3. Make it favourite
-//! ```rust
+//! ```rust,no_run
+//! # let connection = book_lib::db::setup();
 //! book_lib::update_favourite(&connection, &("book_name".to_string()), true); //true to be favourite, false not to be
 //! ```
 //!
 //! 4. Remove the book
-//! ```rust
+//! ```rust,no_run
+//! # let connection = book_lib::db::setup();
 //! book_lib::remove_book(&connection, &("book_name".to_string()));
 //! ```
 //!
-//! ## Examples of implementation
+//! 5. Import books from a bibliography
+//! ```rust,no_run
+//! use book_lib::import::{self, ImportOptions};
+//! # let connection = book_lib::db::setup();
+//!
+//! let report = import::import_bibtex(&connection, "refs.bib", &ImportOptions::default());
+//! ```
+//!
+//! ## Threads and processes
+//! The database can be used by several processes at once, e.g. a CLI and a GUI. A connection
+//! can be moved to another thread but not shared, see the [`shared`] module. Async applications
+//! can enable the `async` feature, see `async_api`.
+//!
 //! ## Examples of implementation
 //! 1. [cli for managing PDFs](https://github.com/DobbiKov/book-cli)
 //! 2. [GUI for managing PDFs](https://github.com/DobbiKov/book-manager-app)
 
+#[cfg(feature = "async")]
+pub mod async_api;
+pub mod audit;
+pub mod batch;
 pub mod book;
 pub mod db;
 pub mod errors;
+pub mod export;
+pub mod hash;
+pub mod health;
 pub mod help;
+pub mod history;
+pub mod import;
+pub mod journal;
+pub mod kind;
+pub mod notify;
+pub mod open;
+pub mod pdf;
+pub mod progress;
+pub mod roots;
+pub mod scan;
+pub mod shared;
+pub mod stats;
+pub mod storage;
+pub mod trash;
+pub mod watch;
 
-use errors::{CreateBookError, GetBookError, GetBooksError, RemoveBookError, UpdateFavouriteError};
+use errors::{
+    CreateBookError, GetBookError, GetBooksError, RemoveBookError, RenameBookError,
+    UpdateFavouriteError, UpdatePathError, UpdateSectionError,
+};
+use journal::JournalOp;
 use rusqlite::Connection;
 
 /// Returns all the books stored in the database or an error.
@@ -62,23 +104,126 @@ pub fn get_book(conn: &Connection, name: &String) -> Result<book::Book, GetBookE
     }
 }
 
-/// Removes a book by the given name or an error.
+/// Moves a book by the given name to the trash or an error, see [`trash`] to restore it.
 pub fn remove_book(conn: &Connection, name: &String) -> Result<book::Book, RemoveBookError> {
-    match db::remove_book(conn, name) {
-        Ok(res) => Ok(res),
-        Err(err) => Err(RemoveBookError::from(err)),
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(RemoveBookError::Other),
+    };
+    let book_id = match db::get_book_id(conn, name) {
+        Ok(book_id) => book_id,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(RemoveBookError::BookDoesNotExist),
+        Err(_) => return Err(RemoveBookError::Other),
+    };
+    let res = match db::remove_book(conn, name) {
+        Ok(res) => res,
+        Err(err) => return Err(RemoveBookError::from(err)),
+    };
+    let recorded = journal::record(conn, JournalOp::RemoveBook, book_id, name, None, None);
+    if recorded.is_err() || savepoint.release().is_err() {
+        return Err(RemoveBookError::Other);
     }
+    Ok(res)
 }
 
-/// Creates a book by the given book data.
-pub fn create_book(conn: &Connection, bk: &book::Book) -> Result<bool, CreateBookError> {
-    if !help::is_pdf(&bk.path) {
-        return Err(CreateBookError::ProvidedPathIsNotPdf);
-    }
+/// Records a change of a book in the journal, see [`journal`]. It must be called in the
+/// transaction of the change.
+fn record_change(
+    conn: &Connection,
+    op: JournalOp,
+    name: &String,
+    before: Option<String>,
+    after: Option<String>,
+) -> rusqlite::Result<()> {
+    let book_id = db::get_book_id(conn, name)?;
+    journal::record(conn, op, book_id, name, before, after)
+}
+
+/// Checks that a book can be created from the given data without writing anything.
+///
+/// Returns the kind of the document, see [`kind`]. The content of PDFs is checked with
+/// [`pdf::check_pdf`].
+pub(crate) fn validate_book(
+    conn: &Connection,
+    bk: &book::Book,
+) -> Result<kind::DocumentKind, CreateBookError> {
+    let detected = check_kind(conn, kind::DocumentKind::detect(bk.file_path()))?;
     let (is_correct, _) = help::is_correct_path(&bk.path);
     if !is_correct {
         return Err(CreateBookError::ProvidedPathIsIncorrect);
     }
+    if detected == kind::DocumentKind::Pdf {
+        match pdf::check_pdf(bk.file_path()) {
+            Ok(pdf::PdfVerdict::Valid) => {}
+            Ok(verdict) => return Err(CreateBookError::InvalidPdf(verdict)),
+            Err(_) => return Err(CreateBookError::ProvidedPathIsIncorrect),
+        }
+    }
+    Ok(detected)
+}
+
+/// Checks that a kind is allowed in the library, `None` is a file whose kind isn't known.
+pub(crate) fn check_kind(
+    conn: &Connection,
+    kind: Option<kind::DocumentKind>,
+) -> Result<kind::DocumentKind, CreateBookError> {
+    let allowed = match kind::get_allowed_kinds(conn) {
+        Ok(allowed) => allowed,
+        Err(_) => return Err(CreateBookError::OtherError),
+    };
+    match kind {
+        Some(kind) if allowed.contains(&kind) => Ok(kind),
+        _ if allowed == [kind::DocumentKind::Pdf] => Err(CreateBookError::ProvidedPathIsNotPdf),
+        other => Err(CreateBookError::DocumentKindNotAllowed(other)),
+    }
+}
+
+/// Creates a book by the given book data.
+///
+/// When a managed storage is configured, the file is copied or moved into the library root first,
+/// see [`storage`]. The kind of the book is detected from its file, see [`kind`].
+pub fn create_book(conn: &Connection, bk: &book::Book) -> Result<bool, CreateBookError> {
+    let mut bk = bk.clone();
+    bk.kind = validate_book(conn, &bk)?;
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(CreateBookError::OtherError),
+    };
+    let created = match storage::get_managed_storage(conn) {
+        Ok(Some(managed)) => storage::create_managed_book(conn, &managed, &bk)?,
+        Ok(None) => insert_book(conn, &bk)?,
+        Err(_) => return Err(CreateBookError::OtherError),
+    };
+    let recorded = record_change(conn, JournalOp::CreateBook, &bk.name, None, None);
+    if recorded.is_err() || savepoint.release().is_err() {
+        return Err(CreateBookError::OtherError);
+    }
+    Ok(created)
+}
+
+/// Creates a book whose file isn't on this machine, flagged as missing. Only its kind is checked,
+/// the file is checked when it's found again.
+pub(crate) fn create_missing_book(
+    conn: &Connection,
+    bk: &book::Book,
+) -> Result<bool, CreateBookError> {
+    let mut bk = bk.clone();
+    check_kind(conn, Some(bk.kind))?;
+    bk.missing = true;
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(CreateBookError::OtherError),
+    };
+    let created = insert_book(conn, &bk)?;
+    let recorded = record_change(conn, JournalOp::CreateBook, &bk.name, None, None);
+    if recorded.is_err() || savepoint.release().is_err() {
+        return Err(CreateBookError::OtherError);
+    }
+    Ok(created)
+}
+
+/// Inserts a validated book in the database.
+pub(crate) fn insert_book(conn: &Connection, bk: &book::Book) -> Result<bool, CreateBookError> {
     match db::create_book(conn, bk) {
         Ok(_) => Ok(true),
         Err(err) => match err {
@@ -88,32 +233,30 @@ pub fn create_book(conn: &Connection, bk: &book::Book) -> Result<bool, CreateBoo
     }
 }
 
-// Opens a book by the given name or returns an error.
-//pub fn open_book(conn: &Connection, name: &String) -> Result<(), OpenBookError> {
-//    // TODO: redo it
-//    // for any OS
-//    let bk_res = get_book(conn, name);
-//    match bk_res {
-//        Ok(bk) => {
-//            let path = bk.path;
-//            if !help::is_pdf(&path) {
-//                return Err(OpenBookError::FileIsNotPDF);
-//            }
-//            let (is_correct, _) = help::is_correct_path(&path);
-//            if !is_correct {
-//                return Err(OpenBookError::PathIsIncorrect);
-//            }
-//            process::Command::new("open")
-//                .args(["-a", "Skim", path.as_str()])
-//                .output()
-//                .expect("error while opening the file with Skim");
-//            Ok(())
-//        }
-//        Err(err) => match err {
-//            GetBookError::TableOrBookDoesnotExist => Err(OpenBookError::BookDoesNotExist),
-//        },
-//    }
-//}
+/// Creates a book like [`create_book`] and looks for the books that have the same file content,
+/// see [`hash::DuplicatePolicy`].
+///
+/// Returns the names of the books with the same content, always empty with
+/// [`hash::DuplicatePolicy::Allow`].
+pub fn create_book_with_policy(
+    conn: &Connection,
+    bk: &book::Book,
+    policy: hash::DuplicatePolicy,
+) -> Result<Vec<String>, CreateBookError> {
+    validate_book(conn, bk)?;
+    let same_content = match policy {
+        hash::DuplicatePolicy::Allow => Vec::new(),
+        _ => match hash::find_same_content(conn, &bk.path) {
+            Ok(names) => names,
+            Err(_) => return Err(CreateBookError::OtherError),
+        },
+    };
+    if let (hash::DuplicatePolicy::Refuse, Some(name)) = (policy, same_content.first()) {
+        return Err(CreateBookError::SameContentAs(name.clone()));
+    }
+    create_book(conn, bk)?;
+    Ok(same_content)
+}
 
 /// Update the books favourite state by the book's name.
 pub fn update_favourite(
@@ -121,8 +264,147 @@ pub fn update_favourite(
     name: &String,
     favourite: bool,
 ) -> Result<book::Book, UpdateFavouriteError> {
-    match db::update_favourite_error(conn, name, favourite) {
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(UpdateFavouriteError::Other),
+    };
+    let before = db::get_book(conn, name).ok().map(|book| book.favourite);
+    let book = match db::update_favourite_error(conn, name, favourite) {
+        Ok(book) => book,
+        Err(err) => return Err(UpdateFavouriteError::from(err)),
+    };
+    if let Some(before) = before.filter(|before| *before != favourite) {
+        let recorded = record_change(
+            conn,
+            JournalOp::UpdateFavourite,
+            name,
+            journal::favourite_value(before),
+            journal::favourite_value(favourite),
+        );
+        if recorded.is_err() {
+            return Err(UpdateFavouriteError::Other);
+        }
+    }
+    match savepoint.release() {
+        Ok(_) => Ok(book),
+        Err(_) => Err(UpdateFavouriteError::Other),
+    }
+}
+
+/// Points a book to a new file, the new path goes through the same validation as in
+/// [`create_book`].
+pub fn update_path(
+    conn: &Connection,
+    name: &String,
+    path: &str,
+) -> Result<book::Book, UpdatePathError> {
+    let before = match db::get_book(conn, name) {
+        Ok(book) => book.path,
+        Err(_) => return Err(UpdatePathError::BookDoesNotExist),
+    };
+    let bk = book::Book::init(name.clone(), path.to_string(), None, false);
+    match validate_book(conn, &bk) {
+        Ok(_) => {}
+        Err(CreateBookError::ProvidedPathIsNotPdf) => {
+            return Err(UpdatePathError::ProvidedPathIsNotPdf)
+        }
+        Err(CreateBookError::DocumentKindNotAllowed(kind)) => {
+            return Err(UpdatePathError::DocumentKindNotAllowed(kind))
+        }
+        Err(CreateBookError::InvalidPdf(verdict)) => {
+            return Err(UpdatePathError::InvalidPdf(verdict))
+        }
+        Err(_) => return Err(UpdatePathError::ProvidedPathIsIncorrect),
+    }
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(UpdatePathError::Other),
+    };
+    if db::update_path(conn, name, path).is_err() {
+        return Err(UpdatePathError::Other);
+    }
+    let book = match db::get_book(conn, name) {
+        Ok(book) => book,
+        Err(_) => return Err(UpdatePathError::Other),
+    };
+    if book.path != before {
+        let after = Some(book.path.clone());
+        if record_change(conn, JournalOp::UpdatePath, name, Some(before), after).is_err() {
+            return Err(UpdatePathError::Other);
+        }
+    }
+    match savepoint.release() {
+        Ok(_) => Ok(book),
+        Err(_) => Err(UpdatePathError::Other),
+    }
+}
+
+/// Renames a book, its file is renamed too if it's kept in the managed library root.
+pub fn rename_book(
+    conn: &Connection,
+    name: &String,
+    new_name: &String,
+) -> Result<book::Book, RenameBookError> {
+    if db::get_book(conn, name).is_err() {
+        return Err(RenameBookError::BookDoesNotExist);
+    }
+    if db::get_book(conn, new_name).is_ok() {
+        return Err(RenameBookError::BookNameAlreadyUsed);
+    }
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(RenameBookError::Other),
+    };
+    if db::rename_book(conn, name, new_name).is_err() {
+        return Err(RenameBookError::Other);
+    }
+    // the file is put back if the change is rolled back
+    if storage::sync_book_file(conn, new_name).is_err() {
+        return Err(RenameBookError::CouldNotMoveFile);
+    }
+    let (before, after) = (Some(name.clone()), Some(new_name.clone()));
+    let recorded = record_change(conn, JournalOp::RenameBook, new_name, before, after);
+    if recorded.is_err() || savepoint.release().is_err() {
+        return Err(RenameBookError::Other);
+    }
+    match db::get_book(conn, new_name) {
+        Ok(book) => Ok(book),
+        Err(_) => Err(RenameBookError::Other),
+    }
+}
+
+/// Moves a book to another section (None to remove it from its section), its file is moved too
+/// if it's kept in the managed library root.
+pub fn update_section(
+    conn: &Connection,
+    name: &String,
+    section: Option<String>,
+) -> Result<book::Book, UpdateSectionError> {
+    let before = match db::get_book(conn, name) {
+        Ok(book) => book.section,
+        Err(_) => return Err(UpdateSectionError::BookDoesNotExist),
+    };
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(UpdateSectionError::Other),
+    };
+    if db::update_section(conn, name, &section).is_err() {
+        return Err(UpdateSectionError::Other);
+    }
+    // the file is put back if the change is rolled back
+    if storage::sync_book_file(conn, name).is_err() {
+        return Err(UpdateSectionError::CouldNotMoveFile);
+    }
+    if before != section
+        && record_change(conn, JournalOp::UpdateSection, name, before, section).is_err()
+    {
+        return Err(UpdateSectionError::Other);
+    }
+    if savepoint.release().is_err() {
+        return Err(UpdateSectionError::Other);
+    }
+    match db::get_book(conn, name) {
         Ok(book) => Ok(book),
-        Err(err) => Err(UpdateFavouriteError::from(err)),
+        Err(_) => Err(UpdateSectionError::Other),
     }
 }
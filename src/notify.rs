@@ -0,0 +1,396 @@
+//! A module for noticing the changes of the library made by this program or by other ones.
+//!
+//! A [`ChangeWatcher`] keeps the state of the books it last saw, compares it with the database and
+//! gives what changed as [`ChangeEvent`]s to the callbacks registered with
+//! [`ChangeWatcher::on_change`]. Only the changes that were committed are seen, a change rolled
+//! back gives no event.
+//!
+//! The changes made through the connection given to [`ChangeWatcher::listen`] are caught by SQLite
+//! hooks: the update hook remembers the rows that were written, the commit hook keeps them and the
+//! rollback hook forgets them. The callbacks are called as soon as a change made by the library on
+//! that connection is committed. SQLite forbids reading the database from the hooks themselves, so
+//! the changes committed by a transaction that wasn't opened by the library are given with the
+//! next change of the library or by [`ChangeWatcher::poll`].
+//!
+//! The changes made by other connections or processes are caught with `PRAGMA data_version` by
+//! [`ChangeWatcher::poll`], which is cheap when nothing changed.
+//!
+//! Books moved to the trash are reported as removed and books restored from it as added, see
+//! [`crate::trash`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::notify::{ChangeEvent, ChangeWatcher};
+//! # let connection = book_lib::db::setup();
+//!
+//! let mut watcher = ChangeWatcher::new(&connection).unwrap();
+//! watcher.listen(&connection);
+//! watcher.on_change(|event| match event {
+//!     ChangeEvent::BookAdded { name, .. } => println!("{} added", name),
+//!     ChangeEvent::BookRemoved { name, .. } => println!("{} removed", name),
+//!     ChangeEvent::BookUpdated { name, fields, .. } => println!("{} changed: {:?}", name, fields),
+//! });
+//! // the callbacks are called with the changes of this connection right away
+//! let _ = book_lib::update_favourite(&connection, &"book_name".to_string(), true);
+//! // and with the changes of the other processes when polling
+//! loop {
+//!     let _ = watcher.poll(&connection);
+//!     std::thread::sleep(std::time::Duration::from_millis(500));
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use rusqlite::hooks::Action;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+
+use crate::errors::NotifyError;
+use crate::storage::connection_id;
+
+/// Maximal number of ids read by a single query, SQLite limits the number of parameters
+const IDS_PER_QUERY: usize = 500;
+
+/// Columns of the `books` table whose changes are reported, `name` must be the first one
+const WATCHED_COLUMNS: &[&str] = &[
+    "name",
+    "path",
+    "section",
+    "favourite",
+    "title",
+    "authors",
+    "year",
+    "publisher",
+    "series",
+    "doi",
+    "isbn",
+    "tags",
+    "missing",
+    "kind",
+    "opener",
+    "page_count",
+    "current_page",
+    "finished_at",
+    "last_opened_at",
+];
+
+/// A change of a book
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChangeEvent {
+    /// a book was created or restored from the trash
+    BookAdded { id: i64, name: String },
+    /// a book was removed, `name` is its last known name
+    BookRemoved { id: i64, name: String },
+    /// the data of a book changed, `fields` are the names of the changed columns of the `books`
+    /// table (`name`, `path`, `section`, `favourite`, `title`, `tags`, `current_page`...)
+    BookUpdated {
+        id: i64,
+        name: String,
+        fields: Vec<String>,
+    },
+}
+
+type Callback = Box<dyn FnMut(&ChangeEvent) + Send>;
+
+/// State of a watcher, shared with the hooks of the connection it listens to
+#[derive(Default)]
+struct State {
+    /// values of the watched columns of every book in the library, by id
+    books: BTreeMap<i64, Vec<Value>>,
+    data_version: i64,
+    /// rows of `books` written by the transaction going on
+    pending: BTreeSet<i64>,
+    /// rows of `books` written by committed transactions and not compared yet
+    committed: BTreeSet<i64>,
+    callbacks: Vec<Callback>,
+    /// true while the callbacks are called, the events of the changes they make are queued
+    delivering: bool,
+    queued: Vec<ChangeEvent>,
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Watchers listening to a connection, by connection id
+static LISTENERS: Mutex<Vec<(usize, Weak<Mutex<State>>)>> = Mutex::new(Vec::new());
+
+/// Watches the books of the library for changes, see the module documentation
+pub struct ChangeWatcher {
+    state: Arc<Mutex<State>>,
+}
+
+impl std::fmt::Debug for ChangeWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = lock(&self.state);
+        f.debug_struct("ChangeWatcher")
+            .field("books", &state.books.len())
+            .field("data_version", &state.data_version)
+            .field("callbacks", &state.callbacks.len())
+            .finish()
+    }
+}
+
+fn data_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("PRAGMA data_version", [], |row| row.get(0))
+}
+
+/// Reads the watched columns of the books, all of them if `ids` is None
+fn read_books(
+    conn: &Connection,
+    ids: Option<&BTreeSet<i64>>,
+) -> rusqlite::Result<BTreeMap<i64, Vec<Value>>> {
+    let sql = format!(
+        "SELECT id, {} FROM books WHERE deleted_at IS NULL",
+        WATCHED_COLUMNS.join(", ")
+    );
+    let read = |sql: &str, ids: &[i64]| -> rusqlite::Result<BTreeMap<i64, Vec<Value>>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params_from_iter(ids), |row| {
+            let values = (1..=WATCHED_COLUMNS.len())
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<rusqlite::Result<Vec<Value>>>()?;
+            Ok((row.get(0)?, values))
+        })?;
+        rows.collect()
+    };
+    let ids = match ids {
+        Some(ids) => ids.iter().copied().collect::<Vec<i64>>(),
+        None => return read(&sql, &[]),
+    };
+    let mut books = BTreeMap::new();
+    for chunk in ids.chunks(IDS_PER_QUERY) {
+        let marks = vec!["?"; chunk.len()].join(", ");
+        books.append(&mut read(&format!("{} AND id IN ({})", sql, marks), chunk)?);
+    }
+    Ok(books)
+}
+
+fn name_of(values: &[Value]) -> String {
+    match values.first() {
+        Some(Value::Text(name)) => name.clone(),
+        _ => String::new(),
+    }
+}
+
+impl State {
+    /// Compares the books written since the last comparison with the database, every book if
+    /// another connection wrote to it, and returns the changes in the order of their ids
+    fn changes(&mut self, conn: &Connection) -> rusqlite::Result<Vec<ChangeEvent>> {
+        let version = data_version(conn)?;
+        let touched = std::mem::take(&mut self.committed);
+        let ids = match version != self.data_version {
+            true => None,
+            false if touched.is_empty() => return Ok(Vec::new()),
+            false => Some(touched),
+        };
+        let current = match read_books(conn, ids.as_ref()) {
+            Ok(current) => current,
+            Err(err) => {
+                // compared again next time
+                self.committed.extend(ids.into_iter().flatten());
+                return Err(err);
+            }
+        };
+        self.data_version = version;
+        let checked: BTreeSet<i64> = match &ids {
+            Some(ids) => ids.clone(),
+            None => self.books.keys().chain(current.keys()).copied().collect(),
+        };
+        let mut events = Vec::new();
+        for id in checked {
+            let event = match (self.books.get(&id), current.get(&id)) {
+                (None, Some(new)) => ChangeEvent::BookAdded {
+                    id,
+                    name: name_of(new),
+                },
+                (Some(old), None) => ChangeEvent::BookRemoved {
+                    id,
+                    name: name_of(old),
+                },
+                (Some(old), Some(new)) if old != new => ChangeEvent::BookUpdated {
+                    id,
+                    name: name_of(new),
+                    fields: WATCHED_COLUMNS
+                        .iter()
+                        .zip(old.iter().zip(new))
+                        .filter(|(_, (old, new))| old != new)
+                        .map(|(column, _)| column.to_string())
+                        .collect(),
+                },
+                _ => continue,
+            };
+            match current.get(&id) {
+                Some(new) => self.books.insert(id, new.clone()),
+                None => self.books.remove(&id),
+            };
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+/// Gives events to the callbacks of a watcher.
+///
+/// The lock isn't held while a callback runs, so it can change the library: the events of its
+/// changes are given once every callback saw the current ones.
+fn deliver(state: &Mutex<State>, events: Vec<ChangeEvent>) {
+    let mut callbacks = {
+        let mut state = lock(state);
+        if state.delivering {
+            state.queued.extend(events);
+            return;
+        }
+        state.delivering = true;
+        std::mem::take(&mut state.callbacks)
+    };
+    let mut events = events;
+    loop {
+        for event in &events {
+            for callback in callbacks.iter_mut() {
+                callback(event);
+            }
+        }
+        let mut state = lock(state);
+        if state.queued.is_empty() {
+            // callbacks registered meanwhile come after the others
+            callbacks.append(&mut state.callbacks);
+            state.callbacks = callbacks;
+            state.delivering = false;
+            return;
+        }
+        events = std::mem::take(&mut state.queued);
+    }
+}
+
+/// Gives the changes committed through `conn` to the callbacks of the watcher listening to it,
+/// called by [`crate::db`] once a transaction of the library is committed
+pub(crate) fn committed(conn: &Connection) {
+    let id = connection_id(conn);
+    let state = {
+        let mut listeners = lock_listeners();
+        listeners.retain(|(_, state)| state.strong_count() > 0);
+        listeners
+            .iter()
+            .find(|(conn_id, _)| *conn_id == id)
+            .and_then(|(_, state)| state.upgrade())
+    };
+    let Some(state) = state else {
+        return;
+    };
+    // the rows that couldn't be read are given by the next change or poll
+    let events = match lock(&state).changes(conn) {
+        Ok(events) => events,
+        Err(_) => return,
+    };
+    if !events.is_empty() {
+        deliver(&state, events);
+    }
+}
+
+fn lock_listeners() -> MutexGuard<'static, Vec<(usize, Weak<Mutex<State>>)>> {
+    LISTENERS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl ChangeWatcher {
+    /// Creates a watcher that knows the current state of the library
+    pub fn new(conn: &Connection) -> Result<ChangeWatcher, NotifyError> {
+        let read = || -> rusqlite::Result<(BTreeMap<i64, Vec<Value>>, i64)> {
+            Ok((read_books(conn, None)?, data_version(conn)?))
+        };
+        match read() {
+            Ok((books, data_version)) => Ok(ChangeWatcher {
+                state: Arc::new(Mutex::new(State {
+                    books,
+                    data_version,
+                    ..Default::default()
+                })),
+            }),
+            Err(_) => Err(NotifyError::DatabaseError),
+        }
+    }
+
+    /// Catches the changes made through `conn` with SQLite hooks, the callbacks are then called as
+    /// soon as the library commits a change, see the module documentation.
+    ///
+    /// A connection has a single update, commit and rollback hook, they replace the ones set
+    /// before, e.g. by another watcher.
+    pub fn listen(&self, conn: &Connection) {
+        let state = Arc::downgrade(&self.state);
+        let on_update = state.clone();
+        conn.update_hook(Some(
+            move |action: Action, db: &str, table: &str, rowid: i64| {
+                if db == "main" && table == "books" && action != Action::UNKNOWN {
+                    if let Some(state) = on_update.upgrade() {
+                        lock(&state).pending.insert(rowid);
+                    }
+                }
+            },
+        ));
+        let on_commit = state.clone();
+        conn.commit_hook(Some(move || {
+            if let Some(state) = on_commit.upgrade() {
+                let mut state = lock(&state);
+                let pending = std::mem::take(&mut state.pending);
+                state.committed.extend(pending);
+            }
+            // the commit goes on
+            false
+        }));
+        let on_rollback = state.clone();
+        conn.rollback_hook(Some(move || {
+            if let Some(state) = on_rollback.upgrade() {
+                lock(&state).pending.clear();
+            }
+        }));
+        let id = connection_id(conn);
+        let mut listeners = lock_listeners();
+        listeners.retain(|(conn_id, state)| *conn_id != id && state.strong_count() > 0);
+        listeners.push((id, state));
+    }
+
+    /// Stops catching the changes made through `conn`, see [`ChangeWatcher::listen`]
+    pub fn unlisten(conn: &Connection) {
+        conn.update_hook(None::<fn(Action, &str, &str, i64)>);
+        conn.commit_hook(None::<fn() -> bool>);
+        conn.rollback_hook(None::<fn()>);
+        let id = connection_id(conn);
+        lock_listeners().retain(|(conn_id, _)| *conn_id != id);
+    }
+
+    /// Registers a callback called with every change
+    pub fn on_change<F>(&mut self, callback: F)
+    where
+        F: FnMut(&ChangeEvent) + Send + 'static,
+    {
+        lock(&self.state).callbacks.push(Box::new(callback));
+    }
+
+    /// Returns true if the library may have changed since the changes were last given, without
+    /// reading it
+    pub fn has_changes(&self, conn: &Connection) -> Result<bool, NotifyError> {
+        let (committed, known) = {
+            let state = lock(&self.state);
+            (!state.committed.is_empty(), state.data_version)
+        };
+        match data_version(conn) {
+            Ok(version) => Ok(committed || version != known),
+            Err(_) => Err(NotifyError::DatabaseError),
+        }
+    }
+
+    /// Returns the changes of the books that weren't given yet, in the order of their ids, and
+    /// gives them to the callbacks.
+    pub fn poll(&mut self, conn: &Connection) -> Result<Vec<ChangeEvent>, NotifyError> {
+        let events = match lock(&self.state).changes(conn) {
+            Ok(events) => events,
+            Err(_) => return Err(NotifyError::DatabaseError),
+        };
+        if !events.is_empty() {
+            deliver(&self.state, events.clone());
+        }
+        Ok(events)
+    }
+}
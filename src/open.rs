@@ -0,0 +1,491 @@
+//! A module for opening books with external programs.
+//!
+//! A book is opened by an [`Opener`]. The openers are kept in an [`OpenerRegistry`] by name, the
+//! registry made by [`OpenerRegistry::with_defaults`] knows the usual program of each platform:
+//! - `xdg-open` on Linux and the BSDs
+//! - `open` on macOS
+//! - `start` on Windows, which hands the file to the shell with `rundll32`
+//!
+//! Any program can be used through a command template, e.g. `zathura {path} -P {page}`, see
+//! [`CommandOpener`]. Templates added with [`add_command_opener`] are kept in the database and
+//! loaded by [`load_registry`].
+//!
+//! [`open_book`] uses the opener preferred for the book ([`set_book_opener`]), then the one
+//! preferred for its kind ([`set_kind_opener`]), then the default opener of the registry.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::kind::DocumentKind;
+//! use book_lib::open;
+//! # let connection = book_lib::db::setup();
+//!
+//! let _ = open::add_command_opener(&connection, "zathura", "zathura {path} -P {page}");
+//! let _ = open::set_kind_opener(&connection, DocumentKind::Djvu, Some("zathura"));
+//! if let Ok(registry) = open::load_registry(&connection) {
+//!     let _ = open::open_book(&connection, &registry, &"book_name".to_string(), Some(12));
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use rusqlite::{params, Connection};
+
+use crate::db;
+use crate::errors::OpenBookError;
+use crate::history;
+use crate::kind::DocumentKind;
+
+const KIND_KEY_PREFIX: &str = "open.kind.";
+const COMMAND_KEY_PREFIX: &str = "open.command.";
+/// Characters of a path that `cmd` doesn't take literally
+const CMD_SPECIAL: &[char] = &['&', '|', '<', '>', '^', '%', '!'];
+
+/// Something that can open the file of a book
+pub trait Opener: Send + Sync {
+    /// Name of the opener in the registry
+    fn name(&self) -> &str;
+
+    /// Opens the file, at the given page if the opener supports it
+    fn open(&self, path: &Path, page: Option<u32>) -> io::Result<()>;
+}
+
+/// An opener that starts a program from a command template.
+///
+/// The template is split on whitespace, double quotes keep a part with spaces together. `{path}`
+/// is replaced by the path of the file and `{page}` by the page to open. Without a page, the
+/// parts with `{page}` are dropped, together with the option right before them (`-P` in
+/// `zathura {path} -P {page}`). The path is added at the end when the template has no `{path}`.
+///
+/// ```rust
+/// use book_lib::open::CommandOpener;
+/// use std::path::Path;
+///
+/// let zathura = CommandOpener::from_template("zathura", "zathura {path} -P {page}").unwrap();
+/// let command = zathura.command(Path::new("/books/Книга.pdf"), Some(12));
+/// assert_eq!(command.get_program(), "zathura");
+/// let args: Vec<_> = command.get_args().collect();
+/// assert_eq!(args, ["/books/Книга.pdf", "-P", "12"]);
+///
+/// let command = zathura.command(Path::new("/books/a.pdf"), None);
+/// let args: Vec<_> = command.get_args().collect();
+/// assert_eq!(args, ["/books/a.pdf"]);
+///
+/// let skim = CommandOpener::from_template("skim", r#"open -a "Skim""#).unwrap();
+/// let command = skim.command(Path::new("/books/a.pdf"), None);
+/// let args: Vec<_> = command.get_args().collect();
+/// assert_eq!(args, ["-a", "Skim", "/books/a.pdf"]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandOpener {
+    name: String,
+    template: String,
+    parts: Vec<String>,
+}
+
+impl CommandOpener {
+    /// Creates an opener from a command template, the template must name a program
+    pub fn from_template(name: &str, template: &str) -> Result<CommandOpener, OpenBookError> {
+        let parts = split_template(template);
+        if parts.first().is_none_or(|program| program.is_empty()) {
+            return Err(OpenBookError::InvalidTemplate(template.to_string()));
+        }
+        Ok(CommandOpener {
+            name: name.to_string(),
+            template: template.to_string(),
+            parts,
+        })
+    }
+
+    /// `xdg-open`, the default opener of the Linux and BSD desktops
+    pub fn xdg_open() -> CommandOpener {
+        CommandOpener::from_template("xdg-open", "xdg-open {path}").unwrap()
+    }
+
+    /// `open`, the default opener of macOS
+    pub fn macos_open() -> CommandOpener {
+        CommandOpener::from_template("open", "open {path}").unwrap()
+    }
+
+    /// `start`, the default opener of Windows.
+    ///
+    /// The file is given to the program associated with it by `url.dll`, not by `cmd /C start`:
+    /// `cmd` would run what follows a `&` in the path as another command.
+    pub fn windows_start() -> CommandOpener {
+        CommandOpener::from_template("start", "rundll32 url.dll,FileProtocolHandler {path}")
+            .unwrap()
+    }
+
+    /// Returns the command template of the opener
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    fn runs_cmd(&self) -> bool {
+        let program = Path::new(&self.parts[0])
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase());
+        matches!(program.as_deref(), Some("cmd" | "cmd.exe"))
+    }
+
+    /// Returns the command that opens a file, without running it
+    pub fn command(&self, path: &Path, page: Option<u32>) -> Command {
+        let mut args: Vec<OsString> = Vec::new();
+        let mut has_path = false;
+        for (i, part) in self.parts.iter().enumerate().skip(1) {
+            if part.contains("{page}") && page.is_none() {
+                continue;
+            }
+            let next_needs_page = self
+                .parts
+                .get(i + 1)
+                .is_some_and(|next| next.contains("{page}"));
+            if page.is_none() && next_needs_page && part.starts_with('-') && !part.contains('{') {
+                continue;
+            }
+            let page = page.map(|page| page.to_string()).unwrap_or_default();
+            let part = part.replace("{page}", &page);
+            let mut arg = OsString::new();
+            let mut pieces = part.split("{path}");
+            if let Some(first) = pieces.next() {
+                arg.push(first);
+            }
+            for piece in pieces {
+                has_path = true;
+                arg.push(path.as_os_str());
+                arg.push(piece);
+            }
+            args.push(arg);
+        }
+        if !has_path {
+            args.push(path.as_os_str().to_os_string());
+        }
+        let mut command = Command::new(&self.parts[0]);
+        command.args(args);
+        command
+    }
+}
+
+impl Opener for CommandOpener {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Starts the program without waiting for it to exit, a background thread waits for it so
+    /// that it doesn't stay a zombie process.
+    ///
+    /// A template run through `cmd` can't open a path with one of `&|<>^%!`, `cmd` would read them
+    /// as its own syntax.
+    fn open(&self, path: &Path, page: Option<u32>) -> io::Result<()> {
+        if self.runs_cmd() && path.to_string_lossy().contains(CMD_SPECIAL) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the path has characters that cmd would interpret",
+            ));
+        }
+        let mut child = self
+            .command(path, page)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        std::thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+fn split_template(template: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut quoted = false;
+    for c in template.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                quoted = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() || quoted {
+                    parts.push(std::mem::take(&mut current));
+                }
+                quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() || quoted {
+        parts.push(current);
+    }
+    parts
+}
+
+/// Openers by name, with the one used when nothing else is preferred
+///
+/// ```rust
+/// use book_lib::open::{Opener, OpenerRegistry};
+/// use std::path::{Path, PathBuf};
+/// use std::sync::{Arc, Mutex};
+///
+/// /// an opener that only remembers what it was asked to open
+/// struct FakeOpener(Arc<Mutex<Vec<(PathBuf, Option<u32>)>>>);
+///
+/// impl Opener for FakeOpener {
+///     fn name(&self) -> &str {
+///         "fake"
+///     }
+///
+///     fn open(&self, path: &Path, page: Option<u32>) -> std::io::Result<()> {
+///         self.0.lock().unwrap().push((path.to_path_buf(), page));
+///         Ok(())
+///     }
+/// }
+///
+/// let opened = Arc::new(Mutex::new(Vec::new()));
+/// let mut registry = OpenerRegistry::with_defaults();
+/// registry.register(Box::new(FakeOpener(opened.clone())));
+/// registry.set_default("fake").unwrap();
+///
+/// registry.default_opener().unwrap().open(Path::new("a.pdf"), Some(3)).unwrap();
+/// assert_eq!(*opened.lock().unwrap(), [(PathBuf::from("a.pdf"), Some(3))]);
+/// assert!(registry.get("xdg-open").is_some());
+/// ```
+pub struct OpenerRegistry {
+    openers: BTreeMap<String, Box<dyn Opener>>,
+    default: Option<String>,
+}
+
+impl OpenerRegistry {
+    /// Creates a registry without any opener
+    pub fn new() -> OpenerRegistry {
+        OpenerRegistry {
+            openers: BTreeMap::new(),
+            default: None,
+        }
+    }
+
+    /// Creates a registry with the `xdg-open`, `open` and `start` openers, the one of the current
+    /// platform being the default
+    pub fn with_defaults() -> OpenerRegistry {
+        let mut registry = OpenerRegistry::new();
+        registry.register(Box::new(CommandOpener::xdg_open()));
+        registry.register(Box::new(CommandOpener::macos_open()));
+        registry.register(Box::new(CommandOpener::windows_start()));
+        let system = if cfg!(target_os = "macos") {
+            "open"
+        } else if cfg!(windows) {
+            "start"
+        } else {
+            "xdg-open"
+        };
+        registry.default = Some(system.to_string());
+        registry
+    }
+
+    /// Adds an opener, replacing the opener with the same name if any
+    pub fn register(&mut self, opener: Box<dyn Opener>) {
+        self.openers.insert(opener.name().to_string(), opener);
+    }
+
+    /// Removes an opener, it stops being the default one
+    pub fn unregister(&mut self, name: &str) -> Option<Box<dyn Opener>> {
+        if self.default.as_deref() == Some(name) {
+            self.default = None;
+        }
+        self.openers.remove(name)
+    }
+
+    /// Returns the opener with the given name
+    pub fn get(&self, name: &str) -> Option<&dyn Opener> {
+        self.openers.get(name).map(|opener| opener.as_ref())
+    }
+
+    /// Returns the names of the openers, sorted
+    pub fn names(&self) -> Vec<String> {
+        self.openers.keys().cloned().collect()
+    }
+
+    /// Sets the opener used when neither the book nor its kind have a preferred one
+    pub fn set_default(&mut self, name: &str) -> Result<(), OpenBookError> {
+        if !self.openers.contains_key(name) {
+            return Err(OpenBookError::UnknownOpener(name.to_string()));
+        }
+        self.default = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Returns the default opener
+    pub fn default_opener(&self) -> Option<&dyn Opener> {
+        self.get(self.default.as_deref()?)
+    }
+}
+
+impl Default for OpenerRegistry {
+    fn default() -> Self {
+        OpenerRegistry::with_defaults()
+    }
+}
+
+/// Returns the registry with the default openers and the command openers stored in the database
+pub fn load_registry(conn: &Connection) -> Result<OpenerRegistry, OpenBookError> {
+    let mut registry = OpenerRegistry::with_defaults();
+    for opener in get_command_openers(conn)? {
+        registry.register(Box::new(opener));
+    }
+    Ok(registry)
+}
+
+/// Returns the command openers stored in the database
+pub fn get_command_openers(conn: &Connection) -> Result<Vec<CommandOpener>, OpenBookError> {
+    let query = || -> rusqlite::Result<Vec<(String, String)>> {
+        let mut stmt =
+            conn.prepare("SELECT key, value FROM settings WHERE key LIKE ? ORDER BY key")?;
+        let rows = stmt
+            .query_map(params![format!("{}%", COMMAND_KEY_PREFIX)], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect();
+        rows
+    };
+    let stored = match query() {
+        Ok(stored) => stored,
+        Err(_) => return Err(OpenBookError::DatabaseError),
+    };
+    Ok(stored
+        .iter()
+        .filter_map(|(key, template)| {
+            let name = key.strip_prefix(COMMAND_KEY_PREFIX)?;
+            CommandOpener::from_template(name, template).ok()
+        })
+        .collect())
+}
+
+/// Stores a command opener in the database, replacing the one with the same name
+pub fn add_command_opener(
+    conn: &Connection,
+    name: &str,
+    template: &str,
+) -> Result<CommandOpener, OpenBookError> {
+    let opener = CommandOpener::from_template(name, template)?;
+    let key = format!("{}{}", COMMAND_KEY_PREFIX, name);
+    match db::set_setting(conn, &key, Some(template)) {
+        Ok(_) => Ok(opener),
+        Err(_) => Err(OpenBookError::DatabaseError),
+    }
+}
+
+/// Removes a command opener from the database, the preferences using it are kept
+pub fn remove_command_opener(conn: &Connection, name: &str) -> Result<(), OpenBookError> {
+    let key = format!("{}{}", COMMAND_KEY_PREFIX, name);
+    match db::set_setting(conn, &key, None) {
+        Ok(0) => Err(OpenBookError::UnknownOpener(name.to_string())),
+        Ok(_) => Ok(()),
+        Err(_) => Err(OpenBookError::DatabaseError),
+    }
+}
+
+/// Returns the opener preferred for a book, if any
+pub fn get_book_opener(conn: &Connection, name: &String) -> Result<Option<String>, OpenBookError> {
+    match db::get_opener(conn, name) {
+        Ok(opener) => Ok(opener),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(OpenBookError::BookDoesNotExist),
+        Err(_) => Err(OpenBookError::DatabaseError),
+    }
+}
+
+/// Sets the opener preferred for a book, None to use the opener of its kind
+pub fn set_book_opener(
+    conn: &Connection,
+    name: &String,
+    opener: Option<&str>,
+) -> Result<(), OpenBookError> {
+    match db::set_opener(conn, name, opener) {
+        Ok(0) => Err(OpenBookError::BookDoesNotExist),
+        Ok(_) => Ok(()),
+        Err(_) => Err(OpenBookError::DatabaseError),
+    }
+}
+
+/// Returns the opener preferred for a kind of documents, if any
+pub fn get_kind_opener(
+    conn: &Connection,
+    kind: DocumentKind,
+) -> Result<Option<String>, OpenBookError> {
+    let key = format!("{}{}", KIND_KEY_PREFIX, kind.as_str());
+    match db::get_setting(conn, &key) {
+        Ok(opener) => Ok(opener),
+        Err(_) => Err(OpenBookError::DatabaseError),
+    }
+}
+
+/// Sets the opener preferred for a kind of documents, None to use the default opener
+pub fn set_kind_opener(
+    conn: &Connection,
+    kind: DocumentKind,
+    opener: Option<&str>,
+) -> Result<(), OpenBookError> {
+    let key = format!("{}{}", KIND_KEY_PREFIX, kind.as_str());
+    match db::set_setting(conn, &key, opener) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(OpenBookError::DatabaseError),
+    }
+}
+
+/// Returns the name of the opener used for a book: the one preferred for the book, then the one
+/// preferred for its kind, then the default one of the registry
+pub fn resolve_opener(
+    conn: &Connection,
+    registry: &OpenerRegistry,
+    name: &String,
+) -> Result<String, OpenBookError> {
+    let bk = match db::get_book(conn, name) {
+        Ok(bk) => bk,
+        Err(_) => return Err(OpenBookError::BookDoesNotExist),
+    };
+    if let Some(opener) = get_book_opener(conn, name)? {
+        return Ok(opener);
+    }
+    if let Some(opener) = get_kind_opener(conn, bk.kind)? {
+        return Ok(opener);
+    }
+    match registry.default_opener() {
+        Some(opener) => Ok(opener.name().to_string()),
+        None => Err(OpenBookError::UnknownOpener("default".to_string())),
+    }
+}
+
+/// Opens a book, at the given page if the opener supports it.
+///
+/// Returns the name of the opener that was used, see [`resolve_opener`]. The opening is recorded
+/// in the reading history, see [`crate::history`].
+pub fn open_book(
+    conn: &Connection,
+    registry: &OpenerRegistry,
+    name: &String,
+    page: Option<u32>,
+) -> Result<String, OpenBookError> {
+    let opener_name = resolve_opener(conn, registry, name)?;
+    let opener = match registry.get(&opener_name) {
+        Some(opener) => opener,
+        None => return Err(OpenBookError::UnknownOpener(opener_name)),
+    };
+    let bk = match db::get_book(conn, name) {
+        Ok(bk) => bk,
+        Err(_) => return Err(OpenBookError::BookDoesNotExist),
+    };
+    let path = bk.file_path();
+    if !path.is_file() {
+        return Err(OpenBookError::PathIsIncorrect);
+    }
+    match opener.open(&path, page) {
+        Ok(_) => {
+            // the book is open even if the history couldn't be written
+            let _ = history::record_open(conn, name, None);
+            Ok(opener_name)
+        }
+        Err(err) => Err(OpenBookError::CouldNotOpen(err.to_string())),
+    }
+}
@@ -0,0 +1,112 @@
+//! A module for checking that a file is a PDF that can be read.
+//!
+//! [`check_pdf`] looks at the content of a file instead of its name:
+//! - the `%PDF-` header must be in the first kilobyte, as required by PDF readers
+//! - the file must end with a `startxref` section and the `%%EOF` marker, a file cut during a
+//!   download or a copy misses them
+//! - a trailer with an `/Encrypt` entry means the file is encrypted
+//!
+//! Only the beginning and the end of the file are read, so checking a large file is cheap. The
+//! check doesn't parse the whole document, a file with a damaged body can still be reported as
+//! valid.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::kind::DocumentKind;
+
+/// Size of the regions read at the beginning and at the end of a file
+const REGION_LEN: u64 = 64 * 1024;
+
+/// Readers look for the header in the first kilobyte and for the end marker in the last one
+const MARKER_LEN: usize = 1024;
+
+/// Result of [`check_pdf`]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PdfVerdict {
+    /// the file looks like a readable PDF
+    Valid,
+    /// the file isn't a PDF, with the kind detected from its content if it's known
+    WrongType(Option<DocumentKind>),
+    /// the file has a PDF header but is damaged, with the reason
+    Corrupt(String),
+    /// the file is encrypted, readers may ask for a password to open it
+    PasswordProtected,
+}
+
+impl std::fmt::Display for PdfVerdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PdfVerdict::Valid => write!(f, "valid PDF"),
+            PdfVerdict::WrongType(Some(kind)) => write!(f, "not a PDF but a {} document", kind),
+            PdfVerdict::WrongType(None) => write!(f, "not a PDF"),
+            PdfVerdict::Corrupt(reason) => write!(f, "corrupt PDF: {}", reason),
+            PdfVerdict::PasswordProtected => write!(f, "password-protected PDF"),
+        }
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Returns the verdict on the content of a PDF, see [`check_pdf`]
+///
+/// `head` and `tail` are the beginning and the end of the file, they are the same slice for a
+/// small file.
+///
+/// ```rust
+/// use book_lib::kind::DocumentKind;
+/// use book_lib::pdf::{check_pdf_bytes, PdfVerdict};
+///
+/// let pdf = b"%PDF-1.7\n1 0 obj\n<<>>\nendobj\ntrailer\n<< /Root 1 0 R >>\nstartxref\n9\n%%EOF\n";
+/// assert_eq!(check_pdf_bytes(pdf, pdf), PdfVerdict::Valid);
+///
+/// let truncated = &pdf[..30];
+/// assert!(matches!(check_pdf_bytes(truncated, truncated), PdfVerdict::Corrupt(_)));
+///
+/// let encrypted = b"%PDF-1.7\ntrailer\n<< /Root 1 0 R /Encrypt 2 0 R >>\nstartxref\n9\n%%EOF";
+/// assert_eq!(check_pdf_bytes(encrypted, encrypted), PdfVerdict::PasswordProtected);
+///
+/// let text = b"just some notes";
+/// assert_eq!(check_pdf_bytes(text, text), PdfVerdict::WrongType(None));
+/// let html = b"<html></html>";
+/// assert_eq!(check_pdf_bytes(html, html), PdfVerdict::WrongType(Some(DocumentKind::Html)));
+/// ```
+pub fn check_pdf_bytes(head: &[u8], tail: &[u8]) -> PdfVerdict {
+    let first = &head[..head.len().min(MARKER_LEN)];
+    if !contains(first, b"%PDF-") {
+        return PdfVerdict::WrongType(DocumentKind::from_magic(head));
+    }
+    let last = &tail[tail.len().saturating_sub(MARKER_LEN)..];
+    if !contains(last, b"%%EOF") {
+        return PdfVerdict::Corrupt("the end of file marker is missing".to_string());
+    }
+    if !contains(last, b"startxref") {
+        return PdfVerdict::Corrupt("the cross-reference offset is missing".to_string());
+    }
+    // the trailer is at the end of the file, or at the beginning of a linearized file
+    if contains(tail, b"/Encrypt") || contains(head, b"/Encrypt") {
+        return PdfVerdict::PasswordProtected;
+    }
+    PdfVerdict::Valid
+}
+
+/// Checks the content of a file, see the module documentation
+pub fn check_pdf<P: AsRef<Path>>(path: P) -> io::Result<PdfVerdict> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::new();
+    (&mut file).take(REGION_LEN).read_to_end(&mut head)?;
+    if len <= REGION_LEN {
+        return Ok(check_pdf_bytes(&head, &head));
+    }
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(len - REGION_LEN))?;
+    file.take(REGION_LEN).read_to_end(&mut tail)?;
+    Ok(check_pdf_bytes(&head, &tail))
+}
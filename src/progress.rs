@@ -0,0 +1,212 @@
+//! A module for the reading progress of the books.
+//!
+//! The progress of a book is the page the reader is at, out of the number of pages of the book
+//! when it's known. Each time the page moves past the furthest page reached so far the pages read
+//! are recorded with the time, they're used by the statistics of [`crate::stats`]. A book is
+//! finished when its last page is reached or when it's marked as finished with [`mark_finished`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::progress;
+//! # let connection = book_lib::db::setup();
+//!
+//! let name = "book_name".to_string();
+//! let _ = progress::set_page_count(&connection, &name, Some(320));
+//! if let Ok(progress) = progress::update_progress(&connection, &name, 42, None) {
+//!     println!("{:.0}% read", progress.percent().unwrap_or(0.0));
+//! }
+//! ```
+
+use rusqlite::{params, Connection};
+
+use crate::audit::AuditOp;
+use crate::db;
+use crate::errors::ProgressError;
+
+/// Reading progress of a book
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Progress {
+    /// page the reader is at, None if the book wasn't started
+    pub current_page: Option<u32>,
+    /// number of pages of the book, None if it isn't known
+    pub page_count: Option<u32>,
+    /// when the book was finished, in seconds since the Unix epoch
+    pub finished_at: Option<i64>,
+}
+
+impl Progress {
+    /// Returns how much of the book was read, from 0 to 100, None if the number of pages isn't
+    /// known
+    ///
+    /// ```rust
+    /// use book_lib::progress::Progress;
+    ///
+    /// let progress = Progress {
+    ///     current_page: Some(50),
+    ///     page_count: Some(200),
+    ///     finished_at: None,
+    /// };
+    /// assert_eq!(progress.percent(), Some(25.0));
+    /// assert_eq!(Progress::default().percent(), None);
+    /// ```
+    pub fn percent(&self) -> Option<f64> {
+        let count = self.page_count.filter(|count| *count > 0)?;
+        let page = self.current_page.unwrap_or(0).min(count);
+        Some(page as f64 * 100.0 / count as f64)
+    }
+
+    /// Returns true if the book was finished
+    pub fn is_finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+}
+
+/// Returns the reading progress of a book
+pub fn get_progress(conn: &Connection, name: &String) -> Result<Progress, ProgressError> {
+    match conn.query_row(
+        "SELECT current_page, page_count, finished_at FROM books WHERE name = ? AND deleted_at IS NULL",
+        params![name],
+        |row| {
+            Ok(Progress {
+                current_page: row.get(0)?,
+                page_count: row.get(1)?,
+                finished_at: row.get(2)?,
+            })
+        },
+    ) {
+        Ok(progress) => Ok(progress),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(ProgressError::BookDoesNotExist),
+        Err(_) => Err(ProgressError::DatabaseError),
+    }
+}
+
+/// Sets the number of pages of a book, None if it isn't known
+pub fn set_page_count(
+    conn: &Connection,
+    name: &String,
+    page_count: Option<u32>,
+) -> Result<Progress, ProgressError> {
+    if page_count == Some(0) {
+        return Err(ProgressError::InvalidPageCount);
+    }
+    match db::audited_update(conn, AuditOp::UpdatePageCount, name, "page_count", || {
+        conn.execute(
+            "UPDATE books SET page_count = ? WHERE name = ? AND deleted_at IS NULL",
+            params![page_count, name],
+        )
+    }) {
+        Ok(0) => Err(ProgressError::BookDoesNotExist),
+        Ok(_) => get_progress(conn, name),
+        Err(_) => Err(ProgressError::DatabaseError),
+    }
+}
+
+/// Moves the reader of a book to a page, at `at` (seconds since the Unix epoch) or now if None.
+///
+/// The pages after the furthest page reached so far are recorded as read, going back and reading
+/// them again doesn't count. Reaching the last page finishes the book. The changes are written in
+/// a single transaction.
+pub fn update_progress(
+    conn: &Connection,
+    name: &String,
+    page: u32,
+    at: Option<i64>,
+) -> Result<Progress, ProgressError> {
+    let previous = get_progress(conn, name)?;
+    if previous.page_count.is_some_and(|count| page > count) {
+        return Err(ProgressError::PageOutOfRange(page));
+    }
+    let at = at.unwrap_or_else(db::now);
+    let finished = previous.page_count == Some(page) && !previous.is_finished();
+    let write = || -> rusqlite::Result<()> {
+        let savepoint = db::Savepoint::new(conn)?;
+        db::audited_update(conn, AuditOp::UpdateProgress, name, "current_page", || {
+            conn.execute(
+                "UPDATE books SET current_page = ? WHERE name = ? AND deleted_at IS NULL",
+                params![page, name],
+            )
+        })?;
+        let book_id = db::get_book_id(conn, name)?;
+        // the pages recorded so far add up to the furthest page reached
+        let furthest: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(pages), 0) FROM page_events WHERE book_id = ?",
+            params![book_id],
+            |row| row.get(0),
+        )?;
+        let read = page as i64 - furthest;
+        if read > 0 {
+            conn.execute(
+                "INSERT INTO page_events (book_id, at, pages) VALUES (?, ?, ?)",
+                params![book_id, at, read],
+            )?;
+        }
+        if finished {
+            db::audited_update(conn, AuditOp::UpdateFinished, name, "finished_at", || {
+                conn.execute(
+                    "UPDATE books SET finished_at = ? WHERE name = ? AND deleted_at IS NULL",
+                    params![at, name],
+                )
+            })?;
+        }
+        savepoint.release()
+    };
+    if write().is_err() {
+        return Err(ProgressError::DatabaseError);
+    }
+    get_progress(conn, name)
+}
+
+/// Marks a book as finished at `at` or now if None, or as not finished if `finished` is false
+pub fn mark_finished(
+    conn: &Connection,
+    name: &String,
+    finished: bool,
+    at: Option<i64>,
+) -> Result<Progress, ProgressError> {
+    let finished_at = match finished {
+        true => Some(at.unwrap_or_else(db::now)),
+        false => None,
+    };
+    match db::audited_update(conn, AuditOp::UpdateFinished, name, "finished_at", || {
+        conn.execute(
+            "UPDATE books SET finished_at = ? WHERE name = ? AND deleted_at IS NULL",
+            params![finished_at, name],
+        )
+    }) {
+        Ok(0) => Err(ProgressError::BookDoesNotExist),
+        Ok(_) => get_progress(conn, name),
+        Err(_) => Err(ProgressError::DatabaseError),
+    }
+}
+
+/// Clears the progress of a book and the pages recorded as read
+pub fn reset_progress(conn: &Connection, name: &String) -> Result<(), ProgressError> {
+    let reset = || -> rusqlite::Result<usize> {
+        let savepoint = db::Savepoint::new(conn)?;
+        let updated =
+            db::audited_update(conn, AuditOp::UpdateProgress, name, "current_page", || {
+                conn.execute(
+                    "UPDATE books SET current_page = NULL WHERE name = ? AND deleted_at IS NULL",
+                    params![name],
+                )
+            })?;
+        db::audited_update(conn, AuditOp::UpdateFinished, name, "finished_at", || {
+            conn.execute(
+                "UPDATE books SET finished_at = NULL WHERE name = ? AND deleted_at IS NULL",
+                params![name],
+            )
+        })?;
+        conn.execute(
+            "DELETE FROM page_events WHERE book_id IN (SELECT id FROM books WHERE name = ? AND deleted_at IS NULL)",
+            params![name],
+        )?;
+        savepoint.release()?;
+        Ok(updated)
+    };
+    match reset() {
+        Ok(0) => Err(ProgressError::BookDoesNotExist),
+        Ok(_) => Ok(()),
+        Err(_) => Err(ProgressError::DatabaseError),
+    }
+}
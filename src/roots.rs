@@ -0,0 +1,266 @@
+//! A module for storing paths relative to named library roots.
+//!
+//! A root is a directory with a name, e.g. `papers` for `/home/me/papers`. The path of a book
+//! inside a root is stored as `@papers/foo.pdf` instead of the absolute path, so the same
+//! database can be used on several machines (or after the home directory moved) as long as each
+//! machine knows where its `papers` root is.
+//!
+//! Roots aren't stored in the database since they differ between machines, they're resolved at
+//! runtime from the following sources, a later one overriding an earlier one:
+//! 1. the file `$HOME/.config/book-cli/roots`, one `name = path` per line, `#` starts a comment
+//! 2. the `BOOK_LIB_ROOTS` environment variable, a list of `name=path` separated like `PATH`
+//!    (`:` on Unix, `;` on Windows)
+//! 3. the roots added with [`add_root`]
+//!
+//! Books are always returned with their resolved absolute path. A path is stored relative to a
+//! root when the book is created or its path is updated, and [`relativize_paths`] converts the
+//! paths stored before a root was added. A book whose root isn't known on the current machine
+//! keeps its `@name/...` path and is reported as missing by the health checks.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::roots;
+//! # let connection = book_lib::db::setup();
+//!
+//! let _ = roots::add_root("papers", "/home/me/papers");
+//! let _ = roots::save_roots();
+//! if let Ok(names) = roots::relativize_paths(&connection) {
+//!     println!("{} books are now portable", names.len());
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+use rusqlite::{params, Connection};
+
+use crate::audit::AuditOp;
+use crate::db;
+use crate::errors::RootError;
+use crate::help;
+
+/// Environment variable with the roots of the current machine
+pub const ROOTS_ENV: &str = "BOOK_LIB_ROOTS";
+
+/// Prefix of the paths stored relative to a root
+pub const ROOT_PREFIX: char = '@';
+
+/// A named library root
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Root {
+    pub name: String,
+    /// absolute path to the root on the current machine
+    pub path: PathBuf,
+}
+
+fn registry() -> &'static RwLock<BTreeMap<String, PathBuf>> {
+    static ROOTS: OnceLock<RwLock<BTreeMap<String, PathBuf>>> = OnceLock::new();
+    ROOTS.get_or_init(|| {
+        let mut roots = BTreeMap::new();
+        if let Some(file) = roots_file() {
+            if let Ok(content) = fs::read_to_string(file) {
+                roots.extend(parse_roots_file(&content));
+            }
+        }
+        if let Some(value) = std::env::var_os(ROOTS_ENV) {
+            for entry in std::env::split_paths(&value) {
+                if let Some((name, path)) = help::path_to_string(entry).split_once('=') {
+                    if is_valid_name(name.trim()) {
+                        roots.insert(name.trim().to_string(), help::path_from_string(path.trim()));
+                    }
+                }
+            }
+        }
+        RwLock::new(roots)
+    })
+}
+
+/// Path to the file with the roots of the current machine
+pub fn roots_file() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config").join("book-cli").join("roots"))
+}
+
+fn parse_roots_file(content: &str) -> Vec<(String, PathBuf)> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, path)| (name.trim().to_string(), PathBuf::from(path.trim())))
+        .filter(|(name, _)| is_valid_name(name))
+        .collect()
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Adds a root for the current process, replacing the root with the same name if any.
+///
+/// The root is stored by its canonical path, call [`save_roots`] to keep it for the next runs.
+pub fn add_root<P: AsRef<Path>>(name: &str, path: P) -> Result<Root, RootError> {
+    if !is_valid_name(name) {
+        return Err(RootError::InvalidName(name.to_string()));
+    }
+    let path = match help::canonical_path(&help::path_to_string(path)) {
+        Ok(path) if path.is_dir() => path,
+        _ => return Err(RootError::PathDoesNotExist),
+    };
+    let mut roots = registry().write().unwrap_or_else(|err| err.into_inner());
+    roots.insert(name.to_string(), path.clone());
+    Ok(Root {
+        name: name.to_string(),
+        path,
+    })
+}
+
+/// Removes a root from the current process
+pub fn remove_root(name: &str) -> Result<(), RootError> {
+    let mut roots = registry().write().unwrap_or_else(|err| err.into_inner());
+    match roots.remove(name) {
+        Some(_) => Ok(()),
+        None => Err(RootError::UnknownRoot(name.to_string())),
+    }
+}
+
+/// Returns the roots known on the current machine, sorted by name
+pub fn get_roots() -> Vec<Root> {
+    let roots = registry().read().unwrap_or_else(|err| err.into_inner());
+    roots
+        .iter()
+        .map(|(name, path)| Root {
+            name: name.clone(),
+            path: path.clone(),
+        })
+        .collect()
+}
+
+/// Writes the current roots to the roots file of the machine, see [`roots_file`]
+pub fn save_roots() -> Result<(), RootError> {
+    let file = match roots_file() {
+        Some(file) => file,
+        None => return Err(RootError::CouldNotWriteFile),
+    };
+    let content: String = get_roots()
+        .iter()
+        .map(|root| format!("{} = {}\n", root.name, help::path_to_string(&root.path)))
+        .collect();
+    let written = match file.parent() {
+        Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(&file, content)),
+        None => fs::write(&file, content),
+    };
+    match written {
+        Ok(_) => Ok(()),
+        Err(_) => Err(RootError::CouldNotWriteFile),
+    }
+}
+
+/// Resolves a stored path: `@name/rest` becomes the path of the root `name` joined with `rest`,
+/// absolute paths and paths of unknown roots are returned unchanged.
+pub fn resolve_path(stored: &str) -> String {
+    let relative = match stored.strip_prefix(ROOT_PREFIX) {
+        Some(relative) => relative,
+        None => return stored.to_string(),
+    };
+    let (name, rest) = relative.split_once('/').unwrap_or((relative, ""));
+    let roots = registry().read().unwrap_or_else(|err| err.into_inner());
+    match roots.get(name) {
+        Some(root) => {
+            let mut path = root.clone();
+            path.extend(rest.split('/').filter(|comp| !comp.is_empty()));
+            help::path_to_string(path)
+        }
+        None => stored.to_string(),
+    }
+}
+
+/// Returns the path to store for a file: `@name/rest` if it's inside a known root (the deepest
+/// one if roots are nested), the path unchanged otherwise.
+///
+/// ```rust
+/// use book_lib::roots;
+///
+/// let dir = std::env::temp_dir().canonicalize().unwrap();
+/// roots::add_root("tmp", &dir).unwrap();
+/// let file = dir.join("notes").join("a.pdf").to_string_lossy().to_string();
+/// let stored = roots::portable_path(&file);
+/// assert_eq!(stored, "@tmp/notes/a.pdf");
+/// assert_eq!(roots::resolve_path(&stored), file);
+/// assert_eq!(roots::resolve_path("@unknown/a.pdf"), "@unknown/a.pdf");
+/// ```
+pub fn portable_path(path: &str) -> String {
+    if path.starts_with(ROOT_PREFIX) {
+        return path.to_string();
+    }
+    let path_buf = help::path_from_string(path);
+    let canonical = help::canonical_path(path).ok();
+    let roots = registry().read().unwrap_or_else(|err| err.into_inner());
+    let best = roots
+        .iter()
+        .filter_map(|(name, root)| {
+            let rest = path_buf
+                .strip_prefix(root)
+                .ok()
+                .or_else(|| canonical.as_ref()?.strip_prefix(root).ok())?;
+            Some((root.components().count(), name, rest))
+        })
+        .max_by_key(|(depth, _, _)| *depth);
+    match best {
+        Some((_, name, rest)) => {
+            let comps: Vec<String> = rest
+                .components()
+                .map(|comp| help::path_to_string(comp.as_os_str()))
+                .collect();
+            format!("{}{}/{}", ROOT_PREFIX, name, comps.join("/"))
+        }
+        None => path.to_string(),
+    }
+}
+
+/// Stores the absolute paths that are inside a known root relative to it.
+///
+/// Returns the names of the converted books, every path is updated in a single transaction.
+pub fn relativize_paths(conn: &Connection) -> Result<Vec<String>, RootError> {
+    let stored = || -> rusqlite::Result<Vec<(String, String)>> {
+        let mut stmt =
+            conn.prepare("SELECT name, path FROM books WHERE deleted_at IS NULL ORDER BY name")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect();
+        rows
+    };
+    let books = match stored() {
+        Ok(books) => books,
+        Err(_) => return Err(RootError::CouldNotReadBooks),
+    };
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(RootError::DatabaseError),
+    };
+    let mut converted = Vec::new();
+    for (name, path) in books {
+        let portable = portable_path(&path);
+        if portable == path {
+            continue;
+        }
+        let updated = db::audited_update(conn, AuditOp::UpdatePath, &name, "path", || {
+            conn.execute(
+                "UPDATE books SET path = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+                params![portable, name],
+            )
+        });
+        if updated.is_err() {
+            return Err(RootError::DatabaseError);
+        }
+        converted.push(name);
+    }
+    match savepoint.release() {
+        Ok(_) => Ok(converted),
+        Err(_) => Err(RootError::DatabaseError),
+    }
+}
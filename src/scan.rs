@@ -0,0 +1,321 @@
+//! A module for registering every document of a directory tree at once.
+//!
+//! The scanner walks a directory recursively and creates a book for each document it finds, with
+//! the same validation as [`crate::create_book`]. Books are named after their file name (a ` (2)`,
+//! ` (3)`, ... suffix is added on collision), and all of them are inserted in a single
+//! transaction.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::scan::{scan_directory, ScanOptions};
+//! # let connection = book_lib::db::setup();
+//!
+//! let options = ScanOptions {
+//!     exclude: vec!["drafts/**".to_string()],
+//!     sections_from_dirs: true,
+//!     ..Default::default()
+//! };
+//! let report = scan_directory(&connection, "/home/me/papers", &options);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rusqlite::Connection;
+use walkdir::WalkDir;
+
+use crate::book::Book;
+use crate::db;
+use crate::errors::{CreateBookError, ScanError};
+use crate::help;
+use crate::import::unique_name;
+use crate::kind::{self, DocumentKind};
+
+/// What the scanner does with symbolic links
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SymlinkPolicy {
+    /// ignore symlinked files and directories
+    #[default]
+    Skip,
+    /// follow symlinks, link cycles are reported as errors
+    Follow,
+}
+
+/// Options of [`scan_directory`]
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanOptions {
+    /// globs relative to the scanned directory, a file is registered only if it matches one of
+    /// them, every document is registered when empty
+    pub include: Vec<String>,
+    /// globs relative to the scanned directory, matching files and directories are skipped
+    pub exclude: Vec<String>,
+    pub symlinks: SymlinkPolicy,
+    /// maximum depth of the walk, None for no limit
+    pub max_depth: Option<usize>,
+    /// use the directory of a file relative to the scanned directory (e.g. `math/algebra`) as
+    /// its section
+    pub sections_from_dirs: bool,
+    /// section of the books that don't get one from their directory
+    pub section: Option<String>,
+    /// build the report without writing anything to the database
+    pub dry_run: bool,
+}
+
+/// What happened to a document found by the scanner
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScanOutcome {
+    /// the book was created, or would be for a dry run
+    Created,
+    /// the file is already registered as the book with the given name
+    AlreadyRegistered(String),
+    /// the book couldn't be created
+    Failed(CreateBookError),
+}
+
+/// A report line for a document found by the scanner
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScannedFile {
+    pub path: String,
+    /// name given to the book
+    pub name: String,
+    pub section: Option<String>,
+    pub outcome: ScanOutcome,
+}
+
+/// Report of a directory scan
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanReport {
+    /// documents found by the scanner
+    pub files: Vec<ScannedFile>,
+    /// files and directories skipped by the exclude globs or missing the include globs
+    pub excluded: Vec<String>,
+    /// number of files whose kind isn't allowed
+    pub ignored: usize,
+    /// errors met while walking the directory (permissions, symlink cycles, ...)
+    pub errors: Vec<String>,
+    /// true if the scan was a dry run and nothing was written
+    pub dry_run: bool,
+}
+
+impl ScanReport {
+    /// Files that were registered by the scan
+    pub fn created(&self) -> impl Iterator<Item = &ScannedFile> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.outcome, ScanOutcome::Created))
+    }
+}
+
+fn build_globs(globs: &[String]) -> Result<GlobSet, ScanError> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        match Glob::new(glob) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(_) => return Err(ScanError::InvalidGlob(glob.clone())),
+        }
+    }
+    match builder.build() {
+        Ok(set) => Ok(set),
+        Err(err) => Err(ScanError::InvalidGlob(err.to_string())),
+    }
+}
+
+/// Returns the name of a book from the name of its file, without the extension
+fn name_from_path(path: &Path) -> String {
+    match path.file_stem() {
+        Some(stem) => stem.to_string_lossy().to_string(),
+        None => path.to_string_lossy().to_string(),
+    }
+}
+
+/// Returns the directory of a file relative to the scanned directory, with `/` separators
+fn section_from_path(relative: &Path) -> Option<String> {
+    let dirs: Vec<String> = relative
+        .parent()?
+        .components()
+        .map(|comp| comp.as_os_str().to_string_lossy().to_string())
+        .collect();
+    if dirs.is_empty() {
+        None
+    } else {
+        Some(dirs.join("/"))
+    }
+}
+
+/// Walks the directory `root` and registers every document of an allowed kind (see [`crate::kind`])
+/// it contains according to the options.
+///
+/// Files that are already registered (by path) are reported and left untouched, so a directory
+/// can be scanned again to pick up new files. Either every new book is inserted or, on a
+/// database error, none of them.
+pub fn scan_directory<P: AsRef<Path>>(
+    conn: &Connection,
+    root: P,
+    options: &ScanOptions,
+) -> Result<ScanReport, ScanError> {
+    let mut report = plan_scan(conn, root, options, &mut HashSet::new())?;
+    if options.dry_run {
+        return Ok(report);
+    }
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(ScanError::DatabaseError),
+    };
+    apply_scan(conn, &mut report)?;
+    match savepoint.release() {
+        Ok(_) => Ok(report),
+        Err(_) => Err(ScanError::DatabaseError),
+    }
+}
+
+/// Walks the directory and returns the report of a dry run: the files that can be registered
+/// have the [`ScanOutcome::Created`] outcome, see [`apply_scan`].
+///
+/// The names given to the books aren't used by the books of the database nor by `taken`, they
+/// are added to `taken`.
+pub(crate) fn plan_scan<P: AsRef<Path>>(
+    conn: &Connection,
+    root: P,
+    options: &ScanOptions,
+    taken: &mut HashSet<String>,
+) -> Result<ScanReport, ScanError> {
+    let root = match help::canonical_path(&help::path_to_string(root)) {
+        Ok(root) if root.is_dir() => root,
+        _ => return Err(ScanError::RootDoesNotExist),
+    };
+    let include = build_globs(&options.include)?;
+    let allowed = kind::get_allowed_kinds(conn).unwrap_or(vec![DocumentKind::Pdf]);
+    let exclude = build_globs(&options.exclude)?;
+    // registered books by both their stored and their canonical path
+    let mut registered: HashMap<PathBuf, Book> = HashMap::new();
+    for bk in db::get_books(conn).unwrap_or_default() {
+        if let Ok(canonical) = help::canonical_path(&bk.path) {
+            registered.insert(canonical, bk.clone());
+        }
+        registered.insert(bk.file_path(), bk);
+    }
+
+    let mut report = ScanReport {
+        dry_run: true,
+        ..Default::default()
+    };
+    let mut walker = WalkDir::new(&root)
+        .follow_links(options.symlinks == SymlinkPolicy::Follow)
+        .sort_by_file_name();
+    if let Some(depth) = options.max_depth {
+        walker = walker.max_depth(depth);
+    }
+    let relative =
+        |path: &Path| -> PathBuf { path.strip_prefix(&root).unwrap_or(path).to_path_buf() };
+    let mut excluded = Vec::new();
+    let walk = walker.into_iter().filter_entry(|entry| {
+        if options.symlinks == SymlinkPolicy::Skip && entry.path_is_symlink() {
+            return false;
+        }
+        let rel = relative(entry.path());
+        if entry.depth() > 0 && exclude.is_match(&rel) {
+            excluded.push(help::path_to_string(entry.path()));
+            return false;
+        }
+        true
+    });
+
+    let mut candidates = Vec::new();
+    for entry_res in walk {
+        let entry = match entry_res {
+            Ok(entry) => entry,
+            Err(err) => {
+                report.errors.push(err.to_string());
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = help::path_to_string(entry.path());
+        if !DocumentKind::detect(entry.path()).is_some_and(|kind| allowed.contains(&kind)) {
+            report.ignored += 1;
+            continue;
+        }
+        let rel = relative(entry.path());
+        if !options.include.is_empty() && !include.is_match(&rel) {
+            report.excluded.push(path);
+            continue;
+        }
+        candidates.push((entry.path().to_path_buf(), rel));
+    }
+    report.excluded.extend(excluded);
+
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    for (path, rel) in candidates {
+        let path_str = help::path_to_string(&path);
+        let section = if options.sections_from_dirs {
+            section_from_path(&rel).or(options.section.clone())
+        } else {
+            options.section.clone()
+        };
+        // with followed symlinks the same file can be reached through several paths
+        let canonical = help::canonical_path(&path_str).unwrap_or(path.clone());
+        if !seen.insert(canonical.clone()) {
+            continue;
+        }
+        if let Some(bk) = registered.get(&path).or(registered.get(&canonical)) {
+            report.files.push(ScannedFile {
+                path: path_str,
+                name: bk.name.clone(),
+                section: bk.section.clone(),
+                outcome: ScanOutcome::AlreadyRegistered(bk.name.clone()),
+            });
+            continue;
+        }
+        let name = unique_name(conn, &name_from_path(&path), taken);
+        let bk = Book::init(name.clone(), path_str.clone(), section.clone(), false);
+        let outcome = match crate::validate_book(conn, &bk) {
+            Ok(_) => {
+                taken.insert(name.clone());
+                ScanOutcome::Created
+            }
+            Err(CreateBookError::OtherError) => return Err(ScanError::DatabaseError),
+            Err(err) => ScanOutcome::Failed(err),
+        };
+        report.files.push(ScannedFile {
+            path: path_str,
+            name,
+            section,
+            outcome,
+        });
+    }
+    Ok(report)
+}
+
+/// Creates the books planned by [`plan_scan`], the files that can't be registered anymore get
+/// the [`ScanOutcome::Failed`] outcome.
+pub(crate) fn apply_scan(conn: &Connection, report: &mut ScanReport) -> Result<(), ScanError> {
+    let planned = report
+        .files
+        .iter_mut()
+        .filter(|file| matches!(file.outcome, ScanOutcome::Created));
+    for file in planned {
+        let bk = Book::init(
+            file.name.clone(),
+            file.path.clone(),
+            file.section.clone(),
+            false,
+        );
+        match crate::create_book(conn, &bk) {
+            Ok(_) => {}
+            Err(CreateBookError::OtherError) => return Err(ScanError::DatabaseError),
+            Err(err) => file.outcome = ScanOutcome::Failed(err),
+        }
+    }
+    report.dry_run = false;
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! A module for using the library from several threads and processes.
+//!
+//! Several processes can use the library at the same time: [`crate::db::setup`] opens the
+//! database in write-ahead log mode with a busy timeout, the changes are written in immediate
+//! transactions and the ones refused because the database is locked are tried again a few times,
+//! waiting longer each time.
+//!
+//! In a single process, the handles of the library can be used from threads as follows:
+//! - [`rusqlite::Connection`] is `Send` but not `Sync`: it can be moved to another thread, not
+//!   used by two threads at once. Every thread can open its own with [`crate::db::setup`];
+//! - [`SharedConnection`] is `Send`, `Sync` and `Clone`: a single connection behind a mutex, for
+//!   the applications that pass the library around between threads;
+//! - [`crate::notify::ChangeWatcher`] is `Send`, its callbacks must be `Send` too;
+//! - [`crate::batch::Batch`] borrows the connection, it can't leave the thread running the batch;
+//! - the values returned by the library ([`crate::book::Book`], the reports and the errors) are
+//!   `Send` and `Sync`.
+//!
+//! ```rust
+//! fn assert_send<T: Send>() {}
+//! fn assert_sync<T: Sync>() {}
+//!
+//! assert_send::<rusqlite::Connection>();
+//! assert_send::<book_lib::shared::SharedConnection>();
+//! assert_sync::<book_lib::shared::SharedConnection>();
+//! assert_send::<book_lib::notify::ChangeWatcher>();
+//! assert_send::<book_lib::book::Book>();
+//! assert_sync::<book_lib::book::Book>();
+//! assert_send::<book_lib::batch::BatchReport>();
+//! assert_sync::<book_lib::batch::BatchReport>();
+//! assert_send::<book_lib::errors::BatchError>();
+//! assert_sync::<book_lib::errors::BatchError>();
+//! ```
+//!
+//! A connection can't be shared between threads without a [`SharedConnection`]:
+//! ```rust,compile_fail
+//! fn assert_sync<T: Sync>() {}
+//!
+//! assert_sync::<rusqlite::Connection>();
+//! ```
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::shared::SharedConnection;
+//!
+//! let shared = SharedConnection::setup();
+//! let handles: Vec<_> = (0..4)
+//!     .map(|i| {
+//!         let shared = shared.clone();
+//!         std::thread::spawn(move || {
+//!             shared.with(|conn| book_lib::update_favourite(conn, &format!("book_{}", i), true))
+//!         })
+//!     })
+//!     .collect();
+//! for handle in handles {
+//!     let _ = handle.join();
+//! }
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rusqlite::Connection;
+
+use crate::db;
+
+/// A connection to the library that can be cloned and shared between threads.
+///
+/// The clones use the same connection, a thread waits for the others to be done with it.
+#[derive(Clone, Debug)]
+pub struct SharedConnection {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SharedConnection {
+    /// Shares a connection, it should be configured with [`db::configure`]
+    pub fn new(conn: Connection) -> SharedConnection {
+        SharedConnection {
+            conn: Arc::new(Mutex::new(conn)),
+        }
+    }
+
+    /// Opens the library database, see [`db::setup`]
+    pub fn setup() -> SharedConnection {
+        SharedConnection::new(db::setup())
+    }
+
+    /// Locks the connection until the guard is dropped.
+    ///
+    /// A thread that panicked while holding the lock doesn't make the connection unusable: a
+    /// change it didn't finish was rolled back.
+    pub fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Runs `f` with the connection locked, e.g. `shared.with(|conn| book_lib::get_books(conn))`
+    pub fn with<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&Connection) -> T,
+    {
+        f(&self.lock())
+    }
+}
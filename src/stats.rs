@@ -0,0 +1,412 @@
+//! A module for reading statistics and goals.
+//!
+//! The statistics are computed by SQLite from the reading history ([`crate::history`]) and the
+//! progress of the books ([`crate::progress`]), so they stay fast on large histories. The books in
+//! the trash ([`crate::trash`]) are left out. Days, weeks (starting on Monday), months and years
+//! are local to the machine.
+//!
+//! A goal is a target for a period, e.g. 12 books finished a year or 30 minutes of reading a day.
+//! [`goal_progress`] tells how far each goal is in the current period.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::stats::{self, Goal, GoalMetric, GoalPeriod};
+//! # let connection = book_lib::db::setup();
+//!
+//! let goal = Goal::init("daily reading".to_string(), GoalMetric::ReadingMinutes, GoalPeriod::Day, 30);
+//! let _ = stats::add_goal(&connection, &goal);
+//! for progress in stats::goal_progress(&connection, None).unwrap_or_default() {
+//!     println!("{}: {}/{}", progress.goal.name, progress.current, progress.goal.target);
+//! }
+//! ```
+
+use rusqlite::{params, Connection};
+
+use crate::db;
+use crate::errors::StatsError;
+
+/// Total of a month (`YYYY-MM`) or of a week (`YYYY-MM-DD` of its Monday)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeriodTotal {
+    pub period: String,
+    pub total: i64,
+}
+
+/// Reading streaks, in consecutive days with a reading session or pages read
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Streaks {
+    /// the streak going on, that is the one that ended today or yesterday
+    pub current: i64,
+    pub longest: i64,
+}
+
+/// Reading time of a section
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SectionTime {
+    /// name of the section, empty for the books without a section
+    pub section: String,
+    /// total known reading time in seconds
+    pub seconds: i64,
+    /// number of reading sessions
+    pub opens: i64,
+}
+
+fn collect_rows<T>(
+    conn: &Connection,
+    sql: &str,
+    params: impl rusqlite::Params,
+    from_row: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+) -> Result<Vec<T>, StatsError> {
+    let query = || -> rusqlite::Result<Vec<T>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params, from_row)?.collect();
+        rows
+    };
+    match query() {
+        Ok(rows) => Ok(rows),
+        Err(_) => Err(StatsError::DatabaseError),
+    }
+}
+
+fn period_total(row: &rusqlite::Row) -> rusqlite::Result<PeriodTotal> {
+    Ok(PeriodTotal {
+        period: row.get(0)?,
+        total: row.get(1)?,
+    })
+}
+
+/// Returns the number of books finished each month, in order
+pub fn books_finished_per_month(conn: &Connection) -> Result<Vec<PeriodTotal>, StatsError> {
+    collect_rows(
+        conn,
+        "SELECT strftime('%Y-%m', finished_at, 'unixepoch', 'localtime') AS month, COUNT(*)
+        FROM books WHERE finished_at IS NOT NULL AND deleted_at IS NULL GROUP BY month ORDER BY month",
+        [],
+        period_total,
+    )
+}
+
+/// Returns the number of pages read each week, in order
+pub fn pages_read_per_week(conn: &Connection) -> Result<Vec<PeriodTotal>, StatsError> {
+    collect_rows(
+        conn,
+        "SELECT date(at, 'unixepoch', 'localtime', 'start of day', 'weekday 0', '-6 days') AS week,
+            SUM(pages)
+        FROM page_events JOIN books ON books.id = page_events.book_id
+        WHERE books.deleted_at IS NULL
+        GROUP BY week ORDER BY week",
+        [],
+        period_total,
+    )
+}
+
+/// Returns the reading streaks at `at` (seconds since the Unix epoch), now if None
+pub fn reading_streaks(conn: &Connection, at: Option<i64>) -> Result<Streaks, StatsError> {
+    let at = at.unwrap_or_else(db::now);
+    match conn.query_row(
+        "WITH days AS (
+            SELECT date(opened_at, 'unixepoch', 'localtime') AS day
+            FROM reading_history JOIN books ON books.id = reading_history.book_id
+            WHERE books.deleted_at IS NULL
+            UNION
+            SELECT date(at, 'unixepoch', 'localtime')
+            FROM page_events JOIN books ON books.id = page_events.book_id
+            WHERE books.deleted_at IS NULL
+        ),
+        islands AS (
+            SELECT day, julianday(day) - ROW_NUMBER() OVER (ORDER BY day) AS island
+            FROM days WHERE day <= date(?1, 'unixepoch', 'localtime')
+        ),
+        streaks AS (
+            SELECT MAX(day) AS last_day, COUNT(*) AS length FROM islands GROUP BY island
+        )
+        SELECT
+            COALESCE(MAX(CASE WHEN last_day >= date(?1, 'unixepoch', 'localtime', '-1 day')
+                THEN length END), 0),
+            COALESCE(MAX(length), 0)
+        FROM streaks",
+        params![at],
+        |row| {
+            Ok(Streaks {
+                current: row.get(0)?,
+                longest: row.get(1)?,
+            })
+        },
+    ) {
+        Ok(streaks) => Ok(streaks),
+        Err(_) => Err(StatsError::DatabaseError),
+    }
+}
+
+/// Returns the reading time of each section, the most read first
+pub fn time_per_section(conn: &Connection) -> Result<Vec<SectionTime>, StatsError> {
+    collect_rows(
+        conn,
+        "SELECT COALESCE(section, '') AS sec, COALESCE(SUM(duration), 0) AS seconds, COUNT(*)
+        FROM books JOIN reading_history ON reading_history.book_id = books.id
+        WHERE books.deleted_at IS NULL
+        GROUP BY sec ORDER BY seconds DESC, sec",
+        [],
+        |row| {
+            Ok(SectionTime {
+                section: row.get(0)?,
+                seconds: row.get(1)?,
+                opens: row.get(2)?,
+            })
+        },
+    )
+}
+
+/// What a goal counts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum GoalMetric {
+    BooksFinished,
+    PagesRead,
+    ReadingMinutes,
+}
+
+impl GoalMetric {
+    /// Returns the name under which the metric is stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoalMetric::BooksFinished => "books_finished",
+            GoalMetric::PagesRead => "pages_read",
+            GoalMetric::ReadingMinutes => "reading_minutes",
+        }
+    }
+
+    /// Returns the metric with the given stored name
+    pub fn from_name(name: &str) -> Option<GoalMetric> {
+        [
+            GoalMetric::BooksFinished,
+            GoalMetric::PagesRead,
+            GoalMetric::ReadingMinutes,
+        ]
+        .into_iter()
+        .find(|metric| metric.as_str() == name)
+    }
+
+    /// Query of the metric between two times
+    fn sql(&self) -> &'static str {
+        match self {
+            GoalMetric::BooksFinished => {
+                "SELECT COUNT(*) FROM books WHERE finished_at >= ?1 AND finished_at < ?2 AND deleted_at IS NULL"
+            }
+            GoalMetric::PagesRead => {
+                "SELECT COALESCE(SUM(pages), 0)
+                FROM page_events JOIN books ON books.id = page_events.book_id
+                WHERE at >= ?1 AND at < ?2 AND books.deleted_at IS NULL"
+            }
+            GoalMetric::ReadingMinutes => {
+                "SELECT COALESCE(SUM(duration), 0) / 60
+                FROM reading_history JOIN books ON books.id = reading_history.book_id
+                WHERE opened_at >= ?1 AND opened_at < ?2 AND books.deleted_at IS NULL"
+            }
+        }
+    }
+}
+
+/// Period a goal is reached in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum GoalPeriod {
+    Day,
+    /// a week starting on Monday
+    Week,
+    Month,
+    Year,
+}
+
+impl GoalPeriod {
+    /// Returns the name under which the period is stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoalPeriod::Day => "day",
+            GoalPeriod::Week => "week",
+            GoalPeriod::Month => "month",
+            GoalPeriod::Year => "year",
+        }
+    }
+
+    /// Returns the period with the given stored name
+    pub fn from_name(name: &str) -> Option<GoalPeriod> {
+        [
+            GoalPeriod::Day,
+            GoalPeriod::Week,
+            GoalPeriod::Month,
+            GoalPeriod::Year,
+        ]
+        .into_iter()
+        .find(|period| period.as_str() == name)
+    }
+
+    /// SQLite date modifiers to the start of the period and to the start of the next one
+    fn modifiers(&self) -> (&'static str, &'static str) {
+        match self {
+            GoalPeriod::Day => ("'start of day'", "'+1 day'"),
+            GoalPeriod::Week => ("'start of day', 'weekday 0', '-6 days'", "'+7 days'"),
+            GoalPeriod::Month => ("'start of month'", "'+1 month'"),
+            GoalPeriod::Year => ("'start of year'", "'+1 year'"),
+        }
+    }
+}
+
+/// A reading goal
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Goal {
+    pub name: String,
+    pub metric: GoalMetric,
+    pub period: GoalPeriod,
+    /// amount to reach in each period, in the unit of the metric
+    pub target: u32,
+}
+
+impl Goal {
+    pub fn init(name: String, metric: GoalMetric, period: GoalPeriod, target: u32) -> Goal {
+        Goal {
+            name,
+            metric,
+            period,
+            target,
+        }
+    }
+}
+
+/// Progress of a goal in its current period
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GoalProgress {
+    pub goal: Goal,
+    /// amount reached in the period
+    pub current: i64,
+    /// start of the period, in seconds since the Unix epoch
+    pub period_start: i64,
+    /// start of the next period
+    pub period_end: i64,
+}
+
+impl GoalProgress {
+    /// Returns true if the target is reached
+    pub fn is_reached(&self) -> bool {
+        self.current >= self.goal.target as i64
+    }
+
+    /// Returns the progress from 0 to 100
+    ///
+    /// ```rust
+    /// use book_lib::stats::{Goal, GoalMetric, GoalPeriod, GoalProgress};
+    ///
+    /// let goal = Goal::init("books".to_string(), GoalMetric::BooksFinished, GoalPeriod::Year, 12);
+    /// let progress = GoalProgress { goal, current: 3, period_start: 0, period_end: 0 };
+    /// assert_eq!(progress.percent(), 25.0);
+    /// assert!(!progress.is_reached());
+    /// ```
+    pub fn percent(&self) -> f64 {
+        let target = self.goal.target.max(1) as f64;
+        (self.current as f64 * 100.0 / target).min(100.0)
+    }
+}
+
+/// Adds a goal
+pub fn add_goal(conn: &Connection, goal: &Goal) -> Result<(), StatsError> {
+    if goal.target == 0 {
+        return Err(StatsError::InvalidTarget);
+    }
+    if get_goals(conn)?.iter().any(|other| other.name == goal.name) {
+        return Err(StatsError::GoalAlreadyExists(goal.name.clone()));
+    }
+    match conn.execute(
+        "INSERT INTO goals (name, metric, period, target) VALUES (?, ?, ?, ?)",
+        params![
+            goal.name,
+            goal.metric.as_str(),
+            goal.period.as_str(),
+            goal.target
+        ],
+    ) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(StatsError::DatabaseError),
+    }
+}
+
+/// Removes a goal
+pub fn remove_goal(conn: &Connection, name: &str) -> Result<(), StatsError> {
+    match conn.execute("DELETE FROM goals WHERE name = ?", params![name]) {
+        Ok(0) => Err(StatsError::GoalDoesNotExist(name.to_string())),
+        Ok(_) => Ok(()),
+        Err(_) => Err(StatsError::DatabaseError),
+    }
+}
+
+/// Returns every goal, in the order they were added
+pub fn get_goals(conn: &Connection) -> Result<Vec<Goal>, StatsError> {
+    let rows = collect_rows(
+        conn,
+        "SELECT name, metric, period, target FROM goals ORDER BY id",
+        [],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, u32>(3)?,
+            ))
+        },
+    )?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(name, metric, period, target)| {
+            Some(Goal::init(
+                name,
+                GoalMetric::from_name(&metric)?,
+                GoalPeriod::from_name(&period)?,
+                target,
+            ))
+        })
+        .collect())
+}
+
+/// Returns the progress of a goal in the period that contains `at`
+fn evaluate_goal(conn: &Connection, goal: &Goal, at: i64) -> rusqlite::Result<GoalProgress> {
+    let (start, length) = goal.period.modifiers();
+    let (period_start, period_end): (i64, i64) = conn.query_row(
+        &format!(
+            "SELECT CAST(strftime('%s', ?1, 'unixepoch', 'localtime', {start}, 'utc') AS INTEGER),
+                CAST(strftime('%s', ?1, 'unixepoch', 'localtime', {start}, {length}, 'utc') AS INTEGER)",
+        ),
+        params![at],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let current = conn.query_row(
+        goal.metric.sql(),
+        params![period_start, period_end],
+        |row| row.get(0),
+    )?;
+    Ok(GoalProgress {
+        goal: goal.clone(),
+        current,
+        period_start,
+        period_end,
+    })
+}
+
+/// Returns the progress of every goal in its period that contains `at` (seconds since the Unix
+/// epoch), now if None
+pub fn goal_progress(conn: &Connection, at: Option<i64>) -> Result<Vec<GoalProgress>, StatsError> {
+    let at = at.unwrap_or_else(db::now);
+    let mut res = Vec::new();
+    for goal in get_goals(conn)? {
+        match evaluate_goal(conn, &goal, at) {
+            Ok(progress) => res.push(progress),
+            Err(_) => return Err(StatsError::DatabaseError),
+        }
+    }
+    Ok(res)
+}
@@ -0,0 +1,644 @@
+//! A module for keeping the documents of the library in a single directory.
+//!
+//! By default the library only references files wherever they are. When a managed storage is
+//! configured with [`set_managed_storage`], [`crate::create_book`] copies or moves the file into
+//! the library root, at a path given by a naming template. Renaming a book or changing its section
+//! moves its file accordingly, and [`consolidate`] brings the books registered before into the
+//! root.
+//!
+//! Files that are already inside the library root are left where they are when a book is
+//! created, only renames and section changes move them.
+//!
+//! ## Naming template
+//! A template is a path relative to the root with placeholders between braces, `/` separates
+//! directories. The available placeholders are:
+//! - `{name}`: the name of the book
+//! - `{title}`: the title of the book, or its name if it doesn't have one
+//! - `{author}`: the first author, `Unknown` if there's none
+//! - `{authors}`: every author separated by `, `, `Unknown` if there's none
+//! - `{year}`: the year of publication, empty if unknown
+//! - `{section}`: the section (subsections like `math/algebra` become directories), `Unsorted`
+//!   if there's none
+//! - `{ext}`: the usual extension of the kind of the document, e.g. `pdf` or `epub`
+//!
+//! The values are cleaned from characters that aren't allowed in file names, and a ` (2)`,
+//! ` (3)`, ... suffix is added when the path is already taken by another file, existing files are
+//! never overwritten. The template must end with `.{ext}`, or with `.pdf` which is then replaced
+//! by the extension of the document.
+//!
+//! ## Transactions
+//! A file copied or moved while a transaction is open is put back when the transaction is
+//! rolled back, e.g. when a scan, an import or a batch fails halfway (see [`crate::batch`]).
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::storage::{self, ManagedStorage, StorageMode, DEFAULT_TEMPLATE};
+//! # let connection = book_lib::db::setup();
+//!
+//! let managed = ManagedStorage::init(
+//!     "/home/me/library".to_string(),
+//!     DEFAULT_TEMPLATE.to_string(),
+//!     StorageMode::Copy,
+//! );
+//! let _ = storage::set_managed_storage(&connection, &managed);
+//! let report = storage::consolidate(&connection, false);
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+
+use crate::book::Book;
+use crate::db;
+use crate::errors::{CreateBookError, StorageError};
+use crate::help;
+use crate::watch::MovedBook;
+
+/// Default naming template
+pub const DEFAULT_TEMPLATE: &str = "{section}/{author} - {title}.{ext}";
+
+const ROOT_KEY: &str = "storage.root";
+const TEMPLATE_KEY: &str = "storage.template";
+const MODE_KEY: &str = "storage.mode";
+
+const PLACEHOLDERS: &[&str] = &[
+    "name", "title", "author", "authors", "year", "section", "ext",
+];
+
+/// How files get into the library root
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StorageMode {
+    /// copy the file, the original is left untouched
+    #[default]
+    Copy,
+    /// move the file
+    Move,
+}
+
+impl StorageMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StorageMode::Copy => "copy",
+            StorageMode::Move => "move",
+        }
+    }
+
+    fn from_str(value: &str) -> StorageMode {
+        match value {
+            "move" => StorageMode::Move,
+            _ => StorageMode::Copy,
+        }
+    }
+}
+
+/// Configuration of the managed storage
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManagedStorage {
+    /// canonical path to the library root
+    pub root: String,
+    /// naming template, see the module documentation
+    pub template: String,
+    pub mode: StorageMode,
+}
+
+impl ManagedStorage {
+    /// Init function for the managed storage that takes each field and returns a ManagedStorage
+    pub fn init(root: String, template: String, mode: StorageMode) -> ManagedStorage {
+        ManagedStorage {
+            root,
+            template,
+            mode,
+        }
+    }
+
+    /// Returns true if the path is inside the library root
+    pub fn contains(&self, path: &str) -> bool {
+        let path = help::canonical_path(path).unwrap_or_else(|_| help::path_from_string(path));
+        path.starts_with(&self.root)
+    }
+
+    /// Returns the path of the book in the library root according to the template, without
+    /// looking for collisions
+    pub fn path_for(&self, bk: &Book) -> Result<PathBuf, StorageError> {
+        Ok(Path::new(&self.root).join(render_template(&self.template, bk)?))
+    }
+}
+
+/// Replaces the characters that aren't allowed in file names
+fn clean(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    cleaned.trim().trim_end_matches('.').trim().to_string()
+}
+
+fn placeholder_value(placeholder: &str, bk: &Book) -> String {
+    let meta = &bk.metadata;
+    let or_unknown = |value: String| {
+        if value.is_empty() {
+            "Unknown".to_string()
+        } else {
+            value
+        }
+    };
+    match placeholder {
+        "name" => clean(&bk.name),
+        "title" => clean(meta.title.as_ref().unwrap_or(&bk.name)),
+        "author" => or_unknown(clean(meta.authors.first().map_or("", |a| a.as_str()))),
+        "authors" => or_unknown(clean(&meta.authors.join(", "))),
+        "year" => meta.year.map(|year| year.to_string()).unwrap_or_default(),
+        "section" => {
+            let dirs: Vec<String> = bk
+                .section
+                .as_deref()
+                .unwrap_or("")
+                .split('/')
+                .map(clean)
+                .filter(|dir| !dir.is_empty())
+                .collect();
+            if dirs.is_empty() {
+                "Unsorted".to_string()
+            } else {
+                dirs.join("/")
+            }
+        }
+        "ext" => bk.kind.extensions()[0].to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Returns the path of a book relative to the library root according to a naming template
+///
+/// ```rust
+/// use book_lib::book::Book;
+/// use book_lib::kind::DocumentKind;
+/// use book_lib::storage::{render_template, DEFAULT_TEMPLATE};
+/// use std::path::PathBuf;
+///
+/// let mut bk = Book::init("sicp".to_string(), "/tmp/sicp.pdf".to_string(), Some("cs/lisp".to_string()), false);
+/// bk.metadata.title = Some("Structure and Interpretation: 2nd edition".to_string());
+/// bk.metadata.authors = vec!["Abelson, Harold".to_string()];
+/// assert_eq!(
+///     render_template(DEFAULT_TEMPLATE, &bk).unwrap(),
+///     PathBuf::from("cs/lisp/Abelson, Harold - Structure and Interpretation_ 2nd edition.pdf")
+/// );
+/// bk.kind = DocumentKind::Epub;
+/// assert_eq!(render_template("{name}.pdf", &bk).unwrap(), PathBuf::from("sicp.epub"));
+/// assert!(render_template("{publisher}.pdf", &bk).is_err());
+/// ```
+pub fn render_template(template: &str, bk: &Book) -> Result<PathBuf, StorageError> {
+    let template = match template.strip_suffix(".pdf") {
+        Some(base) => format!("{}.{{ext}}", base),
+        None if template.ends_with(".{ext}") => template.to_string(),
+        None => {
+            return Err(StorageError::InvalidTemplate(
+                "the template must end with .{ext} or .pdf".to_string(),
+            ))
+        }
+    };
+    let mut rendered = String::new();
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(StorageError::InvalidTemplate("unclosed {".to_string())),
+        };
+        let placeholder = &rest[start + 1..end];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(StorageError::InvalidTemplate(format!(
+                "unknown placeholder {{{}}}",
+                placeholder
+            )));
+        }
+        rendered.push_str(&placeholder_value(placeholder, bk));
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    let mut path = PathBuf::new();
+    for comp in rendered.split('/').map(str::trim) {
+        match comp {
+            "" | "." => {}
+            ".." => path.push("_"),
+            comp => path.push(comp),
+        }
+    }
+    Ok(path)
+}
+
+/// Returns the configured managed storage, None if the library references files in place
+pub fn get_managed_storage(conn: &Connection) -> Result<Option<ManagedStorage>, StorageError> {
+    let get = |key: &str| match db::get_setting(conn, key) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(StorageError::DatabaseError),
+    };
+    let root = match get(ROOT_KEY)? {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+    let template = get(TEMPLATE_KEY)?.unwrap_or(DEFAULT_TEMPLATE.to_string());
+    let mode = StorageMode::from_str(&get(MODE_KEY)?.unwrap_or_default());
+    Ok(Some(ManagedStorage::init(root, template, mode)))
+}
+
+/// Turns the managed storage on, the root is created if it doesn't exist.
+///
+/// Returns the stored configuration, with the canonical path of the root.
+pub fn set_managed_storage(
+    conn: &Connection,
+    storage: &ManagedStorage,
+) -> Result<ManagedStorage, StorageError> {
+    render_template(
+        &storage.template,
+        &Book::init(String::new(), String::new(), None, false),
+    )?;
+    if fs::create_dir_all(&storage.root).is_err() {
+        return Err(StorageError::CouldNotCreateRoot);
+    }
+    let root = match help::canonical_path(&storage.root) {
+        Ok(root) => help::path_to_string(root),
+        Err(_) => return Err(StorageError::CouldNotCreateRoot),
+    };
+    let stored = ManagedStorage::init(root, storage.template.clone(), storage.mode);
+    for (key, value) in [
+        (ROOT_KEY, stored.root.as_str()),
+        (TEMPLATE_KEY, stored.template.as_str()),
+        (MODE_KEY, stored.mode.as_str()),
+    ] {
+        if db::set_setting(conn, key, Some(value)).is_err() {
+            return Err(StorageError::DatabaseError);
+        }
+    }
+    Ok(stored)
+}
+
+/// Turns the managed storage off, the files stay in the library root
+pub fn disable_managed_storage(conn: &Connection) -> Result<(), StorageError> {
+    for key in [ROOT_KEY, TEMPLATE_KEY, MODE_KEY] {
+        if db::set_setting(conn, key, None).is_err() {
+            return Err(StorageError::DatabaseError);
+        }
+    }
+    Ok(())
+}
+
+/// Returns `target`, then `target` with a ` (2)`, ` (3)`, ... suffix
+fn candidates(target: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    let stem = target
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = target
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    std::iter::once(target.to_path_buf())
+        .chain((2..).map(move |i| target.with_file_name(format!("{} ({}){}", stem, i, ext))))
+}
+
+/// Returns the path [`transfer`] would use for `target` if no other file is created meanwhile,
+/// for dry runs
+fn free_path(target: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    candidates(target)
+        .find(|path| !taken.contains(path) && !path.exists())
+        .unwrap_or_else(|| target.to_path_buf())
+}
+
+/// Copies or moves a file to `to`, fails with [`io::ErrorKind::AlreadyExists`] if `to` exists.
+///
+/// The destination is claimed atomically, by a hard link or by a file created with
+/// `create_new`, so a file created at the same time by another program is never overwritten.
+fn claim(from: &Path, to: &Path, mode: StorageMode) -> io::Result<()> {
+    if mode == StorageMode::Move {
+        match fs::hard_link(from, to) {
+            Ok(_) => return fs::remove_file(from),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => return Err(err),
+            // hard links fail across file systems and on some file systems
+            Err(_) => {}
+        }
+    }
+    let mut dest = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(to)?;
+    let copied = fs::File::open(from).and_then(|mut src| {
+        io::copy(&mut src, &mut dest)?;
+        fs::set_permissions(to, src.metadata()?.permissions())
+    });
+    let res = match (copied, mode) {
+        (Ok(_), StorageMode::Move) => fs::remove_file(from),
+        (res, _) => res,
+    };
+    if res.is_err() {
+        let _ = fs::remove_file(to);
+    }
+    res
+}
+
+/// Copies or moves a file to the first free path among `target`, `target (2)`, ... and returns
+/// the path used, creating the directories of the destination.
+///
+/// `current` is the path of the file if it's already in place at one of these paths, nothing is
+/// done then. When a transaction is open on the connection, the transfer is undone if the
+/// transaction is rolled back, see [`db::Savepoint`].
+fn transfer(
+    conn: &Connection,
+    from: &Path,
+    target: &Path,
+    mode: StorageMode,
+    current: Option<&Path>,
+) -> io::Result<PathBuf> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    for candidate in candidates(target) {
+        if Some(candidate.as_path()) == current {
+            return Ok(candidate);
+        }
+        match claim(from, &candidate, mode) {
+            Ok(_) => {
+                track_transfer(conn, from, &candidate, mode);
+                return Ok(candidate);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!("there are infinitely many candidates")
+}
+
+/// Undoes a transfer
+fn undo_transfer(from: &Path, to: &Path, mode: StorageMode) {
+    let _ = match mode {
+        StorageMode::Copy => fs::remove_file(to),
+        StorageMode::Move => claim(to, from, StorageMode::Move),
+    };
+}
+
+/// A file copied or moved while a transaction was open on a connection
+struct PendingTransfer {
+    /// SQLite handle of the connection
+    conn: usize,
+    from: PathBuf,
+    to: PathBuf,
+    mode: StorageMode,
+}
+
+thread_local! {
+    /// Transfers of the open transactions, a connection and its savepoints are always used from
+    /// a single thread at a time
+    static PENDING: RefCell<Vec<PendingTransfer>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Identifies a connection for as long as it's open
+pub(crate) fn connection_id(conn: &Connection) -> usize {
+    // SAFETY: the handle is only used as an identifier, it stays the same when the `Connection`
+    // is moved
+    unsafe { conn.handle() as usize }
+}
+
+fn track_transfer(conn: &Connection, from: &Path, to: &Path, mode: StorageMode) {
+    if conn.is_autocommit() {
+        return;
+    }
+    PENDING.with_borrow_mut(|pending| {
+        pending.push(PendingTransfer {
+            conn: connection_id(conn),
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            mode,
+        })
+    });
+}
+
+/// Returns the mark given to [`commit_transfers`] and [`rollback_transfers`] when a savepoint
+/// is opened
+pub(crate) fn transfers_mark() -> usize {
+    PENDING.with_borrow(|pending| pending.len())
+}
+
+/// Forgets the transfers of the connection made after `mark`, they are kept
+pub(crate) fn commit_transfers(conn: &Connection, mark: usize) {
+    let id = connection_id(conn);
+    PENDING.with_borrow_mut(|pending| {
+        let mut i = 0;
+        pending.retain(|transfer| {
+            i += 1;
+            i <= mark || transfer.conn != id
+        });
+    });
+}
+
+/// Undoes the transfers of the connection made after `mark`, the last one first
+pub(crate) fn rollback_transfers(conn: &Connection, mark: usize) {
+    let id = connection_id(conn);
+    let undone: Vec<PendingTransfer> = PENDING.with_borrow_mut(|pending| {
+        let mut kept = Vec::new();
+        let mut undone = Vec::new();
+        for (i, transfer) in pending.drain(..).enumerate() {
+            if i >= mark && transfer.conn == id {
+                undone.push(transfer);
+            } else {
+                kept.push(transfer);
+            }
+        }
+        *pending = kept;
+        undone
+    });
+    for transfer in undone.iter().rev() {
+        undo_transfer(&transfer.from, &transfer.to, transfer.mode);
+    }
+}
+
+/// Removes the empty directories left in the root after a file was moved out of `dir`
+fn remove_empty_dirs(storage: &ManagedStorage, dir: Option<&Path>) {
+    let mut dir = dir;
+    while let Some(curr) = dir {
+        if curr == Path::new(&storage.root) || !curr.starts_with(&storage.root) {
+            break;
+        }
+        if fs::remove_dir(curr).is_err() {
+            break;
+        }
+        dir = curr.parent();
+    }
+}
+
+fn transfer_error(err: io::Error) -> StorageError {
+    StorageError::CouldNotTransferFile(err.to_string())
+}
+
+/// Stores the file of a new book in the library root and creates the book with its new path.
+///
+/// The file is put back if the book can't be created or if the transaction it's created in is
+/// rolled back.
+pub(crate) fn create_managed_book(
+    conn: &Connection,
+    storage: &ManagedStorage,
+    bk: &Book,
+) -> Result<bool, CreateBookError> {
+    if storage.contains(&bk.path) {
+        return crate::insert_book(conn, bk);
+    }
+    if db::get_book(conn, &bk.name).is_ok() {
+        return Err(CreateBookError::BookNameAlreadyUsed);
+    }
+    let target = match storage.path_for(bk) {
+        Ok(target) => target,
+        Err(_) => return Err(CreateBookError::CouldNotStoreFile),
+    };
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(CreateBookError::OtherError),
+    };
+    let target = match transfer(conn, &bk.file_path(), &target, storage.mode, None) {
+        Ok(target) => target,
+        Err(_) => return Err(CreateBookError::CouldNotStoreFile),
+    };
+    let mut stored = bk.clone();
+    stored.path = help::path_to_string(&target);
+    let created = crate::insert_book(conn, &stored)?;
+    match savepoint.release() {
+        Ok(_) => Ok(created),
+        Err(_) => Err(CreateBookError::OtherError),
+    }
+}
+
+/// Moves the file of a book to the path given by the template, if the managed storage is on and
+/// the file is inside the library root.
+///
+/// Returns the move that was made, if any. Called after a book was renamed or moved to another
+/// section, the file is put back if the transaction of the change is rolled back.
+pub(crate) fn sync_book_file(
+    conn: &Connection,
+    name: &String,
+) -> Result<Option<MovedBook>, StorageError> {
+    let storage = match get_managed_storage(conn)? {
+        Some(storage) => storage,
+        None => return Ok(None),
+    };
+    let bk = match db::get_book(conn, name) {
+        Ok(bk) => bk,
+        Err(_) => return Err(StorageError::BookDoesNotExist),
+    };
+    if bk.missing || !storage.contains(&bk.path) {
+        return Ok(None);
+    }
+    let current = bk.file_path();
+    let target = storage.path_for(&bk)?;
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(StorageError::DatabaseError),
+    };
+    let target = transfer(conn, &current, &target, StorageMode::Move, Some(&current))
+        .map_err(transfer_error)?;
+    if target == current {
+        return Ok(None);
+    }
+    let new_path = help::path_to_string(&target);
+    if db::update_path(conn, name, &new_path).is_err() || savepoint.release().is_err() {
+        return Err(StorageError::DatabaseError);
+    }
+    remove_empty_dirs(&storage, current.parent());
+    Ok(Some(MovedBook {
+        name: bk.name,
+        old_path: bk.path,
+        new_path,
+    }))
+}
+
+/// Transfers the file of a book into the root and updates the book, in a single transaction
+fn move_into_root(
+    conn: &Connection,
+    storage: &ManagedStorage,
+    bk: &Book,
+    target: &Path,
+) -> Result<PathBuf, StorageError> {
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(StorageError::DatabaseError),
+    };
+    let target =
+        transfer(conn, &bk.file_path(), target, storage.mode, None).map_err(transfer_error)?;
+    let new_path = help::path_to_string(&target);
+    if db::update_path(conn, &bk.name, &new_path).is_err() || savepoint.release().is_err() {
+        return Err(StorageError::DatabaseError);
+    }
+    Ok(target)
+}
+
+/// Report of [`consolidate`]
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsolidateReport {
+    /// books whose file was copied or moved into the root
+    pub moved: Vec<MovedBook>,
+    /// books that couldn't be brought into the root
+    pub failed: Vec<(String, StorageError)>,
+    /// true if the consolidation was a dry run and nothing was written
+    pub dry_run: bool,
+}
+
+/// Copies or moves the files of the books that are outside of the library root into it, according
+/// to the configured storage mode.
+///
+/// Each book is updated in the same transaction as its file is transferred, so an interrupted
+/// consolidation leaves every book pointing to an existing file. Books whose file is missing are
+/// skipped. The new paths aren't recorded in the [`crate::journal`].
+pub fn consolidate(conn: &Connection, dry_run: bool) -> Result<ConsolidateReport, StorageError> {
+    let storage = match get_managed_storage(conn)? {
+        Some(storage) => storage,
+        None => return Err(StorageError::NotConfigured),
+    };
+    let books = match db::get_books(conn) {
+        Ok(books) => books,
+        Err(_) => return Err(StorageError::DatabaseError),
+    };
+    let mut report = ConsolidateReport {
+        dry_run,
+        ..Default::default()
+    };
+    let mut taken = HashSet::new();
+    for bk in books {
+        if bk.missing || storage.contains(&bk.path) || !bk.file_path().is_file() {
+            continue;
+        }
+        let target = match storage.path_for(&bk) {
+            Ok(target) => target,
+            Err(err) => {
+                report.failed.push((bk.name, err));
+                continue;
+            }
+        };
+        let target = match dry_run {
+            true => free_path(&target, &taken),
+            false => match move_into_root(conn, &storage, &bk, &target) {
+                Ok(target) => target,
+                Err(err) => {
+                    report.failed.push((bk.name, err));
+                    continue;
+                }
+            },
+        };
+        taken.insert(target.clone());
+        let new_path = help::path_to_string(&target);
+        report.moved.push(MovedBook {
+            name: bk.name,
+            old_path: bk.path,
+            new_path,
+        });
+    }
+    Ok(report)
+}
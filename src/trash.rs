@@ -0,0 +1,188 @@
+//! A module for the trash of the library.
+//!
+//! [`crate::remove_book`] doesn't delete a book, it moves it to the trash with the time it was
+//! removed at. Books in the trash are left out of every other query of the library, they keep
+//! their data, progress and history until the trash is emptied with [`empty_trash`] and can be
+//! brought back with [`restore_book`].
+//!
+//! Several removed books can have the same name, the books of the trash are identified by their
+//! id. Times are in seconds since the Unix epoch.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::trash::{self, RestoreConflict};
+//! # let connection = book_lib::db::setup();
+//!
+//! let _ = book_lib::remove_book(&connection, &"book_name".to_string());
+//! for trashed in trash::list_trash(&connection).unwrap_or_default() {
+//!     if trashed.book.name == "book_name" {
+//!         let _ = trash::restore_book(&connection, trashed.id, RestoreConflict::Rename);
+//!     }
+//! }
+//! ```
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::audit::{self, AuditOp};
+use crate::book::Book;
+use crate::db;
+use crate::errors::TrashError;
+use crate::journal::{self, JournalOp};
+
+/// A book in the trash
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrashedBook {
+    /// id of the book in the trash, given to [`restore_book`]
+    pub id: i64,
+    pub book: Book,
+    /// when the book was removed
+    pub deleted_at: i64,
+}
+
+/// What [`restore_book`] does when a book of the library has the name of the restored one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RestoreConflict {
+    /// don't restore the book
+    #[default]
+    Fail,
+    /// restore the book with the first free name among "name (2)", "name (3)"...
+    Rename,
+    /// move the book of the library to the trash and restore this one in its place
+    Replace,
+}
+
+fn trashed_from_row(row: &rusqlite::Row) -> rusqlite::Result<TrashedBook> {
+    let columns = row.as_ref().column_count();
+    Ok(TrashedBook {
+        id: row.get(columns - 2)?,
+        book: db::book_from_row(row)?,
+        deleted_at: row.get(columns - 1)?,
+    })
+}
+
+/// Returns the books in the trash, the last removed first
+pub fn list_trash(conn: &Connection) -> Result<Vec<TrashedBook>, TrashError> {
+    let query = || -> rusqlite::Result<Vec<TrashedBook>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, id, deleted_at FROM books WHERE deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC, id DESC",
+            db::BOOK_COLUMNS
+        ))?;
+        let books = stmt.query_map([], trashed_from_row)?.collect();
+        books
+    };
+    match query() {
+        Ok(books) => Ok(books),
+        Err(_) => Err(TrashError::DatabaseError),
+    }
+}
+
+/// Returns a book of the trash by its id
+pub fn get_trashed_book(conn: &Connection, id: i64) -> Result<TrashedBook, TrashError> {
+    match conn.query_row(
+        &format!(
+            "SELECT {}, id, deleted_at FROM books WHERE id = ? AND deleted_at IS NOT NULL",
+            db::BOOK_COLUMNS
+        ),
+        params![id],
+        trashed_from_row,
+    ) {
+        Ok(trashed) => Ok(trashed),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(TrashError::BookNotInTrash),
+        Err(_) => Err(TrashError::DatabaseError),
+    }
+}
+
+fn free_name(conn: &Connection, name: &str) -> String {
+    (2..)
+        .map(|i| format!("{} ({})", name, i))
+        .find(|candidate| db::get_book(conn, candidate).is_err())
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Brings a book of the trash back to the library, returns the restored book.
+///
+/// `conflict` tells what to do when a book of the library already has its name. The changes are
+/// written in a single transaction and recorded in the journal, the book replaced with
+/// [`RestoreConflict::Replace`] is recorded as removed first, see [`crate::journal`].
+pub fn restore_book(
+    conn: &Connection,
+    id: i64,
+    conflict: RestoreConflict,
+) -> Result<Book, TrashError> {
+    let trashed = get_trashed_book(conn, id)?;
+    let mut name = trashed.book.name.clone();
+    if db::get_book(conn, &name).is_ok() {
+        match conflict {
+            RestoreConflict::Fail => return Err(TrashError::NameConflict(name)),
+            RestoreConflict::Rename => name = free_name(conn, &name),
+            RestoreConflict::Replace => {}
+        }
+    }
+    let restore = || -> rusqlite::Result<()> {
+        let savepoint = db::Savepoint::new(conn)?;
+        if let Some(replaced) = db::get_book_id(conn, &name).optional()? {
+            db::audited_update(conn, AuditOp::RemoveBook, &name, "path", || {
+                conn.execute(
+                    "UPDATE books SET deleted_at = ? WHERE id = ?",
+                    params![db::now(), replaced],
+                )
+            })?;
+            journal::record(conn, JournalOp::RemoveBook, replaced, &name, None, None)?;
+        }
+        db::audited_update(conn, AuditOp::RestoreBook, &name, "path", || {
+            conn.execute(
+                "UPDATE books SET name = ?1, deleted_at = NULL WHERE id = ?2",
+                params![name, id],
+            )
+        })?;
+        let (before, after) = (Some(trashed.book.name.clone()), Some(name.clone()));
+        journal::record(conn, JournalOp::RestoreBook, id, &name, before, after)?;
+        savepoint.release()
+    };
+    if restore().is_err() {
+        return Err(TrashError::DatabaseError);
+    }
+    match db::get_book(conn, &name) {
+        Ok(book) => Ok(book),
+        Err(_) => Err(TrashError::DatabaseError),
+    }
+}
+
+/// Deletes the books of the trash for good with their progress, history and changes recorded in
+/// the journal, returns how many were deleted.
+///
+/// Only the books removed before `older_than` are deleted, every book of the trash if None.
+pub fn empty_trash(conn: &Connection, older_than: Option<i64>) -> Result<usize, TrashError> {
+    let delete = || -> rusqlite::Result<usize> {
+        let savepoint = db::Savepoint::new(conn)?;
+        let books: Vec<(String, String)> = {
+            let mut stmt = conn.prepare(
+                "SELECT name, path FROM books
+                WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < ?1)",
+            )?;
+            let rows = stmt.query_map(params![older_than], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        conn.execute(
+            "DELETE FROM journal WHERE book_id IN (SELECT id FROM books
+                WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < ?1))",
+            params![older_than],
+        )?;
+        let deleted = conn.execute(
+            "DELETE FROM books WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < ?1)",
+            params![older_than],
+        )?;
+        for (name, path) in books {
+            audit::log(conn, AuditOp::PurgeBook, &name, Some(path), None)?;
+        }
+        savepoint.release()?;
+        Ok(deleted)
+    };
+    match delete() {
+        Ok(deleted) => Ok(deleted),
+        Err(_) => Err(TrashError::DatabaseError),
+    }
+}
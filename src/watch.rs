@@ -0,0 +1,270 @@
+//! A module for keeping the library in sync with watched folders.
+//!
+//! A watched folder is a directory whose PDFs are expected to be in the library. Calling
+//! [`reconcile`] compares the watched folders with the books and:
+//! - registers the PDFs that appeared in a watched folder, like [`crate::scan::scan_directory`]
+//! - updates the path of the books whose file was moved inside the watched folders (a new file
+//!   with the same file name as a disappeared book), the moves are recorded in the
+//!   [`crate::journal`]
+//! - flags the books whose file disappeared as missing, and clears the flag of the books whose
+//!   file is back
+//!
+//! The library doesn't run in the background, front-ends call [`reconcile`] when they start,
+//! periodically or when their own file system watcher reports a change.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use book_lib::watch::{self, WatchedFolder};
+//! # let connection = book_lib::db::setup();
+//!
+//! let folder = WatchedFolder::init("/home/me/papers".to_string(), None, true);
+//! let _ = watch::add_watched_folder(&connection, &folder);
+//! if let Ok(report) = watch::reconcile(&connection) {
+//!     for name in report.missing {
+//!         println!("{} disappeared", name);
+//!     }
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection};
+
+use crate::book::Book;
+use crate::db;
+use crate::errors::WatchError;
+use crate::help;
+use crate::journal::JournalOp;
+use crate::scan::{self, ScanOptions, ScanOutcome, ScannedFile};
+
+/// A folder watched by the library
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WatchedFolder {
+    /// canonical path to the folder
+    pub path: String,
+    /// section given to the books added from this folder
+    pub section: Option<String>,
+    /// use the subdirectories of the folder as sections, see [`ScanOptions::sections_from_dirs`]
+    pub sections_from_dirs: bool,
+}
+
+impl WatchedFolder {
+    /// Init function for the watched folder that takes each field and returns a WatchedFolder
+    pub fn init(path: String, section: Option<String>, sections_from_dirs: bool) -> WatchedFolder {
+        WatchedFolder {
+            path,
+            section,
+            sections_from_dirs,
+        }
+    }
+
+    fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            section: self.section.clone(),
+            sections_from_dirs: self.sections_from_dirs,
+            ..Default::default()
+        }
+    }
+}
+
+/// A book whose file was found at a new path
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MovedBook {
+    pub name: String,
+    pub old_path: String,
+    pub new_path: String,
+}
+
+/// Report of a reconciliation pass
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReconcileReport {
+    /// files registered from the watched folders
+    pub added: Vec<ScannedFile>,
+    /// books whose file was moved inside the watched folders
+    pub moved: Vec<MovedBook>,
+    /// names of the books flagged as missing by this pass
+    pub missing: Vec<String>,
+    /// names of the books whose file is back at its path
+    pub restored: Vec<String>,
+    /// errors met while walking the watched folders
+    pub errors: Vec<String>,
+}
+
+/// Starts watching a folder, the folder is stored by its canonical path
+pub fn add_watched_folder(conn: &Connection, folder: &WatchedFolder) -> Result<(), WatchError> {
+    let path = match help::canonical_path(&folder.path) {
+        Ok(path) if path.is_dir() => help::path_to_string(path),
+        _ => return Err(WatchError::FolderDoesNotExist),
+    };
+    if get_watched_folders(conn)?
+        .iter()
+        .any(|watched| watched.path == path)
+    {
+        return Err(WatchError::FolderAlreadyWatched);
+    }
+    match conn.execute(
+        "INSERT INTO watched_folders (path, section, sections_from_dirs) VALUES (?, ?, ?)",
+        params![path, folder.section, folder.sections_from_dirs],
+    ) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(WatchError::DatabaseError),
+    }
+}
+
+/// Stops watching a folder, the books registered from it are kept
+pub fn remove_watched_folder(conn: &Connection, path: &String) -> Result<(), WatchError> {
+    let canonical = match help::canonical_path(path) {
+        Ok(canonical) => help::path_to_string(canonical),
+        Err(_) => path.clone(),
+    };
+    match conn.execute(
+        "DELETE FROM watched_folders WHERE path = ? OR path = ?",
+        params![path, canonical],
+    ) {
+        Ok(0) => Err(WatchError::FolderNotWatched),
+        Ok(_) => Ok(()),
+        Err(_) => Err(WatchError::DatabaseError),
+    }
+}
+
+/// Returns every watched folder
+pub fn get_watched_folders(conn: &Connection) -> Result<Vec<WatchedFolder>, WatchError> {
+    let query = || -> rusqlite::Result<Vec<WatchedFolder>> {
+        let mut stmt = conn
+            .prepare("SELECT path, section, sections_from_dirs FROM watched_folders ORDER BY id")?;
+        let folders = stmt
+            .query_map([], |row| {
+                Ok(WatchedFolder::init(row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect();
+        folders
+    };
+    match query() {
+        Ok(folders) => Ok(folders),
+        Err(_) => Err(WatchError::DatabaseError),
+    }
+}
+
+fn file_name(path: &str) -> Option<String> {
+    help::path_from_string(path)
+        .file_name()
+        .map(help::path_to_string)
+}
+
+/// Brings the library in sync with the watched folders, see the module documentation.
+///
+/// Every change is written in a single transaction, a database error rolls all of them back.
+pub fn reconcile(conn: &Connection) -> Result<ReconcileReport, WatchError> {
+    let folders = get_watched_folders(conn)?;
+    let books: Vec<Book> = match db::get_books(conn) {
+        Ok(books) => books,
+        Err(_) => return Err(WatchError::DatabaseError),
+    };
+    let savepoint = match db::Savepoint::new(conn) {
+        Ok(savepoint) => savepoint,
+        Err(_) => return Err(WatchError::DatabaseError),
+    };
+    let mut report = ReconcileReport::default();
+
+    // books of the watched folders whose file disappeared, by file name
+    let is_watched = |path: &str| {
+        folders
+            .iter()
+            .any(|folder| Path::new(path).starts_with(&folder.path))
+    };
+    let mut gone: HashMap<String, Vec<&Book>> = HashMap::new();
+    for bk in &books {
+        let exists = bk.file_path().exists();
+        if exists && bk.missing {
+            if db::set_missing(conn, &bk.name, false).is_err() {
+                return Err(WatchError::DatabaseError);
+            }
+            report.restored.push(bk.name.clone());
+        } else if !exists && is_watched(&bk.path) {
+            if let Some(name) = file_name(&bk.path) {
+                gone.entry(name).or_default().push(bk);
+            }
+        }
+    }
+
+    // every folder is walked once, its new files are either a disappeared book that moved or
+    // registered as new books
+    let mut taken = HashSet::new();
+    let mut scans = Vec::new();
+    for folder in &folders {
+        match scan::plan_scan(conn, &folder.path, &folder.scan_options(), &mut taken) {
+            Ok(scanned) => scans.push(scanned),
+            Err(err) => report.errors.push(format!("{}: {}", folder.path, err)),
+        }
+    }
+    let mut new_files: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for scanned in &scans {
+        for file in &scanned.files {
+            // a file that couldn't be a new book can't be a moved one either
+            if !matches!(file.outcome, ScanOutcome::Created) {
+                continue;
+            }
+            if let Some(name) = file_name(&file.path) {
+                new_files
+                    .entry(name)
+                    .or_default()
+                    .push(help::path_from_string(&file.path));
+            }
+        }
+    }
+
+    // new files with the file name of exactly one disappeared book are that book moved
+    let mut moved_to: HashSet<PathBuf> = HashSet::new();
+    for (name, gone_books) in &gone {
+        if let (Some(files), 1) = (new_files.get(name), gone_books.len()) {
+            if files.len() == 1 {
+                let bk = gone_books[0];
+                let new_path = help::path_to_string(&files[0]);
+                let relocate = || -> rusqlite::Result<()> {
+                    db::update_path(conn, &bk.name, &new_path)?;
+                    let (before, after) = (Some(bk.path.clone()), Some(new_path.clone()));
+                    crate::record_change(conn, JournalOp::UpdatePath, &bk.name, before, after)
+                };
+                if relocate().is_err() {
+                    return Err(WatchError::DatabaseError);
+                }
+                moved_to.insert(files[0].clone());
+                report.moved.push(MovedBook {
+                    name: bk.name.clone(),
+                    old_path: bk.path.clone(),
+                    new_path,
+                });
+                continue;
+            }
+        }
+        for bk in gone_books {
+            if !bk.missing {
+                if db::set_missing(conn, &bk.name, true).is_err() {
+                    return Err(WatchError::DatabaseError);
+                }
+                report.missing.push(bk.name.clone());
+            }
+        }
+    }
+
+    // the remaining new files are registered
+    for mut scanned in scans {
+        report.errors.extend(std::mem::take(&mut scanned.errors));
+        scanned.files.retain(|file| {
+            !matches!(file.outcome, ScanOutcome::AlreadyRegistered(_))
+                && !moved_to.contains(&help::path_from_string(&file.path))
+        });
+        if scan::apply_scan(conn, &mut scanned).is_err() {
+            return Err(WatchError::DatabaseError);
+        }
+        report.added.extend(scanned.files);
+    }
+    match savepoint.release() {
+        Ok(_) => Ok(report),
+        Err(_) => Err(WatchError::DatabaseError),
+    }
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use book_lib::async_api::AsyncConnection;
+use book_lib::audit::{AuditFilter, AuditOp};
+use book_lib::book::Book;
+use book_lib::errors::TrashError;
+use book_lib::export::LibraryImportOptions;
+use book_lib::journal::JournalOp;
+use book_lib::kind::DocumentKind;
+use book_lib::trash::RestoreConflict;
+
+use common::{library, names, TempDir};
+
+#[tokio::test(flavor = "current_thread")]
+async fn the_wrappers_give_the_results_of_the_blocking_functions() {
+    let dir = TempDir::new("async");
+    let connection = AsyncConnection::new(library());
+    let bk = Book::init("paper".to_string(), dir.pdf("paper.pdf"), None, false);
+    connection.create_book(bk).await.unwrap();
+    connection
+        .set_actor(Some("alice".to_string()))
+        .await
+        .unwrap();
+
+    let progress = connection
+        .update_progress("paper".to_string(), 12, Some(1_000))
+        .await
+        .unwrap();
+    assert_eq!(progress.current_page, Some(12));
+    connection
+        .record_session("paper".to_string(), 1_000, 60)
+        .await
+        .unwrap();
+    assert_eq!(
+        connection.get_history("paper".to_string()).await.unwrap()[0].duration,
+        Some(60)
+    );
+    assert_eq!(connection.pages_read_per_week().await.unwrap()[0].total, 12);
+
+    let entries = connection
+        .query_audit(AuditFilter {
+            op: Some(AuditOp::UpdateProgress),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(entries[0].actor.as_deref(), Some("alice"));
+    let (count, written) = connection
+        .export_audit_jsonl(AuditFilter::default(), Vec::new())
+        .await
+        .unwrap();
+    assert_eq!(String::from_utf8(written).unwrap().lines().count(), count);
+
+    let hash = connection.content_hash("paper".to_string()).await.unwrap();
+    assert_eq!(
+        hash,
+        book_lib::hash::hash_file(dir.join("paper.pdf")).unwrap()
+    );
+    assert!(connection.find_duplicates().await.unwrap().is_empty());
+    connection
+        .set_allowed_kinds(vec![DocumentKind::Pdf, DocumentKind::Epub])
+        .await
+        .unwrap();
+    assert_eq!(connection.get_allowed_kinds().await.unwrap().len(), 2);
+    assert!(connection.get_managed_storage().await.unwrap().is_none());
+
+    connection.remove_book("paper".to_string()).await.unwrap();
+    let id = connection.list_trash().await.unwrap()[0].id;
+    connection
+        .restore_book(id, RestoreConflict::Fail)
+        .await
+        .unwrap();
+    assert!(matches!(
+        connection.restore_book(id, RestoreConflict::Fail).await,
+        Err(TrashError::BookNotInTrash)
+    ));
+    assert_eq!(connection.undo().await.unwrap().op, JournalOp::RestoreBook);
+    assert!(connection.get_books().await.unwrap().is_empty());
+    connection.redo().await.unwrap();
+
+    let json = connection.export_json(Vec::new()).await.unwrap();
+    let other = AsyncConnection::new(library());
+    let report = other
+        .import_json(std::io::Cursor::new(json), LibraryImportOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(report.created, vec!["paper"]);
+    assert_eq!(names(&other.get_books().await.unwrap()), vec!["paper"]);
+}
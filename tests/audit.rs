@@ -0,0 +1,78 @@
+mod common;
+
+use book_lib::audit::{self, AuditFilter, AuditOp};
+use book_lib::{history, progress};
+use rusqlite::Connection;
+
+use common::{add_book, library, TempDir};
+
+fn changes(conn: &Connection, op: AuditOp) -> Vec<(Option<String>, Option<String>)> {
+    let filter = AuditFilter {
+        op: Some(op),
+        ..Default::default()
+    };
+    let mut entries = audit::query_audit(conn, &filter).unwrap();
+    entries.sort_by_key(|entry| entry.id);
+    entries
+        .into_iter()
+        .map(|entry| (entry.old_value, entry.new_value))
+        .collect()
+}
+
+fn some(value: &str) -> Option<String> {
+    Some(value.to_string())
+}
+
+#[test]
+fn progress_changes_are_logged() {
+    let conn = library();
+    let dir = TempDir::new("audit");
+    let bk = add_book(&conn, &dir, "paper");
+    audit::set_actor(&conn, Some("alice")).unwrap();
+
+    progress::set_page_count(&conn, &bk.name, Some(10)).unwrap();
+    progress::update_progress(&conn, &bk.name, 4, Some(100)).unwrap();
+    progress::update_progress(&conn, &bk.name, 10, Some(200)).unwrap();
+    progress::mark_finished(&conn, &bk.name, false, None).unwrap();
+    progress::reset_progress(&conn, &bk.name).unwrap();
+
+    assert_eq!(
+        changes(&conn, AuditOp::UpdatePageCount),
+        vec![(None, some("10"))]
+    );
+    assert_eq!(
+        changes(&conn, AuditOp::UpdateProgress),
+        vec![
+            (None, some("4")),
+            (some("4"), some("10")),
+            (some("10"), None)
+        ]
+    );
+    assert_eq!(
+        changes(&conn, AuditOp::UpdateFinished),
+        vec![(None, some("200")), (some("200"), None)]
+    );
+    let filter = AuditFilter {
+        actor: Some("alice".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(audit::query_audit(&conn, &filter).unwrap().len(), 6);
+}
+
+#[test]
+fn openings_are_left_out() {
+    let conn = library();
+    let dir = TempDir::new("audit");
+    let bk = add_book(&conn, &dir, "paper");
+    let before = audit::query_audit(&conn, &AuditFilter::default())
+        .unwrap()
+        .len();
+
+    history::record_session(&conn, &bk.name, 1_000, 60).unwrap();
+    history::clear_history(&conn).unwrap();
+
+    let after = audit::query_audit(&conn, &AuditFilter::default())
+        .unwrap()
+        .len();
+    assert_eq!(after, before);
+}
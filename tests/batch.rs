@@ -0,0 +1,76 @@
+mod common;
+
+use book_lib::batch::{self, BatchOutcome};
+use book_lib::errors::{BatchError, BatchItemError, RemoveBookError};
+use book_lib::journal::{self, JournalOp};
+
+use common::{add_book, library, names, TempDir};
+
+#[test]
+fn a_batch_is_written_at_once() {
+    let conn = library();
+    let dir = TempDir::new("batch");
+    add_book(&conn, &dir, "first");
+    add_book(&conn, &dir, "second");
+    let journal_len = journal::get_journal(&conn, 100).unwrap().len();
+
+    let report = batch::run_batch(&conn, |batch| {
+        batch.update_favourite(&"first".to_string(), true)?;
+        batch.rename_book(&"second".to_string(), &"renamed".to_string())?;
+        Ok(())
+    })
+    .unwrap();
+
+    assert!(report.committed);
+    let ops: Vec<JournalOp> = report.items.iter().map(|item| item.op).collect();
+    assert_eq!(ops, vec![JournalOp::UpdateFavourite, JournalOp::RenameBook]);
+    assert!(report
+        .items
+        .iter()
+        .all(|item| matches!(item.outcome, BatchOutcome::Done)));
+    assert_eq!(
+        names(&book_lib::get_books(&conn).unwrap()),
+        vec!["first", "renamed"]
+    );
+    // every change is recorded on its own
+    assert_eq!(
+        journal::get_journal(&conn, 100).unwrap().len(),
+        journal_len + 2
+    );
+}
+
+#[test]
+fn a_failed_change_rolls_the_whole_batch_back() {
+    let conn = library();
+    let dir = TempDir::new("batch");
+    add_book(&conn, &dir, "paper");
+    let journal_len = journal::get_journal(&conn, 100).unwrap().len();
+
+    let res = batch::run_batch(&conn, |batch| {
+        batch.update_favourite(&"paper".to_string(), true)?;
+        // an error that is ignored still fails the batch
+        let _ = batch.remove_book(&"missing".to_string());
+        batch.update_section(&"paper".to_string(), Some("math".to_string()))?;
+        Ok(())
+    });
+
+    let report = match res {
+        Err(BatchError::RolledBack(report)) => report,
+        other => panic!("unexpected result {:?}", other),
+    };
+    assert!(!report.committed);
+    assert_eq!(report.items.len(), 3);
+    let failed: Vec<_> = report.failed().collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].book, "missing");
+    assert!(matches!(
+        failed[0].outcome,
+        BatchOutcome::Failed(BatchItemError::RemoveBook(
+            RemoveBookError::BookDoesNotExist
+        ))
+    ));
+    let bk = book_lib::get_book(&conn, &"paper".to_string()).unwrap();
+    assert!(!bk.favourite);
+    assert_eq!(bk.section, None);
+    assert_eq!(journal::get_journal(&conn, 100).unwrap().len(), journal_len);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::path::Path;
+
+use book_lib::import::calibre::{self, METADATA_DB};
+use book_lib::import::ImportOptions;
+use rusqlite::Connection;
+
+use common::{library, TempDir, PDF};
+
+/// Writes a Calibre library with a PDF and an EPUB book
+fn calibre_library(root: &Path) {
+    let calibre = Connection::open(root.join(METADATA_DB)).unwrap();
+    calibre
+        .execute_batch(
+            "CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, path TEXT, pubdate TEXT,
+                isbn TEXT);
+            CREATE TABLE data (id INTEGER PRIMARY KEY, book INTEGER, format TEXT, name TEXT);
+            CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER,
+                author INTEGER);
+            CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER, tag INTEGER);
+            CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER,
+                series INTEGER);
+            CREATE TABLE publishers (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE books_publishers_link (id INTEGER PRIMARY KEY, book INTEGER,
+                publisher INTEGER);
+            CREATE TABLE identifiers (id INTEGER PRIMARY KEY, book INTEGER, type TEXT,
+                val TEXT);
+            INSERT INTO books VALUES
+                (1, 'Dune', 'Frank Herbert/Dune (1)', '1965-08-01 00:00:00+00:00', ''),
+                (2, 'Novel', 'Someone/Novel (2)', '0101-01-01 00:00:00+00:00', NULL);
+            INSERT INTO data VALUES (1, 1, 'PDF', 'Dune - Frank Herbert'),
+                (2, 2, 'EPUB', 'Novel - Someone');
+            INSERT INTO authors VALUES (1, 'Frank Herbert'), (2, 'Doe| Jane');
+            INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 1, 2);
+            INSERT INTO tags VALUES (1, 'fiction'), (2, 'classic');
+            INSERT INTO books_tags_link VALUES (1, 1, 1), (2, 1, 2);
+            INSERT INTO series VALUES (1, 'Dune');
+            INSERT INTO books_series_link VALUES (1, 1, 1);
+            INSERT INTO identifiers VALUES (1, 1, 'isbn', '9780441013593');",
+        )
+        .unwrap();
+    let dir = root.join("Frank Herbert/Dune (1)");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("Dune - Frank Herbert.pdf"), PDF).unwrap();
+}
+
+#[test]
+fn pdf_formats_are_imported_with_their_metadata() {
+    let conn = library();
+    let dir = TempDir::new("calibre");
+    calibre_library(&dir.path);
+
+    let report = calibre::import_calibre(&conn, &dir.path, &ImportOptions::default()).unwrap();
+
+    assert_eq!(report.entries.len(), 1);
+    assert_eq!(report.created().count(), 1);
+    let bk = book_lib::get_book(&conn, &"Dune".to_string()).unwrap();
+    assert_eq!(
+        Path::new(&bk.path),
+        dir.join("Frank Herbert/Dune (1)/Dune - Frank Herbert.pdf")
+    );
+    assert_eq!(bk.section.as_deref(), Some("fiction"));
+    assert_eq!(bk.metadata.authors, vec!["Frank Herbert", "Doe, Jane"]);
+    assert_eq!(bk.metadata.tags, vec!["fiction", "classic"]);
+    assert_eq!(bk.metadata.series.as_deref(), Some("Dune"));
+    assert_eq!(bk.metadata.isbn.as_deref(), Some("9780441013593"));
+    assert_eq!(bk.metadata.year, Some(1965));
+
+    // a second import creates nothing
+    let report = calibre::import_calibre(&conn, &dir.path, &ImportOptions::default()).unwrap();
+    assert_eq!(report.skipped().count(), 1);
+    assert_eq!(calibre::read_library(&dir.path).unwrap().len(), 1);
+}
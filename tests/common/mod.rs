@@ -0,0 +1,92 @@
+//! Helpers shared by the integration tests: an in-memory library and temporary files.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use book_lib::book::Book;
+use book_lib::db;
+use rusqlite::Connection;
+
+/// A small PDF accepted by `pdf::check_pdf`
+pub const PDF: &[u8] =
+    b"%PDF-1.7\n1 0 obj\n<<>>\nendobj\ntrailer\n<< /Root 1 0 R >>\nstartxref\n9\n%%EOF\n";
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns an empty library in memory
+pub fn library() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    db::configure(&conn).unwrap();
+    db::prepare(&conn).unwrap();
+    conn
+}
+
+/// A directory removed when dropped
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!(
+            "book_lib-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        TempDir {
+            path: path.canonicalize().unwrap(),
+        }
+    }
+
+    /// Writes a file in the directory and returns its path
+    pub fn file(&self, name: &str, content: &[u8]) -> String {
+        let path = self.path.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Writes a PDF in the directory and returns its path
+    pub fn pdf(&self, name: &str) -> String {
+        self.file(name, PDF)
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Creates a book for a new PDF of `dir`
+pub fn add_book(conn: &Connection, dir: &TempDir, name: &str) -> Book {
+    let bk = Book::init(
+        name.to_string(),
+        dir.pdf(&format!("{}.pdf", name)),
+        None,
+        false,
+    );
+    book_lib::create_book(conn, &bk).ok().unwrap();
+    book_lib::get_book(conn, &name.to_string()).ok().unwrap()
+}
+
+pub fn exists(path: &str) -> bool {
+    Path::new(path).exists()
+}
+
+pub fn names(books: &[Book]) -> Vec<String> {
+    let mut names: Vec<String> = books.iter().map(|bk| bk.name.clone()).collect();
+    names.sort();
+    names
+}
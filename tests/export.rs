@@ -0,0 +1,127 @@
+mod common;
+
+use book_lib::errors::CreateBookError;
+use book_lib::export::{self, ImportMode, LibraryImportOptions};
+use book_lib::trash;
+
+use common::{add_book, library, names, TempDir};
+
+fn library_json(books: &[(&str, &str, bool)]) -> String {
+    let books: Vec<serde_json::Value> = books
+        .iter()
+        .map(|(name, path, missing)| {
+            serde_json::json!({ "name": name, "path": path, "missing": missing })
+        })
+        .collect();
+    serde_json::json!({ "version": 1, "books": books }).to_string()
+}
+
+#[test]
+fn import_checks_the_files_and_keeps_missing_books() {
+    let conn = library();
+    let dir = TempDir::new("export");
+    let pdf = dir.pdf("present.pdf");
+    let text = dir.file("notes.txt", b"just some notes");
+    let gone = dir.join("gone.pdf").to_str().unwrap().to_string();
+    let json = library_json(&[
+        ("present", &pdf, true),
+        ("notes", &text, false),
+        ("gone", &gone, false),
+    ]);
+
+    let report =
+        export::import_json(&conn, json.as_bytes(), &LibraryImportOptions::default()).unwrap();
+
+    assert_eq!(report.created, vec!["present", "gone"]);
+    assert_eq!(report.rejected.len(), 1);
+    assert_eq!(report.rejected[0].0, "notes");
+    assert!(matches!(
+        report.rejected[0].1,
+        CreateBookError::ProvidedPathIsNotPdf
+    ));
+    let present = book_lib::get_book(&conn, &"present".to_string()).unwrap();
+    assert!(present.missing);
+    let gone = book_lib::get_book(&conn, &"gone".to_string()).unwrap();
+    assert!(gone.missing);
+    assert!(book_lib::get_book(&conn, &"notes".to_string()).is_err());
+}
+
+#[test]
+fn missing_books_of_a_kind_not_allowed_are_rejected() {
+    let conn = library();
+    let json = serde_json::json!({ "version": 1, "books": [
+        { "name": "novel", "path": "/nowhere/novel.epub", "kind": "epub" },
+        { "name": "paper", "path": "/nowhere/paper.pdf", "kind": "pdf" },
+    ] })
+    .to_string();
+
+    let report =
+        export::import_json(&conn, json.as_bytes(), &LibraryImportOptions::default()).unwrap();
+
+    assert_eq!(report.created, vec!["paper"]);
+    assert_eq!(report.rejected.len(), 1);
+    assert_eq!(report.rejected[0].0, "novel");
+    assert!(matches!(
+        report.rejected[0].1,
+        CreateBookError::ProvidedPathIsNotPdf
+    ));
+    assert_eq!(names(&book_lib::get_books(&conn).unwrap()), vec!["paper"]);
+}
+
+#[test]
+fn dry_run_reports_without_writing() {
+    let conn = library();
+    let dir = TempDir::new("export");
+    let text = dir.file("notes.txt", b"just some notes");
+    let json = library_json(&[("notes", &text, false), ("other", "/nowhere/x.pdf", false)]);
+    let options = LibraryImportOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+
+    let report = export::import_json(&conn, json.as_bytes(), &options).unwrap();
+
+    assert_eq!(report.created, vec!["other"]);
+    assert_eq!(report.rejected.len(), 1);
+    assert!(book_lib::get_books(&conn).unwrap().is_empty());
+}
+
+#[test]
+fn replace_moves_every_book_to_the_trash() {
+    let conn = library();
+    let dir = TempDir::new("export");
+    add_book(&conn, &dir, "old");
+    let pdf = dir.pdf("new.pdf");
+    let json = library_json(&[("new", &pdf, false)]);
+    let options = LibraryImportOptions {
+        mode: ImportMode::Replace,
+        ..Default::default()
+    };
+
+    let report = export::import_json(&conn, json.as_bytes(), &options).unwrap();
+
+    assert_eq!(report.removed, vec!["old"]);
+    assert_eq!(names(&book_lib::get_books(&conn).unwrap()), vec!["new"]);
+    let trashed = trash::list_trash(&conn).unwrap();
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(trashed[0].book.name, "old");
+}
+
+#[test]
+fn export_and_import_round_trip() {
+    let conn = library();
+    let dir = TempDir::new("export");
+    add_book(&conn, &dir, "first");
+    add_book(&conn, &dir, "second");
+    let mut json = Vec::new();
+    export::export_json(&conn, &mut json).unwrap();
+
+    let other = library();
+    let report =
+        export::import_json(&other, json.as_slice(), &LibraryImportOptions::default()).unwrap();
+
+    assert_eq!(report.created.len(), 2);
+    let books = book_lib::get_books(&other).unwrap();
+    assert_eq!(names(&books), vec!["first", "second"]);
+    assert!(books.iter().all(|bk| !bk.missing));
+}
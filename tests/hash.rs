@@ -0,0 +1,79 @@
+mod common;
+
+use book_lib::book::Book;
+use book_lib::errors::CreateBookError;
+use book_lib::hash::{self, DuplicatePolicy};
+
+use common::{add_book, library, names, TempDir, PDF};
+
+/// A PDF of the size of [`PDF`] with another content
+fn other_pdf() -> Vec<u8> {
+    PDF.iter()
+        .map(|byte| if *byte == b'7' { b'4' } else { *byte })
+        .collect()
+}
+
+#[test]
+fn files_with_the_same_content_are_grouped() {
+    let conn = library();
+    let dir = TempDir::new("hash");
+    let a = add_book(&conn, &dir, "a");
+    add_book(&conn, &dir, "b");
+    let c = Book::init(
+        "c".to_string(),
+        dir.file("c.pdf", &other_pdf()),
+        None,
+        false,
+    );
+    book_lib::create_book(&conn, &c).unwrap();
+
+    let groups = hash::find_duplicates(&conn).unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(names(&groups[0].books), vec!["a", "b"]);
+    assert_eq!(groups[0].size, PDF.len() as i64);
+    assert_eq!(groups[0].hash, hash::hash_file(&a.path).unwrap());
+    assert_eq!(hash::content_hash(&conn, &a.name).unwrap(), groups[0].hash);
+
+    // a changed file is hashed again
+    std::fs::write(&a.path, other_pdf()).unwrap();
+    // whatever the precision of the modification times of the file system
+    let file = std::fs::File::options().write(true).open(&a.path).unwrap();
+    file.set_modified(std::time::UNIX_EPOCH).unwrap();
+    let groups = hash::find_duplicates(&conn).unwrap();
+    assert_eq!(names(&groups[0].books), vec!["a", "c"]);
+}
+
+#[test]
+fn the_policy_decides_about_new_duplicates() {
+    let conn = library();
+    let dir = TempDir::new("hash");
+    add_book(&conn, &dir, "a");
+    let copy = |name: &str| {
+        Book::init(
+            name.to_string(),
+            dir.pdf(&format!("{}.pdf", name)),
+            None,
+            false,
+        )
+    };
+
+    assert!(matches!(
+        book_lib::create_book_with_policy(&conn, &copy("refused"), DuplicatePolicy::Refuse),
+        Err(CreateBookError::SameContentAs(name)) if name == "a"
+    ));
+    assert_eq!(
+        book_lib::create_book_with_policy(&conn, &copy("warned"), DuplicatePolicy::Warn).unwrap(),
+        vec!["a"]
+    );
+    assert!(
+        book_lib::create_book_with_policy(&conn, &copy("allowed"), DuplicatePolicy::Allow)
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        names(&book_lib::get_books(&conn).unwrap()),
+        vec!["a", "allowed", "warned"]
+    );
+    let same = hash::find_same_content(&conn, dir.join("refused.pdf")).unwrap();
+    assert_eq!(same.len(), 3);
+}
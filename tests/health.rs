@@ -0,0 +1,88 @@
+mod common;
+
+use std::fs;
+
+use book_lib::book::Book;
+use book_lib::hash;
+use book_lib::health::{self, MatchKind, RelocateMode};
+use book_lib::journal::{self, JournalOp};
+
+use common::{add_book, library, TempDir, PDF};
+
+/// A PDF with a comment, so that its content and size differ from the other ones
+fn pdf_with(comment: &str) -> Vec<u8> {
+    let mut content = format!("%PDF-1.7\n%{}\n", comment).into_bytes();
+    content.extend_from_slice(&PDF[9..]);
+    content
+}
+
+#[test]
+fn missing_books_are_relocated_or_suggested() {
+    let conn = library();
+    let dir = TempDir::new("health");
+    let moved = Book::init(
+        "moved".to_string(),
+        dir.file("old/moved.pdf", &pdf_with("moved")),
+        None,
+        false,
+    );
+    book_lib::create_book(&conn, &moved).unwrap();
+    hash::content_hash(&conn, &moved.name).unwrap();
+    let changed = add_book(&conn, &dir, "changed");
+    let lost = add_book(&conn, &dir, "lost");
+    for bk in [&moved, &changed, &lost] {
+        fs::remove_file(&bk.path).unwrap();
+    }
+    // the file of `moved` is renamed, `changed` has two files of its name with another size
+    let new_path = dir.file("new/renamed.pdf", &pdf_with("moved"));
+    dir.file("new/a/changed.pdf", &pdf_with("a"));
+    dir.file("new/b/changed.pdf", &pdf_with("b"));
+
+    let missing = health::find_missing_books(&conn).unwrap();
+    assert_eq!(missing.len(), 3);
+    assert!(missing.iter().all(|bk| bk.missing));
+
+    let roots = vec![dir.join("new")];
+    let report = health::relocate_missing_books(&conn, &roots, RelocateMode::Automatic).unwrap();
+
+    assert_eq!(report.relocated.len(), 1);
+    assert_eq!(report.relocated[0].name, "moved");
+    assert_eq!(report.relocated[0].new_path, new_path);
+    assert_eq!(report.suggestions.len(), 1);
+    assert_eq!(report.suggestions[0].name, "changed");
+    assert_eq!(report.suggestions[0].candidates.len(), 2);
+    assert!(report.suggestions[0]
+        .candidates
+        .iter()
+        .all(|candidate| candidate.matched_by == MatchKind::FileName));
+    assert_eq!(report.not_found, vec!["lost"]);
+
+    let bk = book_lib::get_book(&conn, &moved.name).unwrap();
+    assert_eq!(bk.path, new_path);
+    assert!(!bk.missing);
+    assert_eq!(
+        journal::next_undo(&conn).unwrap().unwrap().op,
+        JournalOp::UpdatePath
+    );
+}
+
+#[test]
+fn suggest_only_changes_nothing() {
+    let conn = library();
+    let dir = TempDir::new("health");
+    let bk = add_book(&conn, &dir, "paper");
+    fs::create_dir(dir.join("new")).unwrap();
+    fs::rename(&bk.path, dir.join("new/paper.pdf")).unwrap();
+
+    let roots = vec![dir.join("new")];
+    let report = health::relocate_missing_books(&conn, &roots, RelocateMode::SuggestOnly).unwrap();
+
+    assert!(report.relocated.is_empty());
+    assert_eq!(
+        report.suggestions[0].candidates[0].matched_by,
+        MatchKind::FileNameAndSize
+    );
+    assert_eq!(book_lib::get_book(&conn, &bk.name).unwrap().path, bk.path);
+    let candidates = health::find_candidates(&conn, &bk.name, &roots).unwrap();
+    assert_eq!(candidates.len(), 1);
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use book_lib::errors::HistoryError;
+use book_lib::history;
+
+use common::{add_book, library, TempDir};
+
+const DAY: i64 = 24 * 60 * 60;
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[test]
+fn sessions_are_recorded_with_the_last_opening() {
+    let conn = library();
+    let dir = TempDir::new("history");
+    let bk = add_book(&conn, &dir, "paper");
+
+    let id = history::record_open(&conn, &bk.name, Some(1_000)).unwrap();
+    history::record_close(&conn, id, Some(1_600)).unwrap();
+    history::record_session(&conn, &bk.name, 500, 60).unwrap();
+
+    let events = history::get_history(&conn, &bk.name).unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().any(|event| event.duration == Some(600)));
+    let bk = book_lib::get_book(&conn, &bk.name).unwrap();
+    assert_eq!(bk.last_opened_at, Some(1_000));
+    assert!(matches!(
+        history::record_open(&conn, &"nothing".to_string(), None),
+        Err(HistoryError::BookDoesNotExist)
+    ));
+}
+
+#[test]
+fn old_sessions_are_pruned_at_most_once_an_hour() {
+    let conn = library();
+    let dir = TempDir::new("history");
+    let bk = add_book(&conn, &dir, "paper");
+    history::record_open(&conn, &bk.name, Some(now() - 30 * DAY)).unwrap();
+
+    // the retention prunes right away
+    history::set_retention(&conn, Some(7)).unwrap();
+    assert!(history::get_history(&conn, &bk.name).unwrap().is_empty());
+
+    // it was just pruned, recording a session doesn't prune again
+    history::record_open(&conn, &bk.name, Some(now() - 30 * DAY)).unwrap();
+    history::record_open(&conn, &bk.name, None).unwrap();
+    assert_eq!(history::get_history(&conn, &bk.name).unwrap().len(), 2);
+
+    assert_eq!(history::prune_history(&conn).unwrap(), 1);
+    assert_eq!(history::get_history(&conn, &bk.name).unwrap().len(), 1);
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use book_lib::import::{self, DuplicateStrategy, ImportFailure, ImportOptions, ImportOutcome};
+
+use common::{add_book, library, names, TempDir};
+
+const BIBTEX: &str = r#"
+@string{pub = "Springer"}
+
+@book{knuth,
+  title = {The {Art} of Computer Programming},
+  author = {Donald Knuth and Someone Else},
+  year = 1968,
+  publisher = pub,
+  file = {Full text:papers/knuth.pdf:PDF}
+}
+
+@article{nofile,
+  title = "Without a file"
+}
+
+@misc{missing,
+  file = {missing.pdf}
+}
+"#;
+
+#[test]
+fn bibtex_entries_with_a_file_are_imported() {
+    let conn = library();
+    let dir = TempDir::new("import");
+    dir.pdf("papers/knuth.pdf");
+    let path = dir.file("refs.bib", BIBTEX.as_bytes());
+    let options = ImportOptions {
+        section: Some("cs".to_string()),
+        ..Default::default()
+    };
+
+    let report = import::import_bibtex(&conn, &path, &options).unwrap();
+
+    assert_eq!(report.created().count(), 1);
+    assert!(matches!(
+        report.entries[1].outcome,
+        ImportOutcome::Failed(ImportFailure::NoFile)
+    ));
+    assert!(matches!(
+        report.entries[2].outcome,
+        ImportOutcome::Failed(ImportFailure::Create(_))
+    ));
+    let bk = book_lib::get_book(&conn, &"knuth".to_string()).unwrap();
+    assert_eq!(bk.path, dir.join("papers/knuth.pdf").to_str().unwrap());
+    assert_eq!(bk.section.as_deref(), Some("cs"));
+    assert_eq!(
+        bk.metadata.title.as_deref(),
+        Some("The Art of Computer Programming")
+    );
+    assert_eq!(bk.metadata.authors, vec!["Donald Knuth", "Someone Else"]);
+    assert_eq!(bk.metadata.year, Some(1968));
+    assert_eq!(bk.metadata.publisher.as_deref(), Some("Springer"));
+}
+
+#[test]
+fn ris_records_follow_the_duplicate_strategy() {
+    let conn = library();
+    let dir = TempDir::new("import");
+    add_book(&conn, &dir, "Paper");
+    dir.pdf("paper.pdf");
+    let ris = "TY  - JOUR\nTI  - Paper\nAU  - Doe, Jane\nPY  - 2020\nL1  - paper.pdf\nER  - \n";
+    let path = dir.file("refs.ris", ris.as_bytes());
+    let mut options = ImportOptions {
+        name_from_title: true,
+        ..Default::default()
+    };
+
+    let report = import::import_ris(&conn, &path, &options).unwrap();
+    assert_eq!(report.skipped().count(), 1);
+
+    options.duplicates = DuplicateStrategy::Rename;
+    options.dry_run = true;
+    let report = import::import_ris(&conn, &path, &options).unwrap();
+    assert!(report.dry_run);
+    assert_eq!(report.created().next().unwrap().name, "Paper (2)");
+    assert_eq!(book_lib::get_books(&conn).unwrap().len(), 1);
+
+    options.dry_run = false;
+    import::import_ris(&conn, &path, &options).unwrap();
+    let books = book_lib::get_books(&conn).unwrap();
+    assert_eq!(names(&books), vec!["Paper", "Paper (2)"]);
+    let bk = books.iter().find(|bk| bk.name == "Paper (2)").unwrap();
+    assert_eq!(bk.metadata.authors, vec!["Doe, Jane"]);
+    assert_eq!(bk.metadata.year, Some(2020));
+}
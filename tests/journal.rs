@@ -0,0 +1,129 @@
+mod common;
+
+use book_lib::errors::{JournalError, UpdateFavouriteError};
+use book_lib::journal::{self, JournalOp};
+use book_lib::trash::{self, RestoreConflict};
+
+use common::{add_book, library, names, TempDir};
+
+#[test]
+fn changes_are_undone_and_redone_in_order() {
+    let conn = library();
+    let dir = TempDir::new("journal");
+    add_book(&conn, &dir, "paper");
+    let name = "paper".to_string();
+    book_lib::update_favourite(&conn, &name, true).unwrap();
+    book_lib::update_section(&conn, &name, Some("math".to_string())).unwrap();
+    book_lib::rename_book(&conn, &name, &"essay".to_string()).unwrap();
+
+    let undone = journal::undo(&conn).unwrap();
+    assert_eq!(undone.op, JournalOp::RenameBook);
+    let undone = journal::undo(&conn).unwrap();
+    assert_eq!(undone.op, JournalOp::UpdateSection);
+    let bk = book_lib::get_book(&conn, &name).unwrap();
+    assert_eq!(bk.section, None);
+    assert!(bk.favourite);
+
+    assert_eq!(journal::redo(&conn).unwrap().op, JournalOp::UpdateSection);
+    assert_eq!(journal::redo(&conn).unwrap().op, JournalOp::RenameBook);
+    let bk = book_lib::get_book(&conn, &"essay".to_string()).unwrap();
+    assert_eq!(bk.section.as_deref(), Some("math"));
+    assert!(matches!(
+        journal::redo(&conn),
+        Err(JournalError::NothingToRedo)
+    ));
+
+    for _ in 0..4 {
+        journal::undo(&conn).unwrap();
+    }
+    assert!(book_lib::get_books(&conn).unwrap().is_empty());
+    assert!(matches!(
+        journal::undo(&conn),
+        Err(JournalError::NothingToUndo)
+    ));
+}
+
+#[test]
+fn a_change_made_since_makes_the_replay_diverge() {
+    let conn = library();
+    let dir = TempDir::new("journal");
+    add_book(&conn, &dir, "paper");
+    let name = "paper".to_string();
+    book_lib::update_favourite(&conn, &name, true).unwrap();
+    // a change that isn't recorded, e.g. made by an older version of the library
+    let set_favourite = |favourite: bool| {
+        conn.execute(
+            "UPDATE books SET favourite = ? WHERE name = 'paper'",
+            [favourite],
+        )
+        .unwrap()
+    };
+
+    set_favourite(false);
+    assert!(matches!(journal::undo(&conn), Err(JournalError::Diverged)));
+    assert!(journal::next_undo(&conn).unwrap().is_some());
+
+    set_favourite(true);
+    journal::undo(&conn).unwrap();
+    set_favourite(true);
+    assert!(matches!(journal::redo(&conn), Err(JournalError::Diverged)));
+    assert!(journal::next_redo(&conn).unwrap().is_some());
+}
+
+#[test]
+fn restorations_from_the_trash_are_recorded() {
+    let conn = library();
+    let dir = TempDir::new("journal");
+    add_book(&conn, &dir, "paper");
+    book_lib::remove_book(&conn, &"paper".to_string()).unwrap();
+    let id = trash::list_trash(&conn).unwrap()[0].id;
+    add_book(&conn, &dir, "paper");
+    trash::restore_book(&conn, id, RestoreConflict::Rename).unwrap();
+
+    let undone = journal::undo(&conn).unwrap();
+    assert_eq!(undone.op, JournalOp::RestoreBook);
+    assert_eq!(names(&book_lib::get_books(&conn).unwrap()), vec!["paper"]);
+    let trashed = trash::list_trash(&conn).unwrap();
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(trashed[0].book.name, "paper");
+
+    journal::redo(&conn).unwrap();
+    assert_eq!(
+        names(&book_lib::get_books(&conn).unwrap()),
+        vec!["paper", "paper (2)"]
+    );
+}
+
+#[test]
+fn emptying_the_trash_forgets_the_changes_of_deleted_books() {
+    let conn = library();
+    let dir = TempDir::new("journal");
+    add_book(&conn, &dir, "paper");
+    book_lib::remove_book(&conn, &"paper".to_string()).unwrap();
+    assert_eq!(journal::get_journal(&conn, 10).unwrap().len(), 2);
+
+    trash::empty_trash(&conn, None).unwrap();
+
+    assert!(journal::get_journal(&conn, 10).unwrap().is_empty());
+    assert!(matches!(
+        journal::undo(&conn),
+        Err(JournalError::NothingToUndo)
+    ));
+}
+
+#[test]
+fn a_change_is_not_written_without_its_record() {
+    let conn = library();
+    let dir = TempDir::new("journal");
+    add_book(&conn, &dir, "paper");
+    conn.execute("DROP TABLE journal", []).unwrap();
+
+    assert!(matches!(
+        book_lib::update_favourite(&conn, &"paper".to_string(), true),
+        Err(UpdateFavouriteError::Other)
+    ));
+    assert!(book_lib::remove_book(&conn, &"paper".to_string()).is_err());
+
+    let bk = book_lib::get_book(&conn, &"paper".to_string()).unwrap();
+    assert!(!bk.favourite);
+}
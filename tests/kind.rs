@@ -0,0 +1,63 @@
+mod common;
+
+use book_lib::book::Book;
+use book_lib::errors::{CreateBookError, KindError};
+use book_lib::kind::{self, DocumentKind};
+
+use common::{library, TempDir};
+
+const EPUB: &[u8] = b"PK\x03\x04\x14\0\0\0\0\0mimetypeapplication/epub+zip";
+
+fn create(conn: &rusqlite::Connection, name: &str, path: String) -> Result<bool, CreateBookError> {
+    book_lib::create_book(conn, &Book::init(name.to_string(), path, None, false))
+}
+
+#[test]
+fn only_the_allowed_kinds_are_added() {
+    let conn = library();
+    let dir = TempDir::new("kind");
+    let epub = dir.file("novel.epub", EPUB);
+    let notes = dir.file("notes.md", b"# Notes\n");
+    assert_eq!(
+        kind::get_allowed_kinds(&conn).unwrap(),
+        vec![DocumentKind::Pdf]
+    );
+    assert!(matches!(
+        create(&conn, "novel", epub.clone()),
+        Err(CreateBookError::ProvidedPathIsNotPdf)
+    ));
+
+    kind::set_allowed_kinds(&conn, &[DocumentKind::Epub, DocumentKind::Pdf]).unwrap();
+    create(&conn, "novel", epub).unwrap();
+    assert!(matches!(
+        create(&conn, "notes", notes.clone()),
+        Err(CreateBookError::DocumentKindNotAllowed(Some(
+            DocumentKind::Markdown
+        )))
+    ));
+    let bk = book_lib::get_book(&conn, &"novel".to_string()).unwrap();
+    assert_eq!(bk.kind, DocumentKind::Epub);
+
+    kind::set_allowed_kinds(&conn, &[DocumentKind::Markdown]).unwrap();
+    create(&conn, "notes", notes).unwrap();
+    // the books added before are kept
+    assert_eq!(book_lib::get_books(&conn).unwrap().len(), 2);
+    assert!(matches!(
+        kind::set_allowed_kinds(&conn, &[]),
+        Err(KindError::NoKindAllowed)
+    ));
+}
+
+#[test]
+fn the_content_wins_over_the_extension() {
+    let dir = TempDir::new("kind");
+    let renamed = dir.file("novel.pdf", EPUB);
+    let unknown = dir.file("notes.txt", b"some notes");
+
+    assert_eq!(DocumentKind::detect(renamed), Some(DocumentKind::Epub));
+    assert_eq!(DocumentKind::detect(unknown), None);
+    assert_eq!(
+        DocumentKind::detect(dir.join("missing.djvu")),
+        Some(DocumentKind::Djvu)
+    );
+}
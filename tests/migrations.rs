@@ -0,0 +1,50 @@
+mod common;
+
+use book_lib::db;
+use rusqlite::Connection;
+
+use common::TempDir;
+
+fn user_version(conn: &Connection) -> i64 {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn a_library_of_the_first_version_is_migrated() {
+    let dir = TempDir::new("migrations");
+    let conn = Connection::open(dir.join("books.db")).unwrap();
+    // the schema of book-lib 0.1, with a book whose file doesn't exist
+    conn.execute_batch(
+        "CREATE TABLE books(
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            path TEXT NOT NULL,
+            section TEXT,
+            favourite INTEGER NOT NULL DEFAULT 0
+            );
+        INSERT INTO books (name, path, section, favourite)
+            VALUES ('old', '/nowhere/old.pdf', 'math', 1);",
+    )
+    .unwrap();
+    let before = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    db::configure(&conn).unwrap();
+    db::prepare(&conn).unwrap();
+
+    let version = user_version(&conn);
+    assert!(version > 0);
+    let bk = book_lib::get_book(&conn, &"old".to_string()).unwrap();
+    assert_eq!(bk.section.as_deref(), Some("math"));
+    assert!(bk.favourite);
+    assert!(bk.added_at.is_some_and(|at| at >= before));
+    assert_eq!(bk.last_opened_at, None);
+
+    // running the migrations again changes nothing
+    db::prepare(&conn).unwrap();
+    assert_eq!(user_version(&conn), version);
+    assert_eq!(book_lib::get_books(&conn).unwrap().len(), 1);
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use book_lib::db;
+use book_lib::notify::{ChangeEvent, ChangeWatcher};
+use rusqlite::Connection;
+
+use common::{add_book, library, TempDir};
+
+/// Returns a watcher listening to `conn` and the events given to its callback
+fn watch(conn: &Connection) -> (ChangeWatcher, Arc<Mutex<Vec<ChangeEvent>>>) {
+    let mut watcher = ChangeWatcher::new(conn).unwrap();
+    watcher.listen(conn);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let seen = events.clone();
+    watcher.on_change(move |event| seen.lock().unwrap().push(event.clone()));
+    (watcher, events)
+}
+
+#[test]
+fn changes_of_the_library_are_given_on_commit() {
+    let conn = library();
+    let dir = TempDir::new("notify");
+    let (mut watcher, events) = watch(&conn);
+
+    let bk = add_book(&conn, &dir, "paper");
+    book_lib::update_favourite(&conn, &bk.name, true).unwrap();
+    book_lib::remove_book(&conn, &bk.name).unwrap();
+
+    let events = std::mem::take(&mut *events.lock().unwrap());
+    assert_eq!(events.len(), 3);
+    let id = match &events[0] {
+        ChangeEvent::BookAdded { id, name } if name == "paper" => *id,
+        event => panic!("unexpected event {:?}", event),
+    };
+    assert_eq!(
+        events[1],
+        ChangeEvent::BookUpdated {
+            id,
+            name: "paper".to_string(),
+            fields: vec!["favourite".to_string()],
+        }
+    );
+    assert_eq!(
+        events[2],
+        ChangeEvent::BookRemoved {
+            id,
+            name: "paper".to_string(),
+        }
+    );
+    // nothing is given twice
+    assert!(!watcher.has_changes(&conn).unwrap());
+    assert!(watcher.poll(&conn).unwrap().is_empty());
+}
+
+#[test]
+fn a_rollback_gives_no_event() {
+    let conn = library();
+    let dir = TempDir::new("notify");
+    add_book(&conn, &dir, "paper");
+    let (mut watcher, events) = watch(&conn);
+
+    conn.execute_batch(
+        "BEGIN;
+        UPDATE books SET favourite = 1 WHERE name = 'paper';
+        ROLLBACK;",
+    )
+    .unwrap();
+    // a change of the library failing halfway is rolled back too
+    conn.execute("DROP TABLE journal", []).unwrap();
+    assert!(book_lib::update_favourite(&conn, &"paper".to_string(), true).is_err());
+
+    assert!(!watcher.has_changes(&conn).unwrap());
+    assert!(watcher.poll(&conn).unwrap().is_empty());
+    assert!(events.lock().unwrap().is_empty());
+}
+
+#[test]
+fn changes_of_other_connections_are_polled() {
+    let dir = TempDir::new("notify");
+    let open = || {
+        let conn = Connection::open(dir.join("books.db")).unwrap();
+        db::configure(&conn).unwrap();
+        db::prepare(&conn).unwrap();
+        conn
+    };
+    let conn = open();
+    let other = open();
+    let (mut watcher, events) = watch(&conn);
+    assert!(!watcher.has_changes(&conn).unwrap());
+
+    add_book(&other, &dir, "paper");
+
+    assert!(events.lock().unwrap().is_empty());
+    assert!(watcher.has_changes(&conn).unwrap());
+    let polled = watcher.poll(&conn).unwrap();
+    assert!(matches!(
+        polled.as_slice(),
+        [ChangeEvent::BookAdded { name, .. }] if name == "paper"
+    ));
+    assert_eq!(*events.lock().unwrap(), polled);
+    assert!(!watcher.has_changes(&conn).unwrap());
+}
+
+#[test]
+fn more_books_than_sqlite_parameters_are_read() {
+    let conn = library();
+    let (mut watcher, events) = watch(&conn);
+    let count = 33_000;
+
+    // a transaction of the caller: the changes are given by the next poll
+    conn.execute_batch("BEGIN").unwrap();
+    for i in 0..count {
+        conn.execute(
+            "INSERT INTO books (name, path) VALUES (?1, ?1)",
+            [format!("book {}", i)],
+        )
+        .unwrap();
+    }
+    conn.execute_batch("COMMIT").unwrap();
+    assert!(events.lock().unwrap().is_empty());
+    assert!(watcher.has_changes(&conn).unwrap());
+
+    let polled = watcher.poll(&conn).unwrap();
+    assert_eq!(polled.len(), count);
+    assert!(polled
+        .iter()
+        .all(|event| matches!(event, ChangeEvent::BookAdded { .. })));
+    assert_eq!(events.lock().unwrap().len(), count);
+}
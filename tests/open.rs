@@ -0,0 +1,106 @@
+mod common;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use book_lib::errors::OpenBookError;
+use book_lib::history;
+use book_lib::open::{self, CommandOpener, Opener, OpenerRegistry};
+
+use common::{add_book, library, TempDir};
+
+type Opened = Arc<Mutex<Vec<(PathBuf, Option<u32>)>>>;
+
+/// An opener that only remembers what it was asked to open
+struct FakeOpener {
+    name: &'static str,
+    opened: Opened,
+}
+
+impl Opener for FakeOpener {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn open(&self, path: &Path, page: Option<u32>) -> io::Result<()> {
+        self.opened.lock().unwrap().push((path.to_path_buf(), page));
+        Ok(())
+    }
+}
+
+fn registry(names: &[&'static str]) -> (OpenerRegistry, Opened) {
+    let opened = Opened::default();
+    let mut registry = OpenerRegistry::new();
+    for name in names {
+        registry.register(Box::new(FakeOpener {
+            name,
+            opened: opened.clone(),
+        }));
+    }
+    registry.set_default(names[0]).unwrap();
+    (registry, opened)
+}
+
+#[test]
+fn open_book_uses_the_preferred_opener_and_records_the_opening() {
+    let conn = library();
+    let dir = TempDir::new("open");
+    let bk = add_book(&conn, &dir, "paper");
+    let (registry, opened) = registry(&["fake", "other"]);
+
+    let used = open::open_book(&conn, &registry, &bk.name, Some(12)).unwrap();
+    assert_eq!(used, "fake");
+    open::set_book_opener(&conn, &bk.name, Some("other")).unwrap();
+    let used = open::open_book(&conn, &registry, &bk.name, None).unwrap();
+    assert_eq!(used, "other");
+
+    assert_eq!(
+        *opened.lock().unwrap(),
+        [(bk.file_path(), Some(12)), (bk.file_path(), None)]
+    );
+    assert_eq!(history::get_history(&conn, &bk.name).unwrap().len(), 2);
+}
+
+#[test]
+fn open_book_checks_the_file_and_the_opener() {
+    let conn = library();
+    let dir = TempDir::new("open");
+    let bk = add_book(&conn, &dir, "paper");
+    let (registry, opened) = registry(&["fake"]);
+
+    open::set_book_opener(&conn, &bk.name, Some("unknown")).unwrap();
+    assert!(matches!(
+        open::open_book(&conn, &registry, &bk.name, None),
+        Err(OpenBookError::UnknownOpener(name)) if name == "unknown"
+    ));
+    open::set_book_opener(&conn, &bk.name, None).unwrap();
+    std::fs::remove_file(bk.file_path()).unwrap();
+    assert!(matches!(
+        open::open_book(&conn, &registry, &bk.name, None),
+        Err(OpenBookError::PathIsIncorrect)
+    ));
+    assert!(opened.lock().unwrap().is_empty());
+    assert!(history::get_history(&conn, &bk.name).unwrap().is_empty());
+}
+
+#[test]
+fn windows_start_does_not_go_through_cmd() {
+    let start = CommandOpener::windows_start();
+    let command = start.command(Path::new(r"C:\books\a&calc.pdf"), None);
+    assert_eq!(command.get_program(), "rundll32");
+    let args: Vec<_> = command.get_args().collect();
+    assert_eq!(
+        args,
+        ["url.dll,FileProtocolHandler", r"C:\books\a&calc.pdf"]
+    );
+}
+
+#[test]
+fn cmd_templates_refuse_paths_it_would_interpret() {
+    let cmd = CommandOpener::from_template("cmd", r#"cmd /C start "" {path}"#).unwrap();
+    for path in ["a&calc.pdf", "a|b.pdf", "100%.pdf", "a^b.pdf", "hi!.pdf"] {
+        let err = cmd.open(Path::new(path), None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
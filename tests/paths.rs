@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+
+use book_lib::help::{path_from_string, path_to_string};
+
+fn round_trip(path: &Path) {
+    let stored = path_to_string(path);
+    assert_eq!(path_from_string(&stored), path, "stored as {:?}", stored);
+}
+
+#[test]
+fn valid_paths_are_stored_unchanged() {
+    for path in ["/books/paper.pdf", "/книги/論文.pdf", "C:\\books\\a b.pdf"] {
+        assert_eq!(path_to_string(path), path);
+        round_trip(Path::new(path));
+    }
+}
+
+#[test]
+fn literal_escape_characters_round_trip() {
+    for c in [
+        '\u{10FE00}',
+        '\u{10FE80}',
+        '\u{10FEE9}',
+        '\u{10FEFF}',
+        '\u{10E000}',
+        '\u{10E7FF}',
+    ] {
+        let path = PathBuf::from(format!("/books/{}{}x.pdf", c, c));
+        round_trip(&path);
+    }
+    // the character just outside the ranges needs no escape
+    assert_eq!(
+        path_to_string("/books/\u{10FF00}.pdf"),
+        "/books/\u{10FF00}.pdf"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn invalid_bytes_round_trip() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let cases: [&[u8]; 4] = [
+        b"/books/caf\xe9.pdf",
+        b"/books/\xe9\xe9\xff.pdf",
+        // a literal escape character next to the invalid byte it stands for
+        "/books/\u{10FEE9}".as_bytes(),
+        b"/books/\xf4\x8f\xbb\xa9\xe9\xf4\x8f\xb8\x80.pdf",
+    ];
+    for bytes in cases {
+        round_trip(Path::new(OsStr::from_bytes(bytes)));
+    }
+    // a literal escape character and the byte it stands for are stored differently
+    let literal = path_to_string("/\u{10FEE9}");
+    let byte = path_to_string(Path::new(OsStr::from_bytes(b"/\xe9")));
+    assert_ne!(literal, byte);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use book_lib::book::Book;
+use book_lib::errors::{CreateBookError, UpdatePathError};
+use book_lib::pdf::{self, PdfVerdict};
+
+use common::{add_book, library, TempDir, PDF};
+
+fn create(conn: &rusqlite::Connection, name: &str, path: String) -> Result<bool, CreateBookError> {
+    book_lib::create_book(conn, &Book::init(name.to_string(), path, None, false))
+}
+
+#[test]
+fn files_are_checked_by_their_content() {
+    let conn = library();
+    let dir = TempDir::new("pdf");
+
+    // a PDF without the extension is accepted, a text file with it isn't
+    create(&conn, "download", dir.file("download", PDF)).unwrap();
+    assert!(matches!(
+        create(&conn, "notes", dir.file("notes.pdf", b"some notes")),
+        Err(CreateBookError::InvalidPdf(PdfVerdict::WrongType(None)))
+    ));
+    assert!(matches!(
+        create(&conn, "cut", dir.file("cut.pdf", &PDF[..30])),
+        Err(CreateBookError::InvalidPdf(PdfVerdict::Corrupt(_)))
+    ));
+    assert_eq!(book_lib::get_books(&conn).unwrap().len(), 1);
+
+    let bk = add_book(&conn, &dir, "paper");
+    let encrypted = dir.file(
+        "encrypted.pdf",
+        b"%PDF-1.7\ntrailer\n<< /Root 1 0 R /Encrypt 2 0 R >>\nstartxref\n9\n%%EOF\n",
+    );
+    assert!(matches!(
+        book_lib::update_path(&conn, &bk.name, &encrypted),
+        Err(UpdatePathError::InvalidPdf(PdfVerdict::PasswordProtected))
+    ));
+}
+
+#[test]
+fn only_the_ends_of_a_large_file_are_read() {
+    let dir = TempDir::new("pdf");
+    // the header after some junk and the end marker after a large body
+    let mut content = b"junk\n".to_vec();
+    content.extend_from_slice(&PDF[..PDF.len() - 30]);
+    content.resize(content.len() + (1 << 20), b' ');
+    content.extend_from_slice(&PDF[PDF.len() - 30..]);
+    let path = dir.file("large.pdf", &content);
+
+    assert_eq!(pdf::check_pdf(&path).unwrap(), PdfVerdict::Valid);
+    std::fs::write(&path, &content[..content.len() - 10]).unwrap();
+    assert!(matches!(
+        pdf::check_pdf(&path).unwrap(),
+        PdfVerdict::Corrupt(_)
+    ));
+}
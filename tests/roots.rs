@@ -0,0 +1,57 @@
+mod common;
+
+use std::path::Path;
+
+use book_lib::roots;
+use rusqlite::Connection;
+
+use common::{add_book, library, TempDir, PDF};
+
+fn stored_path(conn: &Connection, name: &str) -> String {
+    conn.query_row("SELECT path FROM books WHERE name = ?", [name], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+// the roots are shared by the whole process, a single test changes them
+#[test]
+fn paths_inside_a_root_are_portable() {
+    let conn = library();
+    let dir = TempDir::new("roots");
+    let name = format!("papers-{}", std::process::id());
+    let prefix = format!("@{}/", name);
+    let before = add_book(&conn, &dir, "before");
+
+    roots::add_root(&name, &dir.path).unwrap();
+    assert_eq!(stored_path(&conn, "before"), before.path);
+    assert_eq!(roots::relativize_paths(&conn).unwrap(), vec!["before"]);
+    assert_eq!(
+        stored_path(&conn, "before"),
+        format!("{}before.pdf", prefix)
+    );
+    assert!(roots::relativize_paths(&conn).unwrap().is_empty());
+    add_book(&conn, &dir, "after");
+    assert_eq!(stored_path(&conn, "after"), format!("{}after.pdf", prefix));
+    // the books are returned with their absolute path
+    assert_eq!(
+        book_lib::get_book(&conn, &"before".to_string())
+            .unwrap()
+            .path,
+        before.path
+    );
+
+    // the same library on another machine, where the root is somewhere else
+    let other = TempDir::new("roots");
+    for file in ["before.pdf", "after.pdf"] {
+        std::fs::write(other.join(file), PDF).unwrap();
+    }
+    roots::add_root(&name, &other.path).unwrap();
+    let bk = book_lib::get_book(&conn, &"after".to_string()).unwrap();
+    assert_eq!(Path::new(&bk.path), other.join("after.pdf"));
+
+    // and where it isn't known
+    roots::remove_root(&name).unwrap();
+    let bk = book_lib::get_book(&conn, &"after".to_string()).unwrap();
+    assert_eq!(bk.path, format!("{}after.pdf", prefix));
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use book_lib::scan::{self, ScanOptions, ScanOutcome};
+
+use common::{library, names, TempDir};
+
+#[test]
+fn scan_registers_new_documents_once() {
+    let conn = library();
+    let dir = TempDir::new("scan");
+    dir.pdf("math/algebra.pdf");
+    dir.pdf("physics.pdf");
+    dir.file("notes.txt", b"just some notes");
+    let options = ScanOptions {
+        sections_from_dirs: true,
+        dry_run: true,
+        ..Default::default()
+    };
+
+    let dry = scan::scan_directory(&conn, &dir.path, &options).unwrap();
+    assert!(dry.dry_run);
+    assert_eq!(dry.created().count(), 2);
+    assert_eq!(dry.ignored, 1);
+    assert!(book_lib::get_books(&conn).unwrap().is_empty());
+
+    let options = ScanOptions {
+        dry_run: false,
+        ..options
+    };
+    let report = scan::scan_directory(&conn, &dir.path, &options).unwrap();
+    assert!(!report.dry_run);
+    assert_eq!(report.created().count(), 2);
+    let books = book_lib::get_books(&conn).unwrap();
+    assert_eq!(names(&books), vec!["algebra", "physics"]);
+    let algebra = books.iter().find(|bk| bk.name == "algebra").unwrap();
+    assert_eq!(algebra.section.as_deref(), Some("math"));
+
+    let again = scan::scan_directory(&conn, &dir.path, &options).unwrap();
+    assert_eq!(again.created().count(), 0);
+    assert!(again
+        .files
+        .iter()
+        .all(|file| matches!(file.outcome, ScanOutcome::AlreadyRegistered(_))));
+}
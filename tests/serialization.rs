@@ -0,0 +1,53 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use book_lib::book::{self, Book, SectionGroup};
+use book_lib::export;
+use serde_json::json;
+
+use common::{add_book, library, TempDir};
+
+#[test]
+fn books_serialize_like_the_export() {
+    let conn = library();
+    let dir = TempDir::new("serde");
+    add_book(&conn, &dir, "paper");
+    let mut bk = book_lib::update_favourite(&conn, &"paper".to_string(), true).unwrap();
+    bk.metadata.authors = vec!["Jane Doe".to_string()];
+    bk.metadata.year = Some(2020);
+
+    let value = serde_json::to_value(&bk).unwrap();
+    assert_eq!(value, export::book_to_json(&bk));
+    let read: Book = serde_json::from_value(value).unwrap();
+    assert_eq!(read.name, bk.name);
+    assert_eq!(read.path, bk.path);
+    assert!(read.favourite);
+    assert_eq!(read.metadata, bk.metadata);
+    assert_eq!(read.kind, bk.kind);
+    assert_eq!(read.added_at, bk.added_at);
+
+    let groups: Vec<SectionGroup> = book::sort_books_by_section(vec![bk])
+        .into_iter()
+        .map(SectionGroup::from)
+        .collect();
+    let value = serde_json::to_value(&groups).unwrap();
+    assert_eq!(value[0]["section"], "");
+    assert_eq!(value[0]["books"][0]["name"], "paper");
+}
+
+#[test]
+fn missing_fields_are_read_as_empty_values() {
+    let bk: Book = serde_json::from_value(json!({
+        "name": "paper",
+        "path": "/books/paper.pdf",
+        "section": null,
+        "favourite": false,
+    }))
+    .unwrap();
+
+    assert_eq!(bk.metadata, Default::default());
+    assert!(!bk.missing);
+    assert_eq!(bk.added_at, None);
+    assert_eq!(bk.last_opened_at, None);
+}
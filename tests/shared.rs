@@ -0,0 +1,80 @@
+mod common;
+
+use std::thread;
+
+use book_lib::book::Book;
+use book_lib::db;
+use book_lib::shared::SharedConnection;
+use rusqlite::Connection;
+
+use common::{names, TempDir};
+
+const THREADS: usize = 4;
+const BOOKS: usize = 10;
+
+fn open(dir: &TempDir) -> Connection {
+    let conn = Connection::open(dir.join("books.db")).unwrap();
+    db::configure(&conn).unwrap();
+    conn
+}
+
+#[test]
+fn connections_of_several_threads_write_at_the_same_time() {
+    let dir = TempDir::new("shared");
+    db::prepare(&open(&dir)).unwrap();
+
+    thread::scope(|scope| {
+        for i in 0..THREADS {
+            let dir = &dir;
+            scope.spawn(move || {
+                // a connection per thread, like a connection per process
+                let conn = open(dir);
+                for j in 0..BOOKS {
+                    let name = format!("book {} {}", i, j);
+                    let path = dir.pdf(&format!("{}.pdf", name));
+                    book_lib::create_book(&conn, &Book::init(name.clone(), path, None, false))
+                        .unwrap();
+                    book_lib::update_favourite(&conn, &name, true).unwrap();
+                }
+            });
+        }
+    });
+
+    let books = book_lib::get_books(&open(&dir)).unwrap();
+    assert_eq!(books.len(), THREADS * BOOKS);
+    assert!(books.iter().all(|bk| bk.favourite));
+}
+
+#[test]
+fn a_shared_connection_is_used_by_several_threads() {
+    let dir = TempDir::new("shared");
+    let conn = open(&dir);
+    db::prepare(&conn).unwrap();
+    let shared = SharedConnection::new(conn);
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|i| {
+            let shared = shared.clone();
+            let path = dir.pdf(&format!("{}.pdf", i));
+            thread::spawn(move || {
+                let bk = Book::init(i.to_string(), path, None, false);
+                shared
+                    .with(|conn| book_lib::create_book(conn, &bk))
+                    .unwrap();
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // a thread panicking with the lock doesn't make the connection unusable
+    let poisoner = shared.clone();
+    let _ = thread::spawn(move || {
+        let _conn = poisoner.lock();
+        panic!("the connection is poisoned");
+    })
+    .join();
+    let books = shared.with(book_lib::get_books).unwrap();
+    assert_eq!(names(&books), vec!["0", "1", "2", "3"]);
+}
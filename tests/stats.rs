@@ -0,0 +1,70 @@
+mod common;
+
+use book_lib::stats::{self, Goal, GoalMetric, GoalPeriod};
+use book_lib::{history, progress};
+
+use common::{add_book, library, TempDir};
+
+/// Noon of 2024-03-06 (a Wednesday) in UTC, far enough from midnight for any local time zone
+const AT: i64 = 1_709_726_400;
+const DAY: i64 = 24 * 60 * 60;
+
+#[test]
+fn reading_again_does_not_count_new_pages() {
+    let conn = library();
+    let dir = TempDir::new("stats");
+    let bk = add_book(&conn, &dir, "paper");
+    progress::set_page_count(&conn, &bk.name, Some(100)).unwrap();
+
+    progress::update_progress(&conn, &bk.name, 40, Some(AT)).unwrap();
+    progress::update_progress(&conn, &bk.name, 10, Some(AT)).unwrap();
+    progress::update_progress(&conn, &bk.name, 50, Some(AT)).unwrap();
+    let done = progress::update_progress(&conn, &bk.name, 100, Some(AT)).unwrap();
+
+    assert_eq!(done.finished_at, Some(AT));
+    let weeks = stats::pages_read_per_week(&conn).unwrap();
+    assert_eq!(weeks.len(), 1);
+    assert_eq!(weeks[0].total, 100);
+
+    progress::reset_progress(&conn, &bk.name).unwrap();
+    progress::update_progress(&conn, &bk.name, 20, Some(AT)).unwrap();
+    assert_eq!(stats::pages_read_per_week(&conn).unwrap()[0].total, 20);
+}
+
+#[test]
+fn books_in_the_trash_are_left_out() {
+    let conn = library();
+    let dir = TempDir::new("stats");
+    let kept = add_book(&conn, &dir, "kept");
+    let trashed = add_book(&conn, &dir, "trashed");
+    for (bk, pages) in [(&kept, 10), (&trashed, 30)] {
+        progress::update_progress(&conn, &bk.name, pages, Some(AT)).unwrap();
+        history::record_session(&conn, &bk.name, AT - DAY, 600).unwrap();
+    }
+    history::record_session(&conn, &trashed.name, AT - 2 * DAY, 600).unwrap();
+    for (name, metric) in [
+        ("pages", GoalMetric::PagesRead),
+        ("minutes", GoalMetric::ReadingMinutes),
+    ] {
+        stats::add_goal(
+            &conn,
+            &Goal::init(name.to_string(), metric, GoalPeriod::Year, 1),
+        )
+        .unwrap();
+    }
+    let before = stats::reading_streaks(&conn, Some(AT)).unwrap();
+    assert_eq!(before.longest, 3);
+
+    book_lib::remove_book(&conn, &trashed.name).unwrap();
+
+    assert_eq!(stats::pages_read_per_week(&conn).unwrap()[0].total, 10);
+    let goals = stats::goal_progress(&conn, Some(AT)).unwrap();
+    assert_eq!(goals[0].current, 10);
+    assert_eq!(goals[1].current, 10);
+    let days = history::reading_time_per_day(&conn, AT - 7 * DAY, AT + DAY).unwrap();
+    assert_eq!(days.len(), 1);
+    assert_eq!(days[0].seconds, 600);
+    let streaks = stats::reading_streaks(&conn, Some(AT)).unwrap();
+    assert_eq!(streaks.current, 2);
+    assert_eq!(streaks.longest, 2);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::fs;
+
+use book_lib::batch;
+use book_lib::book::Book;
+use book_lib::errors::BatchError;
+use book_lib::storage::{self, ManagedStorage, StorageMode, DEFAULT_TEMPLATE};
+use rusqlite::Connection;
+
+use common::{exists, library, TempDir, PDF};
+
+fn managed(conn: &Connection, dir: &TempDir, mode: StorageMode) -> String {
+    let root = dir.join("library").to_str().unwrap().to_string();
+    let managed = ManagedStorage::init(root, DEFAULT_TEMPLATE.to_string(), mode);
+    storage::set_managed_storage(conn, &managed).unwrap().root
+}
+
+#[test]
+fn create_book_moves_the_file_into_the_root() {
+    let conn = library();
+    let dir = TempDir::new("storage");
+    let root = managed(&conn, &dir, StorageMode::Move);
+    let path = dir.pdf("inbox/paper.pdf");
+
+    book_lib::create_book(
+        &conn,
+        &Book::init("paper".to_string(), path.clone(), None, false),
+    )
+    .unwrap();
+
+    let bk = book_lib::get_book(&conn, &"paper".to_string()).unwrap();
+    assert_eq!(bk.path, format!("{}/Unsorted/Unknown - paper.pdf", root));
+    assert!(exists(&bk.path));
+    assert!(!exists(&path));
+}
+
+#[test]
+fn existing_files_are_never_overwritten() {
+    let conn = library();
+    let dir = TempDir::new("storage");
+    let root = managed(&conn, &dir, StorageMode::Copy);
+    let taken = format!("{}/Unsorted/Unknown - paper.pdf", root);
+    fs::create_dir_all(format!("{}/Unsorted", root)).unwrap();
+    fs::write(&taken, b"another file").unwrap();
+    let path = dir.pdf("inbox/paper.pdf");
+
+    book_lib::create_book(
+        &conn,
+        &Book::init("paper".to_string(), path.clone(), None, false),
+    )
+    .unwrap();
+
+    let bk = book_lib::get_book(&conn, &"paper".to_string()).unwrap();
+    assert_eq!(
+        bk.path,
+        format!("{}/Unsorted/Unknown - paper (2).pdf", root)
+    );
+    assert_eq!(fs::read(&taken).unwrap(), b"another file");
+    assert_eq!(fs::read(&bk.path).unwrap(), PDF);
+    assert!(exists(&path));
+}
+
+#[test]
+fn rolled_back_changes_put_the_files_back() {
+    let conn = library();
+    let dir = TempDir::new("storage");
+    let root = managed(&conn, &dir, StorageMode::Move);
+    let kept = dir.pdf("inbox/kept.pdf");
+    book_lib::create_book(&conn, &Book::init("kept".to_string(), kept, None, false)).unwrap();
+    let kept_path = book_lib::get_book(&conn, &"kept".to_string()).unwrap().path;
+    let path = dir.pdf("inbox/paper.pdf");
+
+    let res = batch::run_batch(&conn, |batch| {
+        batch.create_book(&Book::init("paper".to_string(), path.clone(), None, false))?;
+        batch.update_section(&"kept".to_string(), Some("math".to_string()))?;
+        batch.remove_book(&"nothing".to_string())?;
+        Ok(())
+    });
+
+    assert!(matches!(res, Err(BatchError::RolledBack(_))));
+    assert!(exists(&path));
+    assert!(!exists(&format!("{}/Unsorted/Unknown - paper.pdf", root)));
+    assert!(exists(&kept_path));
+    assert!(!exists(&format!("{}/math/Unknown - kept.pdf", root)));
+    assert!(book_lib::get_book(&conn, &"paper".to_string()).is_err());
+}
+
+#[test]
+fn renaming_a_book_moves_its_file() {
+    let conn = library();
+    let dir = TempDir::new("storage");
+    let root = managed(&conn, &dir, StorageMode::Move);
+    let path = dir.pdf("inbox/paper.pdf");
+    book_lib::create_book(&conn, &Book::init("paper".to_string(), path, None, false)).unwrap();
+
+    let bk = book_lib::rename_book(&conn, &"paper".to_string(), &"essay".to_string()).unwrap();
+
+    assert_eq!(bk.path, format!("{}/Unsorted/Unknown - essay.pdf", root));
+    assert!(exists(&bk.path));
+    assert!(!exists(&format!("{}/Unsorted/Unknown - paper.pdf", root)));
+}
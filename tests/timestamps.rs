@@ -0,0 +1,53 @@
+mod common;
+
+use book_lib::book::{self, Book, SortKey};
+use book_lib::history;
+
+use common::{add_book, library, TempDir};
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// Names of the books in their order, `common::names` sorts them
+fn names_in_order(books: &[Book]) -> Vec<&str> {
+    books.iter().map(|bk| bk.name.as_str()).collect()
+}
+
+#[test]
+fn timestamps_are_kept_by_every_change() {
+    let conn = library();
+    let dir = TempDir::new("timestamps");
+    let before = now();
+    add_book(&conn, &dir, "old");
+    add_book(&conn, &dir, "new");
+    conn.execute(
+        "UPDATE books SET added_at = 100, updated_at = 100 WHERE name = 'old'",
+        [],
+    )
+    .unwrap();
+
+    let old = book_lib::get_book(&conn, &"old".to_string()).unwrap();
+    assert_eq!((old.added_at, old.updated_at), (Some(100), Some(100)));
+    let new = book_lib::get_book(&conn, &"new".to_string()).unwrap();
+    assert!(new.added_at.is_some_and(|at| at >= before));
+    assert_eq!(new.updated_at, new.added_at);
+    assert_eq!(new.last_opened_at, None);
+
+    book_lib::update_section(&conn, &"old".to_string(), Some("math".to_string())).unwrap();
+    history::record_open(&conn, &"old".to_string(), Some(200)).unwrap();
+
+    let old = book_lib::get_book(&conn, &"old".to_string()).unwrap();
+    assert_eq!(old.added_at, Some(100));
+    assert!(old.updated_at.is_some_and(|at| at >= before));
+    assert_eq!(old.last_opened_at, Some(200));
+
+    let books = book_lib::get_books(&conn).unwrap();
+    let recent = book::sort_books(books.clone(), SortKey::AddedAt, true);
+    assert_eq!(names_in_order(&recent), vec!["new", "old"]);
+    let opened = book::sort_books(books, SortKey::LastOpenedAt, true);
+    assert_eq!(names_in_order(&opened), vec!["old", "new"]);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use book_lib::book::Book;
+use book_lib::errors::TrashError;
+use book_lib::trash::{self, RestoreConflict};
+use book_lib::{history, progress};
+use rusqlite::Connection;
+
+use common::{add_book, library, names, TempDir};
+
+/// Removes the book `name` and returns its id in the trash
+fn trash(conn: &Connection, name: &str) -> i64 {
+    book_lib::remove_book(conn, &name.to_string()).unwrap();
+    trash::list_trash(conn)
+        .unwrap()
+        .into_iter()
+        .find(|trashed| trashed.book.name == name)
+        .unwrap()
+        .id
+}
+
+fn count(conn: &Connection, table: &str) -> i64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+#[test]
+fn restore_handles_name_conflicts() {
+    let conn = library();
+    let dir = TempDir::new("trash");
+    add_book(&conn, &dir, "paper");
+    let id = trash(&conn, "paper");
+    let other = add_book(&conn, &dir, "other");
+    book_lib::rename_book(&conn, &other.name, &"paper".to_string()).unwrap();
+
+    assert!(matches!(
+        trash::restore_book(&conn, id, RestoreConflict::Fail),
+        Err(TrashError::NameConflict(name)) if name == "paper"
+    ));
+    assert_eq!(trash::list_trash(&conn).unwrap().len(), 1);
+
+    let restored = trash::restore_book(&conn, id, RestoreConflict::Rename).unwrap();
+    assert_eq!(restored.name, "paper (2)");
+    assert_eq!(
+        names(&book_lib::get_books(&conn).unwrap()),
+        vec!["paper", "paper (2)"]
+    );
+    assert!(trash::list_trash(&conn).unwrap().is_empty());
+    assert!(matches!(
+        trash::restore_book(&conn, id, RestoreConflict::Rename),
+        Err(TrashError::BookNotInTrash)
+    ));
+}
+
+#[test]
+fn restore_can_replace_the_book_with_the_same_name() {
+    let conn = library();
+    let dir = TempDir::new("trash");
+    let old = add_book(&conn, &dir, "paper");
+    let id = trash(&conn, "paper");
+    let new = Book::init(
+        "paper".to_string(),
+        dir.pdf("new/paper.pdf"),
+        Some("math".to_string()),
+        false,
+    );
+    book_lib::create_book(&conn, &new).unwrap();
+
+    let restored = trash::restore_book(&conn, id, RestoreConflict::Replace).unwrap();
+
+    assert_eq!(restored.path, old.path);
+    assert_eq!(restored.section, None);
+    let trashed = trash::list_trash(&conn).unwrap();
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(trashed[0].book.section.as_deref(), Some("math"));
+}
+
+#[test]
+fn emptying_the_trash_purges_the_history_and_progress() {
+    let conn = library();
+    let dir = TempDir::new("trash");
+    for name in ["kept", "old", "recent"] {
+        add_book(&conn, &dir, name);
+        history::record_session(&conn, &name.to_string(), 1_000, 60).unwrap();
+        progress::update_progress(&conn, &name.to_string(), 5, Some(1_000)).unwrap();
+    }
+    trash(&conn, "old");
+    conn.execute("UPDATE books SET deleted_at = 10 WHERE name = 'old'", [])
+        .unwrap();
+    trash(&conn, "recent");
+
+    assert_eq!(trash::empty_trash(&conn, Some(100)).unwrap(), 1);
+    assert_eq!(count(&conn, "reading_history"), 2);
+    assert_eq!(count(&conn, "page_events"), 2);
+    assert_eq!(trash::list_trash(&conn).unwrap()[0].book.name, "recent");
+
+    assert_eq!(trash::empty_trash(&conn, None).unwrap(), 1);
+    assert!(trash::list_trash(&conn).unwrap().is_empty());
+    assert_eq!(count(&conn, "reading_history"), 1);
+    assert_eq!(count(&conn, "page_events"), 1);
+    assert_eq!(names(&book_lib::get_books(&conn).unwrap()), vec!["kept"]);
+}
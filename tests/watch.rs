@@ -0,0 +1,126 @@
+mod common;
+
+use std::fs;
+
+use book_lib::journal::{self, JournalOp};
+use book_lib::watch::{self, WatchedFolder};
+
+use common::{library, names, TempDir, PDF};
+
+#[test]
+fn reconcile_adds_moves_and_flags_books() {
+    let conn = library();
+    let dir = TempDir::new("watch");
+    dir.pdf("papers/first.pdf");
+    dir.pdf("papers/second.pdf");
+    let folder = WatchedFolder::init(
+        dir.join("papers").to_str().unwrap().to_string(),
+        None,
+        false,
+    );
+    watch::add_watched_folder(&conn, &folder).unwrap();
+
+    let report = watch::reconcile(&conn).unwrap();
+    assert_eq!(report.added.len(), 2);
+    assert_eq!(
+        names(&book_lib::get_books(&conn).unwrap()),
+        vec!["first", "second"]
+    );
+
+    // first is moved to a subdirectory, second is deleted and a new file appears
+    fs::create_dir_all(dir.join("papers/sub")).unwrap();
+    fs::rename(
+        dir.join("papers/first.pdf"),
+        dir.join("papers/sub/first.pdf"),
+    )
+    .unwrap();
+    fs::remove_file(dir.join("papers/second.pdf")).unwrap();
+    dir.pdf("papers/third.pdf");
+
+    let report = watch::reconcile(&conn).unwrap();
+    assert_eq!(report.moved.len(), 1);
+    assert_eq!(report.moved[0].name, "first");
+    assert_eq!(report.missing, vec!["second"]);
+    assert_eq!(report.added.len(), 1);
+    assert_eq!(report.added[0].name, "third");
+    let first = book_lib::get_book(&conn, &"first".to_string()).unwrap();
+    assert_eq!(first.file_path(), dir.join("papers/sub/first.pdf"));
+    assert_eq!(book_lib::get_books(&conn).unwrap().len(), 3);
+
+    // second is back
+    fs::write(dir.join("papers/second.pdf"), PDF).unwrap();
+    let report = watch::reconcile(&conn).unwrap();
+    assert_eq!(report.restored, vec!["second"]);
+    assert!(report.added.is_empty());
+    assert!(report.moved.is_empty());
+}
+
+#[test]
+fn a_move_is_recorded_in_the_journal() {
+    let conn = library();
+    let dir = TempDir::new("watch");
+    dir.pdf("papers/first.pdf");
+    let folder = WatchedFolder::init(
+        dir.join("papers").to_str().unwrap().to_string(),
+        None,
+        false,
+    );
+    watch::add_watched_folder(&conn, &folder).unwrap();
+    watch::reconcile(&conn).unwrap();
+
+    fs::create_dir_all(dir.join("papers/sub")).unwrap();
+    fs::rename(
+        dir.join("papers/first.pdf"),
+        dir.join("papers/sub/first.pdf"),
+    )
+    .unwrap();
+    assert_eq!(watch::reconcile(&conn).unwrap().moved.len(), 1);
+
+    let entry = journal::undo(&conn).unwrap();
+    assert_eq!(entry.op, JournalOp::UpdatePath);
+    let first = book_lib::get_book(&conn, &"first".to_string()).unwrap();
+    assert_eq!(first.file_path(), dir.join("papers/first.pdf"));
+}
+
+#[test]
+fn a_book_is_not_moved_onto_a_file_that_fails_the_checks() {
+    let conn = library();
+    let dir = TempDir::new("watch");
+    dir.pdf("papers/first.pdf");
+    let folder = WatchedFolder::init(
+        dir.join("papers").to_str().unwrap().to_string(),
+        None,
+        false,
+    );
+    watch::add_watched_folder(&conn, &folder).unwrap();
+    watch::reconcile(&conn).unwrap();
+
+    fs::remove_file(dir.join("papers/first.pdf")).unwrap();
+    dir.file("papers/sub/first.pdf", &PDF[..30]);
+
+    let report = watch::reconcile(&conn).unwrap();
+    assert!(report.moved.is_empty());
+    assert_eq!(report.missing, vec!["first"]);
+    let first = book_lib::get_book(&conn, &"first".to_string()).unwrap();
+    assert_eq!(first.file_path(), dir.join("papers/first.pdf"));
+}
+
+#[test]
+fn new_files_of_several_folders_get_distinct_names() {
+    let conn = library();
+    let dir = TempDir::new("watch");
+    dir.pdf("a/notes.pdf");
+    dir.pdf("b/notes.pdf");
+    for sub in ["a", "b"] {
+        let folder = WatchedFolder::init(dir.join(sub).to_str().unwrap().to_string(), None, false);
+        watch::add_watched_folder(&conn, &folder).unwrap();
+    }
+
+    let report = watch::reconcile(&conn).unwrap();
+
+    assert_eq!(report.added.len(), 2);
+    assert_eq!(
+        names(&book_lib::get_books(&conn).unwrap()),
+        vec!["notes", "notes (2)"]
+    );
+}
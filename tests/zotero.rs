@@ -0,0 +1,129 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use book_lib::import::zotero::{self, ZOTERO_DB};
+use book_lib::import::{ImportOptions, ImportOutcome};
+use book_lib::trash;
+use rusqlite::{params, Connection};
+
+use common::{library, TempDir, PDF};
+
+/// Writes a Zotero data directory with a stored PDF attachment for each `(key, title)`
+fn zotero_library(data_dir: &Path, items: &[(&str, &str)]) {
+    std::fs::create_dir_all(data_dir).unwrap();
+    let zotero = Connection::open(data_dir.join(ZOTERO_DB)).unwrap();
+    zotero
+        .execute_batch(
+            "CREATE TABLE items (itemID INTEGER PRIMARY KEY, key TEXT);
+            CREATE TABLE itemAttachments (itemID INTEGER, parentItemID INTEGER, path TEXT,
+                contentType TEXT);
+            CREATE TABLE deletedItems (itemID INTEGER);
+            CREATE TABLE collections (collectionID INTEGER, collectionName TEXT,
+                parentCollectionID INTEGER);
+            CREATE TABLE collectionItems (collectionID INTEGER, itemID INTEGER,
+                orderIndex INTEGER);
+            CREATE TABLE fields (fieldID INTEGER, fieldName TEXT);
+            CREATE TABLE itemData (itemID INTEGER, fieldID INTEGER, valueID INTEGER);
+            CREATE TABLE itemDataValues (valueID INTEGER, value TEXT);
+            CREATE TABLE itemCreators (itemID INTEGER, creatorID INTEGER, orderIndex INTEGER);
+            CREATE TABLE creators (creatorID INTEGER, firstName TEXT, lastName TEXT);
+            CREATE TABLE itemTags (itemID INTEGER, tagID INTEGER);
+            CREATE TABLE tags (tagID INTEGER, name TEXT);
+            INSERT INTO fields VALUES (1, 'title');",
+        )
+        .unwrap();
+    for (i, (key, title)) in items.iter().enumerate() {
+        let id = i as i64 + 1;
+        zotero
+            .execute(
+                "INSERT INTO items VALUES (?1, ?2)",
+                params![id, key.to_string()],
+            )
+            .unwrap();
+        zotero
+            .execute(
+                "INSERT INTO itemAttachments VALUES (?1, NULL, ?2, 'application/pdf')",
+                params![id, format!("storage:{}.pdf", key)],
+            )
+            .unwrap();
+        zotero
+            .execute(
+                "INSERT INTO itemDataValues VALUES (?1, ?2)",
+                params![id, title.to_string()],
+            )
+            .unwrap();
+        zotero
+            .execute("INSERT INTO itemData VALUES (?1, 1, ?1)", params![id])
+            .unwrap();
+        let storage = data_dir.join("storage").join(key);
+        std::fs::create_dir_all(&storage).unwrap();
+        std::fs::write(storage.join(format!("{}.pdf", key)), PDF).unwrap();
+    }
+}
+
+fn data_dir(dir: &TempDir) -> PathBuf {
+    let name = "zotero #1 %20?";
+    #[cfg(unix)]
+    let name = {
+        use std::os::unix::ffi::OsStrExt;
+        // a directory name that isn't valid Unicode
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.push(0xff);
+        std::ffi::OsStr::from_bytes(&bytes).to_os_string()
+    };
+    dir.path.join(name)
+}
+
+#[test]
+fn import_reads_any_data_directory() {
+    let conn = library();
+    let dir = TempDir::new("zotero");
+    let data_dir = data_dir(&dir);
+    zotero_library(&data_dir, &[("AAAA", "First"), ("BBBB", "Second")]);
+
+    let report = zotero::import_zotero(&conn, &data_dir, &ImportOptions::default()).unwrap();
+
+    assert_eq!(report.created().count(), 2);
+    let first = book_lib::get_book(&conn, &"First".to_string()).unwrap();
+    assert_eq!(
+        first.file_path(),
+        data_dir.join("storage").join("AAAA").join("AAAA.pdf")
+    );
+}
+
+#[test]
+fn import_is_incremental() {
+    let conn = library();
+    let dir = TempDir::new("zotero");
+    let data_dir = dir.join("zotero");
+    zotero_library(&data_dir, &[("AAAA", "First")]);
+    zotero::import_zotero(&conn, &data_dir, &ImportOptions::default()).unwrap();
+
+    let report = zotero::import_zotero(&conn, &data_dir, &ImportOptions::default()).unwrap();
+
+    assert_eq!(report.entries.len(), 1);
+    assert!(matches!(
+        &report.entries[0].outcome,
+        ImportOutcome::AlreadyImported(name) if name == "First"
+    ));
+}
+
+#[test]
+fn entries_of_trashed_books_are_not_imported_again() {
+    let conn = library();
+    let dir = TempDir::new("zotero");
+    let data_dir = dir.join("zotero");
+    zotero_library(&data_dir, &[("AAAA", "First")]);
+    zotero::import_zotero(&conn, &data_dir, &ImportOptions::default()).unwrap();
+    book_lib::remove_book(&conn, &"First".to_string()).unwrap();
+
+    let report = zotero::import_zotero(&conn, &data_dir, &ImportOptions::default()).unwrap();
+    assert_eq!(report.in_trash().count(), 1);
+    assert!(book_lib::get_books(&conn).unwrap().is_empty());
+
+    // once the trash is emptied the entry is new again
+    trash::empty_trash(&conn, None).unwrap();
+    let report = zotero::import_zotero(&conn, &data_dir, &ImportOptions::default()).unwrap();
+    assert_eq!(report.created().count(), 1);
+}